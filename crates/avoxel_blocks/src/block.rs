@@ -1,3 +1,4 @@
+use crate::{block_shape::BlockShape, fluid::Fluid};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Serialize, Deserialize, Default)]
@@ -5,11 +6,41 @@ pub struct Block {
     /// The order of texture ids is top, right, bottom, left, front, back.
     /// The texture ids are used to calculate uv coordinates for blocks.
     pub texture_ids: [u32; 6],
+    /// Identifies the block in saved chunks, a namespaced name like `core:grass`.
+    /// Names without a namespace are in the `core` namespace.
     pub name: String,
     /// Does the block contribute to ao (ambient occlusion)
     pub ao: bool,
     /// Is the block transparent
     pub transparent: bool,
+    /// Colour used when drawing the block on top-down maps.
+    /// If not set the colour is averaged from the top texture.
+    #[serde(default)]
+    pub map_color: Option<[u8; 3]>,
+    /// Set for liquids like water and lava
+    #[serde(default)]
+    pub fluid: Option<Fluid>,
+    /// The state bits of the voxel the block uses
+    #[serde(default)]
+    pub properties: Vec<BlockProperty>,
+    #[serde(default)]
+    pub shape: BlockShape,
+}
+
+/// A property stored in the state bits of a voxel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockProperty {
+    /// Faces any of the six directions, the texture ids are rotated so
+    /// the front texture faces that way
+    Facing,
+    /// Like `Facing` but only north, east, south or west
+    HorizontalFacing,
+    /// Aligned to an axis like a log, the top and bottom textures are the ends
+    Axis,
+    /// Open or closed like a door
+    Open,
+    /// A level from 0 to 15, fluids use it for their level
+    Level,
 }
 
 impl Block {
@@ -21,4 +52,8 @@ impl Block {
     pub const LEFT: usize = 3;
     pub const FRONT: usize = 4;
     pub const BACK: usize = 5;
+
+    pub fn has_property(&self, property: BlockProperty) -> bool {
+        self.properties.contains(&property)
+    }
 }
@@ -1,22 +1,39 @@
 use crate::block::Block;
-use crate::block_texture::BlockTexture;
-use avoxel_rendering::prelude::BlockMaterial;
-use bevy::prelude::*;
+use crate::block_texture::{BlockTexture, MAX_ANIMATION_FRAMES};
+use crate::block_tick::BlockTickHandler;
+use crate::fluid::Fluid;
 use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::HashMap};
 
+/// The blocks and textures of a world. This is plain data, the texture and
+/// material handles used to render the blocks are kept on the render side.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct BlockLibrary {
     blocks: Vec<Block>,
+    /// Block ids by namespaced block name
+    #[serde(skip)]
+    block_ids: HashMap<String, u32>,
     textures: Vec<BlockTexture>,
+    /// Texture ids by texture name
+    #[serde(skip)]
+    texture_ids: HashMap<String, u32>,
     texture_path: Option<String>,
     /// The size of the individual textures on the texture atlas
     texture_size: u32,
     /// The number of textures on the texture atlas
     texture_count: u32,
+    /// Whether the textures were set in code with `set_block_textures`
+    #[serde(skip)]
+    provided_textures: bool,
+    /// Scheduled tick handlers by block id
+    #[serde(skip)]
+    tick_handlers: HashMap<u32, BlockTickHandler>,
+    /// Random tick handlers by block id
     #[serde(skip)]
-    texture_handle: Handle<Texture>,
+    random_tick_handlers: HashMap<u32, BlockTickHandler>,
+    /// Whether any block is a fluid
     #[serde(skip)]
-    material_handles: Vec<Handle<BlockMaterial>>,
+    has_fluids: bool,
 }
 
 impl Default for BlockLibrary {
@@ -25,26 +42,48 @@ impl Default for BlockLibrary {
             // we initialize it with a bunch of blocks
             // by default in case the generator needs those
             blocks: vec![Block::default(); 1024],
+            block_ids: Default::default(),
             textures: vec![BlockTexture::default()],
+            texture_ids: Default::default(),
             texture_path: None,
             texture_size: 32,
             texture_count: 1024,
-            texture_handle: Default::default(),
-            material_handles: vec![Handle::default()],
+            provided_textures: false,
+            tick_handlers: Default::default(),
+            random_tick_handlers: Default::default(),
+            has_fluids: false,
         }
     }
 }
 
 impl BlockLibrary {
+    /// The namespace of block names that don't have one
+    pub const DEFAULT_NAMESPACE: &'static str = "core";
+    /// Saved blocks that aren't in the library are loaded as this block
+    pub const PLACEHOLDER: &'static str = "core:unknown";
+
+    /// Adds the default namespace to names without one, `grass` becomes `core:grass`
+    pub fn namespaced(name: &str) -> Cow<str> {
+        if name.contains(':') {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(format!("{}:{}", Self::DEFAULT_NAMESPACE, name))
+        }
+    }
+
     pub fn new() -> Self {
         Self {
             blocks: vec![],
+            block_ids: Default::default(),
             textures: vec![],
+            texture_ids: Default::default(),
             texture_path: None,
             texture_size: 32,
             texture_count: 1024,
-            texture_handle: Default::default(),
-            material_handles: vec![],
+            provided_textures: false,
+            tick_handlers: Default::default(),
+            random_tick_handlers: Default::default(),
+            has_fluids: false,
         }
     }
 
@@ -52,7 +91,78 @@ impl BlockLibrary {
         &self.blocks[block_id]
     }
 
-    pub fn add_block(&mut self, block: Block) -> &mut Self {
+    /// Returns the runtime id of the block with `name`, names without a namespace
+    /// are looked up in the default namespace
+    pub fn get_block_id(&self, name: &str) -> Option<u32> {
+        self.block_ids.get(Self::namespaced(name).as_ref()).copied()
+    }
+
+    /// Returns the namespaced name of a block, `None` for unnamed blocks
+    pub fn get_block_name(&self, block_id: u32) -> Option<Cow<str>> {
+        self.blocks
+            .get(block_id as usize)
+            .filter(|block| !block.name.is_empty())
+            .map(|block| Self::namespaced(&block.name))
+    }
+
+    /// The id unknown saved blocks are loaded as, air if there is no placeholder block
+    pub fn placeholder_block_id(&self) -> u32 {
+        self.get_block_id(Self::PLACEHOLDER).unwrap_or(Block::AIR)
+    }
+
+    /// Adds the `core:unknown` placeholder block unless the library already has one.
+    /// Chunk maps add it to the libraries they use, whether loaded or built in code.
+    pub fn add_placeholder(&mut self) -> &mut Self {
+        if self.get_block_id(Self::PLACEHOLDER).is_none() {
+            self.add_block(Block {
+                name: Self::PLACEHOLDER.to_string(),
+                ..Default::default()
+            });
+        }
+        self
+    }
+
+    pub fn get_block_by_name(&self, name: &str) -> Option<&Block> {
+        self.get_block_id(name)
+            .map(|block_id| self.get_block(block_id as usize))
+    }
+
+    pub fn get_fluid(&self, block_id: u32) -> Option<&Fluid> {
+        self.blocks
+            .get(block_id as usize)
+            .and_then(|block| block.fluid.as_ref())
+    }
+
+    /// Fluid updates are only scheduled if there are fluids
+    pub fn has_fluids(&self) -> bool {
+        self.has_fluids
+    }
+
+    /// True for full cube blocks that aren't fluids, the only blocks downsampled chunks keep
+    pub fn is_solid_cube(&self, block_id: u32) -> bool {
+        block_id != Block::AIR
+            && self.blocks.get(block_id as usize).map_or(false, |block| {
+                block.shape.is_cube() && block.fluid.is_none()
+            })
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Adds a block with the next runtime id. Named blocks can be looked up
+    /// by their namespaced name, the first block with a name keeps it.
+    /// Fluids flow at most `Fluid::MAX_FLOW_DISTANCE` blocks, further distances are clamped.
+    pub fn add_block(&mut self, mut block: Block) -> &mut Self {
+        if let Some(fluid) = &mut block.fluid {
+            fluid.flow_distance = fluid.flow_distance.min(Fluid::MAX_FLOW_DISTANCE);
+        }
+        if !block.name.is_empty() {
+            self.block_ids
+                .entry(Self::namespaced(&block.name).into_owned())
+                .or_insert(self.blocks.len() as u32);
+        }
+        self.has_fluids |= block.fluid.is_some();
         self.blocks.push(block);
         self
     }
@@ -62,14 +172,125 @@ impl BlockLibrary {
         self
     }
 
-    pub fn get_block_texture(&self, texture_id: u32) -> BlockTexture {
-        self.textures[texture_id as usize]
+    pub fn get_block_texture(&self, texture_id: u32) -> &BlockTexture {
+        &self.textures[texture_id as usize]
+    }
+
+    /// The layers of all texture animations, in texture id order.
+    /// The shader looks up the current layer of an animation in this table.
+    pub fn animation_frames(&self) -> Vec<u32> {
+        let mut frames = vec![];
+        for animation in self.textures.iter().filter_map(|t| t.animation.as_ref()) {
+            frames.extend(animation.frames.layers());
+        }
+        frames.truncate(MAX_ANIMATION_FRAMES);
+        frames
+    }
+
+    /// The animation of every texture packed for the mesh, 0 for textures that aren't animated.
+    /// bits 0-7 frame count, 8-15 first frame in `animation_frames`,
+    /// 16-30 frame time in milliseconds, 31 interpolate
+    pub fn texture_animation_data(&self) -> Vec<u32> {
+        let mut offset = 0;
+        self.textures
+            .iter()
+            .map(|texture| {
+                let animation = match &texture.animation {
+                    Some(animation) => animation,
+                    None => return 0,
+                };
+                let count = animation.frames.layers().len();
+                let first = offset;
+                offset += count;
+                if count < 2 || first + count > MAX_ANIMATION_FRAMES || count > 0xFF {
+                    return 0;
+                }
+                let frame_time = ((animation.frame_time * 1000.).round() as u32).min(0x7FFF);
+                count as u32
+                    | (first as u32) << 8
+                    | frame_time << 16
+                    | (animation.interpolate as u32) << 31
+            })
+            .collect()
+    }
+
+    /// Replaces the block textures, texture ids are assigned in order
+    /// and can be looked up by name. Textures set this way are kept when
+    /// definitions are replaced and block library files can use their names.
+    pub fn set_block_textures(&mut self, textures: Vec<(String, BlockTexture)>) -> &mut Self {
+        self.provided_textures = true;
+        self.texture_ids = textures
+            .iter()
+            .enumerate()
+            .map(|(texture_id, (name, _))| (name.clone(), texture_id as u32))
+            .collect();
+        self.textures = textures
+            .into_iter()
+            .map(|(_, block_texture)| block_texture)
+            .collect();
+        self
+    }
+
+    pub fn get_texture_id(&self, name: &str) -> Option<u32> {
+        self.texture_ids.get(name).copied()
+    }
+
+    /// Sets the texture ids by name without marking the textures as provided
+    pub(crate) fn set_texture_ids(&mut self, texture_ids: HashMap<String, u32>) -> &mut Self {
+        self.texture_ids = texture_ids;
+        self
+    }
+
+    /// Whether the textures were set with `set_block_textures`
+    pub fn has_provided_textures(&self) -> bool {
+        self.provided_textures
+    }
+
+    /// Copies the textures and texture settings of `other`
+    pub(crate) fn copy_textures(&mut self, other: &BlockLibrary) -> &mut Self {
+        self.textures = other.textures.clone();
+        self.texture_ids = other.texture_ids.clone();
+        self.texture_path = other.texture_path.clone();
+        self.texture_size = other.texture_size;
+        self.texture_count = other.texture_count;
+        self.provided_textures = other.provided_textures;
+        self
     }
 
     pub fn get_block_library_as_json(&self) -> String {
         serde_json::to_string_pretty(self).unwrap()
     }
 
+    /// Loads blocks, textures and block models from json.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let mut block_library: Self = serde_json::from_str(json)?;
+        block_library.index_block_names();
+        Ok(block_library)
+    }
+
+    fn index_block_names(&mut self) {
+        self.has_fluids = self.blocks.iter().any(|block| block.fluid.is_some());
+        self.block_ids.clear();
+        for (block_id, block) in self.blocks.iter().enumerate() {
+            if !block.name.is_empty() {
+                self.block_ids
+                    .entry(Self::namespaced(&block.name).into_owned())
+                    .or_insert(block_id as u32);
+            }
+        }
+    }
+
+    /// Replaces the blocks and textures with the ones of a loaded block library.
+    /// Tick handlers and textures set with `set_block_textures` are kept.
+    pub fn replace_definitions(&mut self, loaded: &BlockLibrary) -> &mut Self {
+        self.blocks = loaded.blocks.clone();
+        if !self.provided_textures {
+            self.copy_textures(loaded);
+        }
+        self.index_block_names();
+        self
+    }
+
     pub fn set_texture_path(&mut self, path: &str) -> &mut Self {
         self.texture_path = Some(path.to_string());
         self
@@ -84,7 +305,7 @@ impl BlockLibrary {
         self
     }
 
-    pub fn get_texture_size(self) -> u32 {
+    pub fn get_texture_size(&self) -> u32 {
         self.texture_size
     }
 
@@ -97,21 +318,31 @@ impl BlockLibrary {
         self.texture_count
     }
 
-    pub fn get_texture_handle(&self) -> Handle<Texture> {
-        self.texture_handle.clone()
+    /// The handler is called when a tick scheduled for a block of this type is due
+    pub fn set_tick_handler(&mut self, block_id: u32, handler: BlockTickHandler) -> &mut Self {
+        self.tick_handlers.insert(block_id, handler);
+        self
     }
 
-    pub fn set_texture_handle(&mut self, texture_handle: Handle<Texture>) -> &mut Self {
-        self.texture_handle = texture_handle;
-        self
+    pub fn get_tick_handler(&self, block_id: u32) -> Option<&BlockTickHandler> {
+        self.tick_handlers.get(&block_id)
     }
 
-    pub fn add_material_handle(&mut self, material_handle: Handle<BlockMaterial>) -> &mut Self {
-        self.material_handles.push(material_handle);
+    /// The handler is called when a block of this type is picked for a random tick
+    pub fn set_random_tick_handler(
+        &mut self,
+        block_id: u32,
+        handler: BlockTickHandler,
+    ) -> &mut Self {
+        self.random_tick_handlers.insert(block_id, handler);
         self
     }
 
-    pub fn get_material_handle(&self, material_id: usize) -> Handle<BlockMaterial> {
-        self.material_handles[material_id].clone()
+    pub fn get_random_tick_handler(&self, block_id: u32) -> Option<&BlockTickHandler> {
+        self.random_tick_handlers.get(&block_id)
+    }
+
+    pub fn has_random_tick_handlers(&self) -> bool {
+        !self.random_tick_handlers.is_empty()
     }
 }
@@ -0,0 +1,645 @@
+use crate::{
+    block::{Block, BlockProperty},
+    block_library::BlockLibrary,
+    block_shape::BlockShape,
+    block_texture::{BlockTexture, TextureAnimation, TintMode},
+    fluid::Fluid,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+const FACE_NAMES: [&str; 6] = ["top", "right", "bottom", "left", "front", "back"];
+/// Key of named faces that sets the right, left, front and back face
+const SIDES: &str = "sides";
+
+/// The contents of a block library file.
+/// Runtime block ids are assigned in the order the blocks are listed starting at 1,
+/// id 0 is always air and must not be listed. A `core:unknown` placeholder block
+/// is added after the listed blocks unless the file defines one.
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "asset",
+    derive(bevy::reflect::TypeUuid),
+    uuid = "5b1f6f0e-3c1a-4d3e-9a57-2f0b8f6d1c42"
+)]
+pub struct BlockLibraryFile {
+    #[serde(default)]
+    pub texture_path: Option<String>,
+    #[serde(default = "default_texture_size")]
+    pub texture_size: u32,
+    /// The number of textures on the texture atlas, not needed when the
+    /// textures are set in code
+    #[serde(default)]
+    pub texture_count: u32,
+    /// Texture settings by texture id. Textures without settings use the defaults.
+    #[serde(default)]
+    pub textures: Vec<TextureDefinition>,
+    pub blocks: Vec<BlockDefinition>,
+}
+
+fn default_texture_size() -> u32 {
+    32
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TextureDefinition {
+    /// Name blocks can use instead of the texture id
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub rand_rot: bool,
+    #[serde(default)]
+    pub animation: Option<TextureAnimation>,
+    #[serde(default)]
+    pub tint: TintMode,
+}
+
+/// A texture referenced by id or by the name of its `TextureDefinition`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TextureRef {
+    Id(u32),
+    Name(String),
+}
+
+/// The same texture on all faces, one texture per face in the order
+/// top, right, bottom, left, front, back, or textures by face name.
+/// Named faces can use `sides` for the four side faces, faces named on their own
+/// take precedence over it.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockTextures {
+    All(TextureRef),
+    Faces([TextureRef; 6]),
+    Named(BTreeMap<String, TextureRef>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BlockDefinition {
+    pub name: String,
+    pub textures: BlockTextures,
+    #[serde(default)]
+    pub ao: bool,
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default)]
+    pub map_color: Option<[u8; 3]>,
+    #[serde(default)]
+    pub fluid: Option<Fluid>,
+    #[serde(default)]
+    pub properties: Vec<BlockProperty>,
+    #[serde(default)]
+    pub shape: BlockShape,
+}
+
+#[derive(Debug)]
+pub enum BlockLibraryError {
+    Json(serde_json::Error),
+    Ron(ron::Error),
+    /// A block is named air or two blocks have the same name
+    DuplicateBlockName {
+        name: String,
+    },
+    DuplicateTextureName {
+        name: String,
+    },
+    UnknownTexture {
+        block: String,
+        face: &'static str,
+        texture: String,
+    },
+    /// A named face that isn't a face name or `sides`
+    UnknownFace {
+        block: String,
+        face: String,
+    },
+    /// Named faces that leave a face without a texture
+    MissingFace {
+        block: String,
+        face: &'static str,
+    },
+    TextureIdOutOfRange {
+        block: String,
+        face: &'static str,
+        texture_id: u32,
+        texture_count: u32,
+    },
+    TooManyTextures {
+        textures: usize,
+        texture_count: u32,
+    },
+    AnimationFrameOutOfRange {
+        texture_id: u32,
+        layer: u32,
+        texture_count: u32,
+    },
+    /// A fluid flows further than `Fluid::MAX_FLOW_DISTANCE`
+    FlowDistanceOutOfRange {
+        block: String,
+        flow_distance: u32,
+    },
+}
+
+impl fmt::Display for BlockLibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockLibraryError::Json(e) => write!(f, "invalid block library json: {}", e),
+            BlockLibraryError::Ron(e) => write!(f, "invalid block library ron: {}", e),
+            BlockLibraryError::DuplicateBlockName { name } => {
+                write!(f, "block name \"{}\" is used more than once", name)
+            }
+            BlockLibraryError::DuplicateTextureName { name } => {
+                write!(f, "texture name \"{}\" is used more than once", name)
+            }
+            BlockLibraryError::UnknownTexture {
+                block,
+                face,
+                texture,
+            } => write!(
+                f,
+                "block \"{}\" uses unknown texture \"{}\" on its {} face",
+                block, texture, face
+            ),
+            BlockLibraryError::UnknownFace { block, face } => write!(
+                f,
+                "block \"{}\" has a texture for unknown face \"{}\", expected one of {} or {}",
+                block,
+                face,
+                FACE_NAMES.join(", "),
+                SIDES
+            ),
+            BlockLibraryError::MissingFace { block, face } => {
+                write!(f, "block \"{}\" has no texture on its {} face", block, face)
+            }
+            BlockLibraryError::TextureIdOutOfRange {
+                block,
+                face,
+                texture_id,
+                texture_count,
+            } => write!(
+                f,
+                "block \"{}\" uses texture id {} on its {} face but texture_count is {}",
+                block, texture_id, face, texture_count
+            ),
+            BlockLibraryError::TooManyTextures {
+                textures,
+                texture_count,
+            } => write!(
+                f,
+                "{} textures are defined but texture_count is {}",
+                textures, texture_count
+            ),
+            BlockLibraryError::AnimationFrameOutOfRange {
+                texture_id,
+                layer,
+                texture_count,
+            } => write!(
+                f,
+                "the animation of texture {} uses layer {} but texture_count is {}",
+                texture_id, layer, texture_count
+            ),
+            BlockLibraryError::FlowDistanceOutOfRange {
+                block,
+                flow_distance,
+            } => write!(
+                f,
+                "fluid \"{}\" has a flow_distance of {} but at most {} is supported",
+                block,
+                flow_distance,
+                Fluid::MAX_FLOW_DISTANCE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockLibraryError {}
+
+impl BlockLibraryFile {
+    pub fn from_json(bytes: &[u8]) -> Result<Self, BlockLibraryError> {
+        serde_json::from_slice(bytes).map_err(BlockLibraryError::Json)
+    }
+
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, BlockLibraryError> {
+        ron::de::from_bytes(bytes).map_err(BlockLibraryError::Ron)
+    }
+
+    /// Resolves texture names and checks texture ids against `texture_count`
+    pub fn into_block_library(self) -> Result<BlockLibrary, BlockLibraryError> {
+        let block_library = self.texture_library()?;
+        self.add_blocks(block_library)
+    }
+
+    /// Like `into_block_library`, but if `textures` has textures set with
+    /// `BlockLibrary::set_block_textures` the blocks use those and their names
+    /// instead of the textures of the file.
+    pub fn into_block_library_with_textures(
+        self,
+        textures: &BlockLibrary,
+    ) -> Result<BlockLibrary, BlockLibraryError> {
+        if !textures.has_provided_textures() {
+            return self.into_block_library();
+        }
+        let mut block_library = BlockLibrary::new();
+        block_library.copy_textures(textures);
+        self.add_blocks(block_library)
+    }
+
+    /// A library with the textures of the file and no blocks
+    fn texture_library(&self) -> Result<BlockLibrary, BlockLibraryError> {
+        if self.textures.len() > self.texture_count as usize {
+            return Err(BlockLibraryError::TooManyTextures {
+                textures: self.textures.len(),
+                texture_count: self.texture_count,
+            });
+        }
+        let mut texture_ids: HashMap<String, u32> = HashMap::default();
+        for (texture_id, texture) in self.textures.iter().enumerate() {
+            if let Some(name) = &texture.name {
+                if texture_ids
+                    .insert(name.clone(), texture_id as u32)
+                    .is_some()
+                {
+                    return Err(BlockLibraryError::DuplicateTextureName { name: name.clone() });
+                }
+            }
+        }
+
+        let mut block_library = BlockLibrary::new();
+        if let Some(texture_path) = &self.texture_path {
+            block_library.set_texture_path(texture_path);
+        }
+        block_library
+            .set_texture_size(self.texture_size)
+            .set_texture_count(self.texture_count)
+            .set_texture_ids(texture_ids);
+        for texture_id in 0..self.texture_count as usize {
+            let block_texture = match self.textures.get(texture_id) {
+                Some(texture) => BlockTexture {
+                    rand_rot: texture.rand_rot,
+                    animation: texture.animation.clone(),
+                    tint: texture.tint,
+                },
+                None => BlockTexture::default(),
+            };
+            if let Some(animation) = &block_texture.animation {
+                let layers = animation.frames.layers();
+                if let Some(layer) = layers.iter().find(|layer| **layer >= self.texture_count) {
+                    return Err(BlockLibraryError::AnimationFrameOutOfRange {
+                        texture_id: texture_id as u32,
+                        layer: *layer,
+                        texture_count: self.texture_count,
+                    });
+                }
+            }
+            block_library.add_block_texture(block_texture);
+        }
+        Ok(block_library)
+    }
+
+    /// Adds the blocks of the file to a library that has its textures
+    fn add_blocks(
+        self,
+        mut block_library: BlockLibrary,
+    ) -> Result<BlockLibrary, BlockLibraryError> {
+        block_library.add_block(Block {
+            name: "air".to_string(),
+            ..Default::default()
+        });
+
+        for definition in self.blocks {
+            if block_library.get_block_id(&definition.name).is_some() {
+                return Err(BlockLibraryError::DuplicateBlockName {
+                    name: definition.name,
+                });
+            }
+            let faces = match &definition.textures {
+                BlockTextures::All(texture) => [
+                    texture.clone(),
+                    texture.clone(),
+                    texture.clone(),
+                    texture.clone(),
+                    texture.clone(),
+                    texture.clone(),
+                ],
+                BlockTextures::Faces(faces) => faces.clone(),
+                BlockTextures::Named(named) => named_faces(named, &definition.name)?,
+            };
+            let mut block_texture_ids = [0; 6];
+            for (face, texture) in faces.iter().enumerate() {
+                block_texture_ids[face] =
+                    resolve_texture(texture, &block_library, &definition.name, FACE_NAMES[face])?;
+            }
+            if let Some(fluid) = &definition.fluid {
+                if fluid.flow_distance > Fluid::MAX_FLOW_DISTANCE {
+                    return Err(BlockLibraryError::FlowDistanceOutOfRange {
+                        block: definition.name,
+                        flow_distance: fluid.flow_distance,
+                    });
+                }
+            }
+            if let Some(boxes) = definition.shape.boxes() {
+                for model_box in boxes.iter() {
+                    for (face, texture_id) in model_box.texture_ids.iter().flatten().enumerate() {
+                        resolve_texture(
+                            &TextureRef::Id(*texture_id),
+                            &block_library,
+                            &definition.name,
+                            FACE_NAMES[face],
+                        )?;
+                    }
+                }
+            }
+            block_library.add_block(Block {
+                texture_ids: block_texture_ids,
+                name: definition.name,
+                ao: definition.ao,
+                transparent: definition.transparent,
+                map_color: definition.map_color,
+                fluid: definition.fluid,
+                properties: definition.properties,
+                shape: definition.shape,
+            });
+        }
+        block_library.add_placeholder();
+        Ok(block_library)
+    }
+}
+
+/// Orders textures by face name like `BlockTextures::Faces`
+fn named_faces(
+    named: &BTreeMap<String, TextureRef>,
+    block: &str,
+) -> Result<[TextureRef; 6], BlockLibraryError> {
+    let mut faces: [Option<TextureRef>; 6] = Default::default();
+    if let Some(texture) = named.get(SIDES) {
+        for face in [Block::RIGHT, Block::LEFT, Block::FRONT, Block::BACK].iter() {
+            faces[*face] = Some(texture.clone());
+        }
+    }
+    for (name, texture) in named {
+        if name == SIDES {
+            continue;
+        }
+        let face = FACE_NAMES
+            .iter()
+            .position(|face_name| face_name == name)
+            .ok_or_else(|| BlockLibraryError::UnknownFace {
+                block: block.to_string(),
+                face: name.clone(),
+            })?;
+        faces[face] = Some(texture.clone());
+    }
+    if let Some(face) = faces.iter().position(Option::is_none) {
+        return Err(BlockLibraryError::MissingFace {
+            block: block.to_string(),
+            face: FACE_NAMES[face],
+        });
+    }
+    let face = |face: usize| faces[face].clone().unwrap();
+    Ok([face(0), face(1), face(2), face(3), face(4), face(5)])
+}
+
+fn resolve_texture(
+    texture: &TextureRef,
+    block_library: &BlockLibrary,
+    block: &str,
+    face: &'static str,
+) -> Result<u32, BlockLibraryError> {
+    let texture_count = block_library.get_texture_count();
+    let texture_id =
+        match texture {
+            TextureRef::Id(texture_id) => *texture_id,
+            TextureRef::Name(name) => block_library.get_texture_id(name).ok_or_else(|| {
+                BlockLibraryError::UnknownTexture {
+                    block: block.to_string(),
+                    face,
+                    texture: name.clone(),
+                }
+            })?,
+        };
+    if texture_id >= texture_count {
+        return Err(BlockLibraryError::TextureIdOutOfRange {
+            block: block.to_string(),
+            face,
+            texture_id,
+            texture_count,
+        });
+    }
+    Ok(texture_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block_library::BlockLibrary,
+        block_library_file::{BlockLibraryError, BlockLibraryFile},
+        block_texture::BlockTexture,
+    };
+
+    /// Loads a block library file in the given format
+    fn load(format: &str, input: &str) -> Result<BlockLibrary, BlockLibraryError> {
+        let file = match format {
+            "json" => BlockLibraryFile::from_json(input.as_bytes())?,
+            _ => BlockLibraryFile::from_ron(input.as_bytes())?,
+        };
+        file.into_block_library()
+    }
+
+    #[test]
+    fn errors() {
+        // format, input and the expected error variant
+        let cases = [
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [{"name": "stone""#,
+                "Json",
+            ),
+            (
+                "ron",
+                r#"(texture_count: 1, blocks: [(name: "stone""#,
+                "Ron",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [{"name": "stone", "textures": "granite"}]}"#,
+                "UnknownTexture",
+            ),
+            (
+                "ron",
+                r#"(texture_count: 1, blocks: [(name: "stone", textures: "granite")])"#,
+                "UnknownTexture",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [
+                    {"name": "stone", "textures": 0},
+                    {"name": "stone", "textures": 0}
+                ]}"#,
+                "DuplicateBlockName",
+            ),
+            (
+                "ron",
+                r#"(texture_count: 1, blocks: [
+                    (name: "stone", textures: 0),
+                    (name: "core:stone", textures: 0),
+                ])"#,
+                "DuplicateBlockName",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [{"name": "air", "textures": 0}]}"#,
+                "DuplicateBlockName",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [
+                    {"name": "grass", "textures": {"tops": 0, "sides": 0, "bottom": 0}}
+                ]}"#,
+                "UnknownFace",
+            ),
+            (
+                "ron",
+                r#"(texture_count: 1, blocks: [
+                    (name: "grass", textures: {"top": 0, "side": 0, "bottom": 0}),
+                ])"#,
+                "UnknownFace",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [
+                    {"name": "grass", "textures": {"top": 0, "sides": 0}}
+                ]}"#,
+                "MissingFace",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "blocks": [{"name": "stone", "textures": 1}]}"#,
+                "TextureIdOutOfRange",
+            ),
+            (
+                "json",
+                r#"{"texture_count": 1, "textures": [{"name": "a"}, {"name": "b"}], "blocks": []}"#,
+                "TooManyTextures",
+            ),
+            (
+                "ron",
+                r#"(texture_count: 2, textures: [(name: Some("a")), (name: Some("a"))], blocks: [])"#,
+                "DuplicateTextureName",
+            ),
+        ];
+        for (format, input, expected) in cases.iter() {
+            let error = match load(format, input) {
+                Ok(_) => panic!("{} {} loaded", format, input),
+                Err(error) => error,
+            };
+            assert!(
+                format!("{:?}", error).starts_with(expected),
+                "expected {} from {} {}, got {}",
+                expected,
+                format,
+                input,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn named_faces() {
+        let block_library = load(
+            "ron",
+            r#"(
+                texture_count: 3,
+                textures: [(name: Some("grass")), (name: Some("grass_side")), (name: Some("dirt"))],
+                blocks: [
+                    (name: "grass", textures: {"top": "grass", "sides": "grass_side", "bottom": 2}),
+                    (name: "log", textures: {"top": 0, "bottom": 0, "sides": 1, "front": 2}),
+                ],
+            )"#,
+        )
+        .unwrap();
+        let texture_ids = |name: &str| {
+            let block_id = block_library.get_block_id(name).unwrap();
+            block_library.get_block(block_id as usize).texture_ids
+        };
+        assert_eq!(texture_ids("grass"), [0, 1, 2, 1, 1, 1]);
+        assert_eq!(texture_ids("log"), [0, 1, 0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn flow_distance_is_validated() {
+        let json = |flow_distance: u32| {
+            format!(
+                r#"{{"texture_count": 1, "blocks": [{{"name": "water", "textures": 0,
+                "fluid": {{"flow_distance": {}, "tick_delay": 5}}}}]}}"#,
+                flow_distance
+            )
+        };
+        let block_library = BlockLibraryFile::from_json(json(7).as_bytes())
+            .unwrap()
+            .into_block_library()
+            .unwrap();
+        let water = block_library.get_block_id("water").unwrap();
+        assert_eq!(block_library.get_fluid(water).unwrap().flow_distance, 7);
+
+        let error = BlockLibraryFile::from_json(json(8).as_bytes())
+            .unwrap()
+            .into_block_library()
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            BlockLibraryError::FlowDistanceOutOfRange {
+                flow_distance: 8,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn provided_textures() {
+        let mut block_library = BlockLibrary::new();
+        block_library
+            .set_block_textures(vec![
+                ("stone".to_string(), BlockTexture::default()),
+                ("dirt".to_string(), BlockTexture::default()),
+            ])
+            .set_texture_count(2)
+            .set_texture_size(16);
+        let file = || {
+            BlockLibraryFile::from_json(br#"{"blocks": [{"name": "dirt", "textures": "dirt"}]}"#)
+                .unwrap()
+        };
+        // without the provided textures the name is unknown
+        assert!(matches!(
+            file().into_block_library().err().unwrap(),
+            BlockLibraryError::UnknownTexture { .. }
+        ));
+
+        let loaded = file()
+            .into_block_library_with_textures(&block_library)
+            .unwrap();
+        let dirt = loaded.get_block_id("dirt").unwrap();
+        assert_eq!(loaded.get_block(dirt as usize).texture_ids, [1; 6]);
+
+        // reloading a file keeps the provided textures
+        let file_textures = load(
+            "json",
+            r#"{"texture_count": 8, "textures": [{"name": "grass"}], "blocks": []}"#,
+        )
+        .unwrap();
+        block_library.replace_definitions(&file_textures);
+        assert_eq!(block_library.get_texture_count(), 2);
+        assert_eq!(block_library.get_texture_size(), 16);
+        assert_eq!(block_library.get_texture_id("dirt"), Some(1));
+        assert_eq!(block_library.get_texture_id("grass"), None);
+
+        // without provided textures the ones of the file are used
+        let mut block_library = BlockLibrary::new();
+        block_library.replace_definitions(&file_textures);
+        assert_eq!(block_library.get_texture_count(), 8);
+        assert_eq!(block_library.get_texture_id("grass"), Some(0));
+    }
+}
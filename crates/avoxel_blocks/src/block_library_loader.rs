@@ -0,0 +1,97 @@
+use crate::{block_library::BlockLibrary, block_library_file::BlockLibraryFile};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+/// Loads `.blocks.json` and `.blocks.ron` files as `BlockLibraryFile` assets.
+/// Their texture names are resolved when they are applied, because they can
+/// refer to textures set in code.
+#[derive(Default)]
+pub struct BlockLibraryLoader;
+
+impl AssetLoader for BlockLibraryLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let is_ron = load_context
+                .path()
+                .extension()
+                .map_or(false, |extension| extension == "ron");
+            let file = if is_ron {
+                BlockLibraryFile::from_ron(bytes)?
+            } else {
+                BlockLibraryFile::from_json(bytes)?
+            };
+            load_context.set_default_asset(LoadedAsset::new(file));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["blocks.json", "blocks.ron"]
+    }
+}
+
+/// The block library file the `BlockLibrary` resource is loaded from.
+/// Whenever the file is loaded or reloaded the blocks and textures of the
+/// resource are replaced, which remeshes the visible chunks.
+pub struct BlockLibraryHandle(pub Handle<BlockLibraryFile>);
+
+pub struct BlockLibraryAssetPlugin;
+
+impl Plugin for BlockLibraryAssetPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<BlockLibraryFile>()
+            .init_asset_loader::<BlockLibraryLoader>()
+            .add_system(apply_loaded_block_library.system());
+    }
+}
+
+/// Applies the block library file once it's loaded or modified. A file that
+/// fails to resolve is applied again when the textures of the library change,
+/// it may use the names of textures that aren't set yet.
+pub fn apply_loaded_block_library(
+    mut events: EventReader<AssetEvent<BlockLibraryFile>>,
+    block_library_handle: Option<Res<BlockLibraryHandle>>,
+    block_library_files: Res<Assets<BlockLibraryFile>>,
+    mut block_library: ResMut<BlockLibrary>,
+    mut unresolved: Local<bool>,
+) {
+    let block_library_handle = match block_library_handle {
+        Some(block_library_handle) => block_library_handle,
+        None => return,
+    };
+    let mut loaded = false;
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle }
+                if *handle == block_library_handle.0 =>
+            {
+                loaded = true;
+            }
+            _ => {}
+        }
+    }
+    if !loaded && !(*unresolved && block_library.is_changed()) {
+        return;
+    }
+    let file = match block_library_files.get(&block_library_handle.0) {
+        Some(file) => file.clone(),
+        None => return,
+    };
+    match file.into_block_library_with_textures(&block_library) {
+        Ok(resolved) => {
+            *unresolved = false;
+            block_library.replace_definitions(&resolved);
+        }
+        Err(e) => {
+            *unresolved = true;
+            error!("failed to apply the block library file: {}", e);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use crate::block_library::BlockLibrary;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The names of the block ids used in a saved chunk, so the ids can be
+/// remapped when blocks were added or reordered since the chunk was saved
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BlockPalette {
+    entries: Vec<(u32, String)>,
+    /// Names of blocks that weren't in the library when the chunk was loaded. Their
+    /// voxels are placeholders with the index of their name + 1 in the state bits,
+    /// so the blocks come back once they're added to the library again.
+    #[serde(default)]
+    unknown: Vec<String>,
+}
+
+impl BlockPalette {
+    /// Unnamed blocks are left out and keep their id when loaded
+    pub fn new<I: IntoIterator<Item = u32>>(block_ids: I, block_library: &BlockLibrary) -> Self {
+        let mut entries: Vec<(u32, String)> = block_ids
+            .into_iter()
+            .filter_map(|block_id| {
+                block_library
+                    .get_block_name(block_id)
+                    .map(|name| (block_id, name.into_owned()))
+            })
+            .collect();
+        entries.sort_unstable_by_key(|(block_id, _)| *block_id);
+        entries.dedup_by_key(|(block_id, _)| *block_id);
+        Self {
+            entries,
+            unknown: vec![],
+        }
+    }
+
+    /// Keeps the names of the unknown blocks the placeholders in the chunk stand for
+    pub fn with_unknown(mut self, unknown: Vec<String>) -> Self {
+        self.unknown = unknown;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The saved block ids and their names
+    pub fn entries(&self) -> &[(u32, String)] {
+        &self.entries
+    }
+
+    /// See `with_unknown`
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Returns the runtime ids of the saved ids that changed. Blocks that aren't
+    /// in the library anymore are mapped to the placeholder block.
+    pub fn remap(&self, block_library: &BlockLibrary) -> HashMap<u32, u32> {
+        let placeholder = block_library.placeholder_block_id();
+        self.entries
+            .iter()
+            .filter_map(|(saved_id, name)| {
+                let block_id = block_library.get_block_id(name).unwrap_or(placeholder);
+                if block_id != *saved_id {
+                    Some((*saved_id, block_id))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Block, BlockLibrary, BlockPalette};
+
+    fn block_library(names: &[&str]) -> BlockLibrary {
+        let mut block_library = BlockLibrary::new();
+        for name in names {
+            block_library.add_block(Block {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+        block_library
+    }
+
+    #[test]
+    fn remaps_reordered_and_unknown_blocks() {
+        let saved = block_library(&["air", "grass", "dirt", "mymod:ore"]);
+        let palette = BlockPalette::new(vec![0, 1, 2, 3], &saved);
+
+        let current = block_library(&["air", "core:stone", "dirt", "grass", "unknown"]);
+        let remap = palette.remap(&current);
+        assert_eq!(remap.get(&0), None);
+        assert_eq!(remap.get(&1), Some(&3));
+        assert_eq!(remap.get(&2), None);
+        assert_eq!(remap.get(&3), Some(&4));
+    }
+}
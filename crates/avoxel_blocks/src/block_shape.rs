@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// The geometry of a block. Shapes are oriented by the `Facing` and `Axis`
+/// properties of the block, the unrotated front faces south (positive z).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum BlockShape {
+    /// A full unit cube
+    Cube,
+    /// The bottom half of a block
+    Slab,
+    /// A slab with a half height step at the back
+    Stairs,
+    /// Two diagonal planes textured on both sides like plants,
+    /// uses the front texture
+    Cross,
+    /// A thin post standing in the middle of the block
+    Torch,
+    /// A list of boxes
+    Model(Vec<ModelBox>),
+}
+
+impl Default for BlockShape {
+    fn default() -> Self {
+        BlockShape::Cube
+    }
+}
+
+/// An axis aligned box of a block model in sixteenths of a block
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelBox {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Texture ids per face in the order top, right, bottom, left, front, back.
+    /// Uses the texture ids of the block if not set.
+    #[serde(default)]
+    pub texture_ids: Option<[u32; 6]>,
+}
+
+impl ModelBox {
+    pub fn new(min: [f32; 3], max: [f32; 3]) -> Self {
+        Self {
+            min,
+            max,
+            texture_ids: None,
+        }
+    }
+}
+
+impl BlockShape {
+    /// Returns the boxes of box based shapes, `None` for `Cross`
+    pub fn boxes(&self) -> Option<Cow<[ModelBox]>> {
+        let boxes = match self {
+            BlockShape::Cube => vec![ModelBox::new([0., 0., 0.], [16., 16., 16.])],
+            BlockShape::Slab => vec![ModelBox::new([0., 0., 0.], [16., 8., 16.])],
+            BlockShape::Stairs => vec![
+                ModelBox::new([0., 0., 0.], [16., 8., 16.]),
+                ModelBox::new([0., 8., 0.], [16., 16., 8.]),
+            ],
+            BlockShape::Cross => return None,
+            BlockShape::Torch => vec![ModelBox::new([7., 0., 7.], [9., 10., 9.])],
+            BlockShape::Model(boxes) => return Some(Cow::Borrowed(boxes)),
+        };
+        Some(Cow::Owned(boxes))
+    }
+
+    pub fn is_cube(&self) -> bool {
+        matches!(self, BlockShape::Cube)
+    }
+}
@@ -1,13 +1,67 @@
 use serde::{Deserialize, Serialize};
 
+/// The number of animation frames the shader has room for
+pub const MAX_ANIMATION_FRAMES: usize = 256;
+
 /// In the future this struct will contain more fields for texture
-/// For example texture biome hue to change colors base on biomes
-/// And maybe even season hues/color offsets for seasons that would be passed to the shader.
+/// For example season hues/color offsets for seasons that would be passed to the shader.
 /// Could also store UV coordinates in the block texture instead of calculating them
 /// for every block during meshing. Random rotations, would probably still be calculated
 /// by the mesher.
-#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 pub struct BlockTexture {
     /// Defines whether the texture should be randomly rotated
     pub rand_rot: bool,
+    /// Animated textures cycle through layers of the texture array in the shader
+    #[serde(default)]
+    pub animation: Option<TextureAnimation>,
+    /// The color the texture is multiplied with
+    #[serde(default)]
+    pub tint: TintMode,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TintMode {
+    None,
+    /// The grass tint of the column the block is in
+    Grass,
+    /// The foliage tint of the column the block is in
+    Foliage,
+    Fixed([u8; 3]),
+}
+
+impl Default for TintMode {
+    fn default() -> Self {
+        TintMode::None
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TextureAnimation {
+    pub frames: AnimationFrames,
+    /// Seconds each frame is shown
+    pub frame_time: f32,
+    /// Blend into the next frame instead of stepping
+    #[serde(default)]
+    pub interpolate: bool,
+}
+
+/// The texture array layers an animation cycles through
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum AnimationFrames {
+    /// `count` layers starting at `first`
+    Range {
+        first: u32,
+        count: u32,
+    },
+    List(Vec<u32>),
+}
+
+impl AnimationFrames {
+    pub fn layers(&self) -> Vec<u32> {
+        match self {
+            AnimationFrames::Range { first, count } => (*first..*first + *count).collect(),
+            AnimationFrames::List(layers) => layers.clone(),
+        }
+    }
 }
@@ -0,0 +1,16 @@
+use avoxel_math::Pos;
+use std::sync::Arc;
+
+/// What block tick handlers can do with the world
+pub trait BlockTickContext {
+    fn get_voxel(&self, pos: &Pos) -> Option<u32>;
+
+    fn set_voxel(&mut self, voxel: u32, pos: &Pos);
+
+    /// Schedules a tick for `pos` after `delay` ticks. Ticks due on the same tick run
+    /// in order of priority, lowest first. Returns false if a tick is already scheduled.
+    fn schedule_tick(&mut self, pos: &Pos, delay: u64, priority: i32) -> bool;
+}
+
+/// Called with the position and voxel of the ticked block
+pub type BlockTickHandler = Arc<dyn Fn(&mut dyn BlockTickContext, Pos, u32) + Send + Sync>;
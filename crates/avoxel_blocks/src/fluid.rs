@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// Makes a block flow like a liquid
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Fluid {
+    /// How many blocks the fluid flows from a source over flat ground,
+    /// at most `MAX_FLOW_DISTANCE`
+    pub flow_distance: u32,
+    /// Ticks between updates of the fluid
+    pub tick_delay: u64,
+}
+
+impl Fluid {
+    /// Flowing fluids store their level in 3 bits, the next level marks falling fluids
+    pub const MAX_FLOW_DISTANCE: u32 = 7;
+    pub const WATER: Fluid = Fluid {
+        flow_distance: 7,
+        tick_delay: 5,
+    };
+    pub const LAVA: Fluid = Fluid {
+        flow_distance: 3,
+        tick_delay: 30,
+    };
+}
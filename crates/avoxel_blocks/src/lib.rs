@@ -1,7 +1,28 @@
 mod block;
 mod block_library;
+mod block_library_file;
+#[cfg(feature = "asset")]
+mod block_library_loader;
+mod block_palette;
+mod block_shape;
 mod block_texture;
+mod block_tick;
+mod fluid;
 
-pub use block::Block;
+pub use block::{Block, BlockProperty};
 pub use block_library::BlockLibrary;
-pub use block_texture::BlockTexture;
+pub use block_library_file::{
+    BlockDefinition, BlockLibraryError, BlockLibraryFile, BlockTextures, TextureDefinition,
+    TextureRef,
+};
+#[cfg(feature = "asset")]
+pub use block_library_loader::{
+    apply_loaded_block_library, BlockLibraryAssetPlugin, BlockLibraryHandle, BlockLibraryLoader,
+};
+pub use block_palette::BlockPalette;
+pub use block_shape::{BlockShape, ModelBox};
+pub use block_texture::{
+    AnimationFrames, BlockTexture, TextureAnimation, TintMode, MAX_ANIMATION_FRAMES,
+};
+pub use block_tick::{BlockTickContext, BlockTickHandler};
+pub use fluid::Fluid;
@@ -1,7 +1,12 @@
-use crate::{compressed_chunk::Lz4CompressedChunk, voxel::Voxel};
+use crate::{
+    column_tint::ColumnTint,
+    compressed_chunk::{compress_bytes, Lz4CompressedChunk},
+    voxel::{voxel_from_parts, Voxel, VoxelBits},
+};
 use avoxel_math::{Extent3, Pos};
 use bevy_math::Vec3;
 use byteorder::{ByteOrder, LittleEndian};
+use std::collections::HashSet;
 
 // Chunk padding used so neighbor chunk lookups aren't needed in a lot of cases
 pub const CHUNK_PADDING: i32 = 1;
@@ -20,6 +25,14 @@ pub struct Chunk {
     pub ambient_voxel: Voxel,
     /// World position divided by chunk size
     pub pos: Pos,
+    /// Incremented on every modification so copies of the chunk,
+    /// like an in flight compression, can be told apart from the current chunk
+    pub version: u64,
+    /// Tints of the columns including the padding, empty if the generator didn't set any
+    pub column_tints: Vec<ColumnTint>,
+    /// How full each voxel is for smooth meshing, indexed like `voxels`.
+    /// Empty if the chunk doesn't have any, solid voxels are full then.
+    pub densities: Vec<u8>,
 }
 
 impl Chunk {
@@ -28,6 +41,9 @@ impl Chunk {
             pos,
             ambient_voxel: initial_voxel,
             voxels: vec![],
+            version: 0,
+            column_tints: vec![],
+            densities: vec![],
         }
     }
 
@@ -36,6 +52,9 @@ impl Chunk {
             pos,
             ambient_voxel,
             voxels,
+            version: 0,
+            column_tints: vec![],
+            densities: vec![],
         }
     }
 
@@ -52,6 +71,7 @@ impl Chunk {
     /// Uses unstable feature slice_fill to fill an area with voxels of a certain type
     pub fn fill_area(&mut self, voxel: u32, min: Pos, max: Pos) {
         self.fill_if_empty();
+        self.version += 1;
         for z in min.z..max.z {
             for x in min.x..max.x {
                 let start_i = self.block_index(Pos::new(x, min.y, z));
@@ -91,6 +111,78 @@ impl Chunk {
         self.fill_if_empty();
         let i = self.block_index(pos);
         self.voxels[i] = voxel;
+        self.version += 1;
+    }
+
+    fn column_index(&self, x: i32, z: i32) -> usize {
+        let min = self.extent().min;
+        ((x - min.x) + (z - min.z) * CHUNK_SIZE_WITH_PADDING) as usize
+    }
+
+    /// Sets the tint of the column at the world position `x`, `z`
+    pub fn set_column_tint(&mut self, x: i32, z: i32, tint: ColumnTint) {
+        if self.column_tints.is_empty() {
+            self.column_tints = vec![
+                ColumnTint::default();
+                (CHUNK_SIZE_WITH_PADDING * CHUNK_SIZE_WITH_PADDING) as usize
+            ];
+        }
+        let i = self.column_index(x, z);
+        self.column_tints[i] = tint;
+    }
+
+    pub fn get_column_tint(&self, x: i32, z: i32) -> ColumnTint {
+        self.column_tints
+            .get(self.column_index(x, z))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets how full the voxel at the world position is, 255 is full.
+    /// Only used by smooth meshing, make sure the position is within the bounds of the chunk.
+    pub fn set_density(&mut self, density: u8, pos: Pos) {
+        if self.densities.is_empty() {
+            self.densities = vec![u8::MAX; CHUNK_STORAGE_SIZE];
+        }
+        let i = self.block_index(pos);
+        self.densities[i] = density;
+        self.version += 1;
+    }
+
+    /// How full the voxel at the world position is, air is always empty
+    pub fn get_density(&self, pos: Pos) -> u8 {
+        let i = self.block_index(pos);
+        self.density_at(i, self.get_voxel(pos))
+    }
+
+    /// Density of the voxel at index `i` of `voxels`
+    pub fn density_at(&self, i: usize, voxel: Voxel) -> u8 {
+        if voxel.id() == 0 {
+            0
+        } else {
+            self.densities.get(i).copied().unwrap_or(u8::MAX)
+        }
+    }
+
+    /// The block ids of all voxels in the chunk, including the ambient voxel
+    pub fn block_ids(&self) -> HashSet<u32> {
+        let mut block_ids: HashSet<u32> = self.voxels.iter().map(|voxel| voxel.id()).collect();
+        block_ids.insert(self.ambient_voxel.id());
+        block_ids
+    }
+
+    /// Replaces block ids, the state bits of the voxels are kept
+    pub fn remap_block_ids<F: Fn(u32) -> u32>(&mut self, remap: F) {
+        self.remap_voxels(|voxel| voxel_from_parts(remap(voxel.id()), voxel.state()));
+    }
+
+    /// Replaces every voxel, including the ambient voxel
+    pub fn remap_voxels<F: Fn(Voxel) -> Voxel>(&mut self, remap: F) {
+        self.ambient_voxel = remap(self.ambient_voxel);
+        for voxel in self.voxels.iter_mut() {
+            *voxel = remap(*voxel);
+        }
+        self.version += 1;
     }
 
     pub fn get_chunk_translation(self) -> Vec3 {
@@ -109,6 +201,13 @@ impl Chunk {
         }
     }
 
+    /// Bytes used by the voxels, column tints and densities
+    pub fn memory_usage(&self) -> usize {
+        self.voxels.len() * core::mem::size_of::<Voxel>()
+            + self.column_tints.len() * core::mem::size_of::<ColumnTint>()
+            + self.densities.len()
+    }
+
     // Compress the map in-memory using the LZ4 algorithm.
     //
     // WARNING: If byteorder = false, the voxels vec will be used as a byte slice without
@@ -135,12 +234,21 @@ impl Chunk {
         }
 
         let (_output, _result) = encoder.finish();
+        // neighboring columns mostly have the same tint, so they compress well
+        let column_tint_bytes: Vec<u8> = self
+            .column_tints
+            .iter()
+            .flat_map(|tint| tint.to_bytes().to_vec())
+            .collect();
 
         Lz4CompressedChunk {
             pos: self.pos,
             ambient_voxel: self.ambient_voxel,
             compressed_voxels: compressed_bytes,
             empty: self.is_empty(),
+            version: self.version,
+            compressed_column_tints: compress_bytes(&column_tint_bytes, compression_level),
+            compressed_densities: compress_bytes(&self.densities, compression_level),
         }
     }
 }
@@ -148,8 +256,9 @@ impl Chunk {
 #[cfg(test)]
 mod tests {
     use crate::{
-        chunk::{Chunk, CHUNK_STORAGE_SIZE},
-        voxel::Voxel,
+        chunk::{Chunk, CHUNK_SIZE_WITH_PADDING, CHUNK_STORAGE_SIZE},
+        column_tint::ColumnTint,
+        voxel::{Axis, Facing, Voxel, VoxelBits},
     };
     use avoxel_math::Pos;
 
@@ -169,4 +278,69 @@ mod tests {
             chunk.voxels
         );
     }
+
+    #[test]
+    fn state_bits() {
+        let block: Voxel = 7;
+        let voxel = block
+            .with_facing(Facing::East)
+            .with_open(true)
+            .with_level(3);
+        assert_eq!(voxel.id(), 7);
+        assert_eq!(voxel.facing(), Facing::East);
+        assert_eq!(voxel.axis(), Axis::Y);
+        assert!(voxel.is_open());
+        assert_eq!(voxel.level(), 3);
+        let voxel = voxel.with_axis(Axis::Z).with_open(false);
+        assert_eq!(voxel.facing(), Facing::East);
+        assert_eq!(voxel.axis(), Axis::Z);
+        assert!(!voxel.is_open());
+
+        let mut chunk = Chunk::new(Pos::new(0, 0, 0), 0);
+        chunk.set_voxel(voxel, Pos::new(3, 4, 5));
+        let decompressed = chunk.compress(10, true).decompress(true);
+        assert_eq!(decompressed.get_voxel(Pos::new(3, 4, 5)), voxel);
+    }
+
+    #[test]
+    fn densities() {
+        let mut chunk = Chunk::new(Pos::new(0, 0, 0), 0);
+        chunk.set_voxel(2, Pos::new(3, 4, 5));
+        assert!(chunk.compress(10, true).compressed_densities.is_empty());
+        assert_eq!(chunk.get_density(Pos::new(3, 4, 5)), 255);
+        chunk.set_density(100, Pos::new(3, 4, 5));
+        // air stays empty whatever its density
+        chunk.set_density(100, Pos::new(3, 5, 5));
+        let compressed = chunk.compress(10, true);
+        assert!(compressed.compressed_densities.len() < CHUNK_STORAGE_SIZE / 10);
+        assert!(compressed.memory_usage() < chunk.memory_usage() / 10);
+        let decompressed = compressed.decompress(true);
+        assert_eq!(decompressed.densities, chunk.densities);
+        assert_eq!(decompressed.get_density(Pos::new(3, 4, 5)), 100);
+        assert_eq!(decompressed.get_density(Pos::new(3, 5, 5)), 0);
+    }
+
+    #[test]
+    fn column_tints() {
+        let mut chunk = Chunk::new(Pos::new(0, 0, 0), 0);
+        assert!(chunk.compress(10, true).compressed_column_tints.is_empty());
+        let extent = chunk.extent();
+        for z in extent.min.z..=extent.max.z {
+            for x in extent.min.x..=extent.max.x {
+                // tints change slowly like the ones of a generator
+                let green = 100 + (x / 16) as u8;
+                let tint = ColumnTint {
+                    grass: [50, green, 50],
+                    foliage: [20, green, 30],
+                };
+                chunk.set_column_tint(x, z, tint);
+            }
+        }
+        let compressed = chunk.compress(10, true);
+        let columns = (CHUNK_SIZE_WITH_PADDING * CHUNK_SIZE_WITH_PADDING) as usize;
+        assert!(compressed.compressed_column_tints.len() < columns * ColumnTint::BYTES / 10);
+        let decompressed = compressed.decompress(true);
+        assert_eq!(decompressed.column_tints, chunk.column_tints);
+        assert_eq!(decompressed.get_column_tint(40, -1).grass, [50, 102, 50]);
+    }
 }
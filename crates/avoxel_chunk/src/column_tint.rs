@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Colors tinted block textures are multiplied with, set per column by the generator
+/// so grass and leaves can change their hue by biome
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnTint {
+    pub grass: [u8; 3],
+    pub foliage: [u8; 3],
+}
+
+impl Default for ColumnTint {
+    fn default() -> Self {
+        Self {
+            grass: [255; 3],
+            foliage: [255; 3],
+        }
+    }
+}
+
+impl ColumnTint {
+    /// Size of a tint in the compressed chunk
+    pub(crate) const BYTES: usize = 6;
+
+    pub(crate) fn to_bytes(&self) -> [u8; Self::BYTES] {
+        let [gr, gg, gb] = self.grass;
+        let [fr, fg, fb] = self.foliage;
+        [gr, gg, gb, fr, fg, fb]
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            grass: [bytes[0], bytes[1], bytes[2]],
+            foliage: [bytes[3], bytes[4], bytes[5]],
+        }
+    }
+}
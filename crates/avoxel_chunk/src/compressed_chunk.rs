@@ -1,5 +1,6 @@
 use crate::{
     chunk::{Chunk, CHUNK_PADDING, CHUNK_SIZE, CHUNK_STORAGE_SIZE},
+    column_tint::ColumnTint,
     voxel::Voxel,
 };
 use avoxel_math::{Extent3, Pos};
@@ -13,6 +14,14 @@ pub struct Lz4CompressedChunk {
     pub pos: Pos,
     pub ambient_voxel: Voxel,
     pub empty: bool,
+    /// Version of the chunk when it was compressed
+    pub version: u64,
+    /// LZ4 compressed `ColumnTint` bytes, empty if the chunk has no tints
+    #[serde(with = "serde_bytes")]
+    pub compressed_column_tints: Vec<u8>,
+    /// LZ4 compressed densities, empty if the chunk has none
+    #[serde(with = "serde_bytes")]
+    pub compressed_densities: Vec<u8>,
 }
 
 impl Lz4CompressedChunk {
@@ -22,6 +31,39 @@ impl Lz4CompressedChunk {
             max: self.pos * CHUNK_SIZE + CHUNK_SIZE + CHUNK_PADDING - 1,
         }
     }
+
+    /// Bytes used by the compressed data
+    pub fn memory_usage(&self) -> usize {
+        self.compressed_voxels.len()
+            + self.compressed_column_tints.len()
+            + self.compressed_densities.len()
+    }
+}
+
+/// LZ4 compresses data stored next to the voxels, empty data stays empty
+pub(crate) fn compress_bytes(bytes: &[u8], compression_level: u32) -> Vec<u8> {
+    let mut compressed_bytes = Vec::new();
+    if bytes.is_empty() {
+        return compressed_bytes;
+    }
+    let mut encoder = lz4::EncoderBuilder::new()
+        .level(compression_level)
+        .build(&mut compressed_bytes)
+        .unwrap();
+    std::io::copy(&mut std::io::Cursor::new(bytes), &mut encoder).unwrap();
+    let (_output, _result) = encoder.finish();
+    compressed_bytes
+}
+
+/// Reverses `compress_bytes`
+pub(crate) fn decompress_bytes(compressed_bytes: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if compressed_bytes.is_empty() {
+        return bytes;
+    }
+    let mut decoder = lz4::Decoder::new(compressed_bytes).unwrap();
+    std::io::copy(&mut decoder, &mut bytes).unwrap();
+    bytes
 }
 
 impl Lz4CompressedChunk {
@@ -51,6 +93,13 @@ impl Lz4CompressedChunk {
             std::io::copy(&mut decoder, &mut decompressed_slice).unwrap();
         }
 
-        Chunk::new_from_vec(self.pos, self.ambient_voxel, decompressed_voxels)
+        let mut chunk = Chunk::new_from_vec(self.pos, self.ambient_voxel, decompressed_voxels);
+        chunk.version = self.version;
+        chunk.column_tints = decompress_bytes(&self.compressed_column_tints)
+            .chunks_exact(ColumnTint::BYTES)
+            .map(ColumnTint::from_bytes)
+            .collect();
+        chunk.densities = decompress_bytes(&self.compressed_densities);
+        chunk
     }
 }
@@ -1,7 +1,11 @@
 mod chunk;
+mod column_tint;
 mod compressed_chunk;
+mod lod_chunk;
 mod voxel;
 
 pub use chunk::*;
+pub use column_tint::ColumnTint;
 pub use compressed_chunk::*;
+pub use lod_chunk::*;
 pub use voxel::*;
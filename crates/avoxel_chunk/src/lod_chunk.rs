@@ -0,0 +1,388 @@
+use crate::{
+    chunk::{Chunk, CHUNK_SIZE},
+    column_tint::ColumnTint,
+    voxel::{Voxel, VoxelBits},
+};
+use avoxel_math::Pos;
+
+/// The coarsest level, each cell covers 8³ voxels
+pub const MAX_LOD_LEVEL: u32 = 3;
+
+const AIR: Voxel = 0;
+
+/// How the block of a cell is picked from the voxels it covers. In both cases a cell
+/// is solid if at least half of its voxels are solid cubes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LodVoting {
+    /// The most common block
+    Majority,
+    /// The highest block, so the surface of the terrain keeps its look from afar
+    TopSurface,
+}
+
+impl Default for LodVoting {
+    fn default() -> Self {
+        LodVoting::TopSurface
+    }
+}
+
+/// A chunk downsampled to a coarser grid for meshing distant chunks.
+/// At level `n` every cell covers `2ⁿ` voxels along each axis. Cells only
+/// keep the block id of their voxel, state bits are dropped.
+#[derive(Clone, Debug)]
+pub struct LodChunk {
+    /// World position divided by chunk size
+    pub pos: Pos,
+    pub level: u32,
+    /// The cells, indexed like the voxels of a chunk but without padding.
+    /// Empty if every cell is the ambient voxel.
+    pub voxels: Vec<Voxel>,
+    pub ambient_voxel: Voxel,
+    /// Tints of the cell columns, empty if the chunk didn't have any
+    pub column_tints: Vec<ColumnTint>,
+    /// The cells right outside of each side, sampled from the chunk padding
+    /// at the center of each cell face. See `get_neighbor_voxel`.
+    pub borders: [Vec<Voxel>; 6],
+}
+
+impl LodChunk {
+    /// Downsamples the chunk to a level between 1 and `MAX_LOD_LEVEL`.
+    /// Only blocks for which `is_solid` returns true are kept, other shapes
+    /// and fluids count as air since cells are drawn as cubes.
+    pub fn from_chunk(
+        chunk: &Chunk,
+        level: u32,
+        voting: LodVoting,
+        is_solid: impl Fn(u32) -> bool,
+    ) -> Self {
+        assert!((1..=MAX_LOD_LEVEL).contains(&level));
+        let origin = chunk.pos * CHUNK_SIZE;
+        let solid_id = |voxel: Voxel| {
+            let id = voxel.id();
+            if is_solid(id) {
+                id
+            } else {
+                AIR
+            }
+        };
+        let mut lod_chunk = Self::downsampled(
+            chunk.pos,
+            level,
+            1 << level,
+            solid_id(chunk.ambient_voxel),
+            chunk.is_empty(),
+            voting,
+            |local| solid_id(chunk.get_voxel(origin + local)),
+        );
+        let scale = lod_chunk.scale();
+        lod_chunk.borders = lod_chunk.sample_borders(|cell| {
+            // cells outside of the chunk map to the padding next to it
+            let to_voxel = |c: i32| {
+                if c < 0 {
+                    -1
+                } else if c >= CHUNK_SIZE / scale {
+                    CHUNK_SIZE
+                } else {
+                    c * scale + scale / 2
+                }
+            };
+            let local = Pos::new(to_voxel(cell.x), to_voxel(cell.y), to_voxel(cell.z));
+            solid_id(chunk.get_voxel(origin + local))
+        });
+        if !chunk.column_tints.is_empty() {
+            let scale = lod_chunk.scale();
+            lod_chunk.column_tints = lod_chunk
+                .columns()
+                .map(|(x, z)| {
+                    chunk.get_column_tint(
+                        origin.x + x * scale + scale / 2,
+                        origin.z + z * scale + scale / 2,
+                    )
+                })
+                .collect();
+        }
+        lod_chunk
+    }
+
+    /// Downsamples the chunk further to a coarser level
+    pub fn downsample(&self, level: u32, voting: LodVoting) -> Self {
+        assert!(level > self.level && level <= MAX_LOD_LEVEL);
+        let factor = 1 << (level - self.level);
+        let mut lod_chunk = Self::downsampled(
+            self.pos,
+            level,
+            factor,
+            self.ambient_voxel,
+            self.is_empty(),
+            voting,
+            |cell| self.get_voxel(cell),
+        );
+        let size = self.size();
+        lod_chunk.borders = lod_chunk.sample_borders(|cell| {
+            let to_finer = |c: i32| {
+                if c < 0 {
+                    -1
+                } else if c >= size / factor {
+                    size
+                } else {
+                    c * factor + factor / 2
+                }
+            };
+            self.get_neighbor_voxel(Pos::new(
+                to_finer(cell.x),
+                to_finer(cell.y),
+                to_finer(cell.z),
+            ))
+        });
+        if !self.column_tints.is_empty() {
+            lod_chunk.column_tints = lod_chunk
+                .columns()
+                .map(|(x, z)| {
+                    self.get_column_tint(x * factor + factor / 2, z * factor + factor / 2)
+                })
+                .collect();
+        }
+        lod_chunk
+    }
+
+    /// Cells along each axis
+    pub fn size(&self) -> i32 {
+        CHUNK_SIZE >> self.level
+    }
+
+    /// Voxels covered by a cell along each axis
+    pub fn scale(&self) -> i32 {
+        1 << self.level
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    pub fn index(&self, cell: Pos) -> usize {
+        let size = self.size();
+        (cell.y + cell.z * size + cell.x * size * size) as usize
+    }
+
+    /// The voxel of the cell at the local cell position, has to be within the chunk
+    pub fn get_voxel(&self, cell: Pos) -> Voxel {
+        if self.is_empty() {
+            return self.ambient_voxel;
+        }
+        self.voxels[self.index(cell)]
+    }
+
+    /// Like `get_voxel` but also takes the cells right outside of the chunk, next to a side.
+    /// Those belong to the neighboring chunks.
+    pub fn get_neighbor_voxel(&self, cell: Pos) -> Voxel {
+        match self.border_index(cell) {
+            Some((side, i)) => self.borders[side].get(i).copied().unwrap_or(AIR),
+            None => self.get_voxel(cell),
+        }
+    }
+
+    pub fn get_column_tint(&self, x: i32, z: i32) -> ColumnTint {
+        self.column_tints
+            .get((x + z * self.size()) as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Bytes used by the cells
+    pub fn memory_usage(&self) -> usize {
+        let border_cells: usize = self.borders.iter().map(|border| border.len()).sum();
+        (self.voxels.len() + border_cells) * std::mem::size_of::<Voxel>()
+            + self.column_tints.len() * std::mem::size_of::<ColumnTint>()
+    }
+
+    /// The side and index into `borders` of a cell outside of the chunk,
+    /// sides are ordered -x, +x, -y, +y, -z, +z
+    fn border_index(&self, cell: Pos) -> Option<(usize, usize)> {
+        let size = self.size();
+        let coords = [cell.x, cell.y, cell.z];
+        let axis = coords.iter().position(|c| *c < 0 || *c >= size)?;
+        let mut others = (0..3).filter(|a| *a != axis).map(|a| coords[a]);
+        let (u, v) = (others.next()?, others.next()?);
+        if !(0..size).contains(&u) || !(0..size).contains(&v) {
+            return None;
+        }
+        let side = axis * 2 + (coords[axis] >= 0) as usize;
+        Some((side, (u + v * size) as usize))
+    }
+
+    fn sample_borders<F: Fn(Pos) -> Voxel>(&self, sample: F) -> [Vec<Voxel>; 6] {
+        let size = self.size();
+        let mut borders: [Vec<Voxel>; 6] = Default::default();
+        for (side, border) in borders.iter_mut().enumerate() {
+            let axis = side / 2;
+            let c = if side % 2 == 0 { -1 } else { size };
+            for v in 0..size {
+                for u in 0..size {
+                    let cell = match axis {
+                        0 => Pos::new(c, u, v),
+                        1 => Pos::new(u, c, v),
+                        _ => Pos::new(u, v, c),
+                    };
+                    border.push(sample(cell));
+                }
+            }
+        }
+        borders
+    }
+
+    /// Cell columns in the order of `column_tints`
+    fn columns(&self) -> impl Iterator<Item = (i32, i32)> {
+        let size = self.size();
+        (0..size).flat_map(move |z| (0..size).map(move |x| (x, z)))
+    }
+
+    /// Builds the cells of `level` from a source grid where every cell
+    /// covers `factor` source cells along each axis
+    fn downsampled<F: Fn(Pos) -> Voxel>(
+        pos: Pos,
+        level: u32,
+        factor: i32,
+        ambient_voxel: Voxel,
+        empty: bool,
+        voting: LodVoting,
+        source: F,
+    ) -> Self {
+        let mut lod_chunk = Self {
+            pos,
+            level,
+            voxels: vec![],
+            ambient_voxel,
+            column_tints: vec![],
+            borders: Default::default(),
+        };
+        if empty {
+            return lod_chunk;
+        }
+        let size = lod_chunk.size();
+        let mut voxels = vec![AIR; (size * size * size) as usize];
+        let mut counts: Vec<(Voxel, u32)> = vec![];
+        for x in 0..size {
+            for z in 0..size {
+                for y in 0..size {
+                    counts.clear();
+                    let mut solid = 0;
+                    let mut top = AIR;
+                    // top down so the first solid voxel is the highest one
+                    for sy in (y * factor..(y + 1) * factor).rev() {
+                        for sx in x * factor..(x + 1) * factor {
+                            for sz in z * factor..(z + 1) * factor {
+                                let voxel = source(Pos::new(sx, sy, sz));
+                                if voxel == AIR {
+                                    continue;
+                                }
+                                solid += 1;
+                                if top == AIR {
+                                    top = voxel;
+                                }
+                                match counts.iter_mut().find(|(id, _)| *id == voxel) {
+                                    Some((_, count)) => *count += 1,
+                                    None => counts.push((voxel, 1)),
+                                }
+                            }
+                        }
+                    }
+                    if solid * 2 < factor * factor * factor {
+                        continue;
+                    }
+                    let i = lod_chunk.index(Pos::new(x, y, z));
+                    voxels[i] = match voting {
+                        LodVoting::TopSurface => top,
+                        LodVoting::Majority => {
+                            // the first of the most common blocks wins ties
+                            let mut best = (AIR, 0);
+                            for (voxel, count) in counts.iter() {
+                                if *count > best.1 {
+                                    best = (*voxel, *count);
+                                }
+                            }
+                            best.0
+                        }
+                    };
+                }
+            }
+        }
+        lod_chunk.voxels = voxels;
+        lod_chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk::{Chunk, CHUNK_SIZE},
+        lod_chunk::{LodChunk, LodVoting},
+    };
+    use avoxel_math::Pos;
+
+    /// Dirt up to y 10 and grass at y 11
+    fn terrain() -> Chunk {
+        let mut chunk = Chunk::new(Pos::new(1, 0, 0), 0);
+        chunk.fill_area(2, Pos::new(63, -1, -1), Pos::new(129, 11, 65));
+        chunk.fill_area(3, Pos::new(63, 11, -1), Pos::new(129, 12, 65));
+        chunk
+    }
+
+    /// Block 4 stands for a fluid or a block that isn't a cube
+    fn solid(id: u32) -> bool {
+        id != 0 && id != 4
+    }
+
+    #[test]
+    fn voting() {
+        let chunk = terrain();
+        let majority = LodChunk::from_chunk(&chunk, 2, LodVoting::Majority, solid);
+        assert_eq!(majority.size(), 16);
+        // the cell at y 8 to 11 is mostly dirt with grass on top
+        assert_eq!(majority.get_voxel(Pos::new(0, 2, 0)), 2);
+        assert_eq!(majority.get_voxel(Pos::new(5, 0, 7)), 2);
+        assert_eq!(majority.get_voxel(Pos::new(5, 3, 7)), 0);
+        let top_surface = LodChunk::from_chunk(&chunk, 2, LodVoting::TopSurface, solid);
+        assert_eq!(top_surface.get_voxel(Pos::new(0, 2, 0)), 3);
+        assert_eq!(top_surface.get_voxel(Pos::new(5, 0, 7)), 2);
+        // cells outside of the chunk come from the padding
+        assert_eq!(top_surface.get_neighbor_voxel(Pos::new(-1, 0, 5)), 2);
+        assert_eq!(top_surface.get_neighbor_voxel(Pos::new(16, 3, 5)), 0);
+
+        // coarser levels can be built from finer ones
+        let coarse = top_surface.downsample(3, LodVoting::TopSurface);
+        assert_eq!(coarse.size(), 8);
+        assert_eq!(coarse.get_voxel(Pos::new(3, 0, 3)), 2);
+        assert_eq!(coarse.get_voxel(Pos::new(3, 1, 3)), 3);
+        assert_eq!(coarse.get_voxel(Pos::new(3, 2, 3)), 0);
+    }
+
+    #[test]
+    fn only_solid_blocks_vote() {
+        // water from y 12 to 15 and a cell that is half dirt, half water
+        let mut chunk = terrain();
+        chunk.fill_area(4, Pos::new(63, 12, -1), Pos::new(129, 16, 65));
+        chunk.fill_area(4, Pos::new(68, 0, 0), Pos::new(70, 4, 4));
+        let lod_chunk = LodChunk::from_chunk(&chunk, 2, LodVoting::Majority, solid);
+        assert_eq!(lod_chunk.get_voxel(Pos::new(3, 3, 3)), 0);
+        assert_eq!(lod_chunk.get_voxel(Pos::new(1, 0, 0)), 2);
+        assert_eq!(lod_chunk.get_neighbor_voxel(Pos::new(-1, 3, 5)), 0);
+        let top_surface = LodChunk::from_chunk(&chunk, 2, LodVoting::TopSurface, solid);
+        assert_eq!(top_surface.get_voxel(Pos::new(3, 2, 3)), 3);
+        assert_eq!(top_surface.get_voxel(Pos::new(3, 3, 3)), 0);
+
+        // chunks filled with a non solid ambient voxel are air
+        let water = Chunk::new(Pos::new(0, 0, 0), 4);
+        let lod_chunk = LodChunk::from_chunk(&water, 1, LodVoting::Majority, solid);
+        assert!(lod_chunk.is_empty());
+        assert_eq!(lod_chunk.ambient_voxel, 0);
+    }
+
+    #[test]
+    fn empty_chunks_stay_empty() {
+        let chunk = Chunk::new(Pos::new(0, 0, 0), 5);
+        let lod_chunk = LodChunk::from_chunk(&chunk, 1, LodVoting::Majority, solid);
+        assert!(lod_chunk.is_empty());
+        assert_eq!(lod_chunk.get_voxel(Pos::new(31, 31, 31)), 5);
+        assert_eq!(CHUNK_SIZE / lod_chunk.scale(), lod_chunk.size());
+    }
+}
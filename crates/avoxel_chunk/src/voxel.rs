@@ -1 +1,157 @@
 pub type Voxel = u32;
+
+// Voxel layout
+//
+// bits  0-15  block id, the index into the `BlockLibrary`
+// bits 16-19  level, used for fluid levels and fill levels
+// bits 20-22  facing
+// bits 23-24  axis
+// bit  25     open
+// bits 26-31  unused
+//
+// A state of 0 is the default state of a block so voxels written before
+// state bits existed keep their meaning.
+
+pub const VOXEL_ID_BITS: u32 = 16;
+pub const VOXEL_ID_MASK: Voxel = (1 << VOXEL_ID_BITS) - 1;
+
+const LEVEL_SHIFT: u32 = 16;
+const LEVEL_MASK: Voxel = 0xF << LEVEL_SHIFT;
+const FACING_SHIFT: u32 = 20;
+const FACING_MASK: Voxel = 0x7 << FACING_SHIFT;
+const AXIS_SHIFT: u32 = 23;
+const AXIS_MASK: Voxel = 0x3 << AXIS_SHIFT;
+const OPEN_BIT: Voxel = 1 << 25;
+
+/// Level of a fluid source, higher levels are further away from the source
+pub const FLUID_SOURCE: u32 = 0;
+/// Set in the level of fluids flowing down, these are drawn at full height
+pub const FLUID_FALLING: u32 = 8;
+
+/// The direction the front of a block faces
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facing {
+    /// Positive z, the default
+    South,
+    /// Negative x
+    West,
+    /// Negative z
+    North,
+    /// Positive x
+    East,
+    Up,
+    Down,
+}
+
+impl Facing {
+    pub const ALL: [Facing; 6] = [
+        Facing::South,
+        Facing::West,
+        Facing::North,
+        Facing::East,
+        Facing::Up,
+        Facing::Down,
+    ];
+}
+
+/// The axis a block like a log is aligned to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    /// The default
+    Y,
+    X,
+    Z,
+}
+
+/// Typed access to the state bits of a `Voxel`
+pub trait VoxelBits: Sized {
+    /// The block id without state bits
+    fn id(self) -> u32;
+    /// The state bits shifted down
+    fn state(self) -> u32;
+    /// The level of a fluid, `FLUID_SOURCE` for sources
+    fn level(self) -> u32;
+    fn with_level(self, level: u32) -> Self;
+    fn facing(self) -> Facing;
+    fn with_facing(self, facing: Facing) -> Self;
+    fn axis(self) -> Axis;
+    fn with_axis(self, axis: Axis) -> Self;
+    fn is_open(self) -> bool;
+    fn with_open(self, open: bool) -> Self;
+}
+
+impl VoxelBits for Voxel {
+    fn id(self) -> u32 {
+        self & VOXEL_ID_MASK
+    }
+
+    fn state(self) -> u32 {
+        self >> VOXEL_ID_BITS
+    }
+
+    fn level(self) -> u32 {
+        (self & LEVEL_MASK) >> LEVEL_SHIFT
+    }
+
+    fn with_level(self, level: u32) -> Self {
+        (self & !LEVEL_MASK) | ((level << LEVEL_SHIFT) & LEVEL_MASK)
+    }
+
+    fn facing(self) -> Facing {
+        match (self & FACING_MASK) >> FACING_SHIFT {
+            1 => Facing::West,
+            2 => Facing::North,
+            3 => Facing::East,
+            4 => Facing::Up,
+            5 => Facing::Down,
+            _ => Facing::South,
+        }
+    }
+
+    fn with_facing(self, facing: Facing) -> Self {
+        let bits = match facing {
+            Facing::South => 0,
+            Facing::West => 1,
+            Facing::North => 2,
+            Facing::East => 3,
+            Facing::Up => 4,
+            Facing::Down => 5,
+        };
+        (self & !FACING_MASK) | (bits << FACING_SHIFT)
+    }
+
+    fn axis(self) -> Axis {
+        match (self & AXIS_MASK) >> AXIS_SHIFT {
+            1 => Axis::X,
+            2 => Axis::Z,
+            _ => Axis::Y,
+        }
+    }
+
+    fn with_axis(self, axis: Axis) -> Self {
+        let bits = match axis {
+            Axis::Y => 0,
+            Axis::X => 1,
+            Axis::Z => 2,
+        };
+        (self & !AXIS_MASK) | (bits << AXIS_SHIFT)
+    }
+
+    fn is_open(self) -> bool {
+        self & OPEN_BIT != 0
+    }
+
+    fn with_open(self, open: bool) -> Self {
+        if open {
+            self | OPEN_BIT
+        } else {
+            self & !OPEN_BIT
+        }
+    }
+}
+
+/// Builds a voxel from a block id and state bits, the inverse of `VoxelBits::id`
+/// and `VoxelBits::state`
+pub fn voxel_from_parts(id: u32, state: u32) -> Voxel {
+    (id & VOXEL_ID_MASK) | (state << VOXEL_ID_BITS)
+}
@@ -0,0 +1,208 @@
+use avoxel_chunk::CHUNK_SIZE;
+use avoxel_math::{DivFloor, Pos};
+use bevy_utils::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A scheduled tick that hasn't run yet, stored with the chunk it's in
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PendingTick {
+    pub pos: Pos,
+    /// Ticks left until the tick is due
+    pub delay: u64,
+    pub priority: i32,
+}
+
+/// Keeps track of scheduled block ticks and picks random ticks
+pub struct TickScheduler {
+    current_tick: u64,
+    /// Keyed by due tick, priority and the order ticks were scheduled in
+    queue: BTreeMap<(u64, i32, u64), Pos>,
+    scheduled: HashSet<Pos>,
+    next_order: u64,
+    /// Number of random positions per loaded chunk that get a random tick every tick
+    pub(crate) random_ticks_per_chunk: u32,
+    rng_state: u64,
+}
+
+impl Default for TickScheduler {
+    fn default() -> Self {
+        Self {
+            current_tick: 0,
+            queue: Default::default(),
+            scheduled: Default::default(),
+            next_order: 0,
+            random_ticks_per_chunk: 3,
+            rng_state: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+
+impl TickScheduler {
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    pub fn set_random_ticks_per_chunk(&mut self, random_ticks_per_chunk: u32) {
+        self.random_ticks_per_chunk = random_ticks_per_chunk;
+    }
+
+    /// Schedules a tick for `pos` after `delay` ticks. Ticks due on the same tick run
+    /// in order of priority, lowest first. Returns false if a tick is already scheduled.
+    pub fn schedule(&mut self, pos: Pos, delay: u64, priority: i32) -> bool {
+        if !self.scheduled.insert(pos) {
+            return false;
+        }
+        // a tick can't run on the tick it was scheduled in
+        let due = self.current_tick + delay.max(1);
+        self.queue.insert((due, priority, self.next_order), pos);
+        self.next_order += 1;
+        true
+    }
+
+    pub fn is_scheduled(&self, pos: &Pos) -> bool {
+        self.scheduled.contains(pos)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Advances to the next tick and returns the positions of the ticks that are due
+    pub(crate) fn advance(&mut self) -> Vec<Pos> {
+        self.current_tick += 1;
+        let mut due = vec![];
+        while let Some((&key, _)) = self.queue.iter().next() {
+            if key.0 > self.current_tick {
+                break;
+            }
+            if let Some(pos) = self.queue.remove(&key) {
+                self.scheduled.remove(&pos);
+                due.push(pos);
+            }
+        }
+        due
+    }
+
+    /// Returns the pending ticks within a chunk with delays relative to the current tick
+    pub(crate) fn chunk_ticks(&self, chunk_key: &Pos) -> Vec<PendingTick> {
+        self.queue
+            .iter()
+            .filter(|(_, pos)| pos.div_floor(CHUNK_SIZE) == *chunk_key)
+            .map(|(&(due, priority, _), &pos)| PendingTick {
+                pos,
+                delay: due.saturating_sub(self.current_tick),
+                priority,
+            })
+            .collect()
+    }
+
+    /// Removes the pending ticks within a chunk, used when the chunk is unloaded
+    pub(crate) fn remove_chunk_ticks(&mut self, chunk_key: &Pos) {
+        let scheduled = &mut self.scheduled;
+        self.queue.retain(|_, pos| {
+            let in_chunk = pos.div_floor(CHUNK_SIZE) == *chunk_key;
+            if in_chunk {
+                scheduled.remove(pos);
+            }
+            !in_chunk
+        });
+    }
+
+    /// Schedules ticks that were stored with a chunk
+    pub(crate) fn restore(&mut self, pending_ticks: &[PendingTick]) {
+        for tick in pending_ticks {
+            self.schedule(tick.pos, tick.delay, tick.priority);
+        }
+    }
+
+    /// Random local position within a chunk
+    pub(crate) fn random_local_pos(&mut self) -> Pos {
+        // xorshift64*
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let r = (self.rng_state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as i32;
+        Pos::new(
+            r & (CHUNK_SIZE - 1),
+            (r >> 6) & (CHUNK_SIZE - 1),
+            (r >> 12) & (CHUNK_SIZE - 1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block_ticks::TickScheduler;
+    use avoxel_math::Pos;
+
+    #[test]
+    fn ticks_run_by_due_tick_then_priority() {
+        let mut tick_scheduler = TickScheduler::default();
+        let (a, b, c, d) = (
+            Pos::new(0, 0, 0),
+            Pos::new(1, 0, 0),
+            Pos::new(2, 0, 0),
+            Pos::new(3, 0, 0),
+        );
+        assert!(tick_scheduler.schedule(a, 2, 0));
+        assert!(tick_scheduler.schedule(b, 1, 5));
+        assert!(tick_scheduler.schedule(c, 2, -1));
+        // a delay of 0 still waits for the next tick
+        assert!(tick_scheduler.schedule(d, 0, 0));
+        // a position is only scheduled once
+        assert!(!tick_scheduler.schedule(a, 1, -10));
+        assert!(tick_scheduler.is_scheduled(&a));
+        assert_eq!(tick_scheduler.pending_count(), 4);
+
+        assert_eq!(tick_scheduler.advance(), vec![d, b]);
+        assert_eq!(tick_scheduler.advance(), vec![c, a]);
+        assert!(tick_scheduler.advance().is_empty());
+        assert!(!tick_scheduler.is_scheduled(&a));
+        assert_eq!(tick_scheduler.current_tick(), 3);
+        // positions can be scheduled again once their tick ran
+        assert!(tick_scheduler.schedule(a, 1, 0));
+    }
+
+    #[test]
+    fn same_priority_runs_in_scheduled_order() {
+        let mut tick_scheduler = TickScheduler::default();
+        let positions: Vec<Pos> = (0..5).map(|i| Pos::new(0, i, 0)).rev().collect();
+        for pos in &positions {
+            tick_scheduler.schedule(*pos, 1, 0);
+        }
+        assert_eq!(tick_scheduler.advance(), positions);
+    }
+
+    #[test]
+    fn pending_ticks_persist_with_their_chunk() {
+        let mut tick_scheduler = TickScheduler::default();
+        let inside = Pos::new(5, 6, 7);
+        let outside = Pos::new(64, 6, 7);
+        tick_scheduler.schedule(inside, 5, 2);
+        tick_scheduler.schedule(outside, 3, 0);
+        tick_scheduler.advance();
+
+        let chunk_key = Pos::new(0, 0, 0);
+        let pending_ticks = tick_scheduler.chunk_ticks(&chunk_key);
+        assert_eq!(pending_ticks.len(), 1);
+        assert_eq!(pending_ticks[0].pos, inside);
+        // delays are relative to the tick the chunk was saved on
+        assert_eq!(pending_ticks[0].delay, 4);
+        assert_eq!(pending_ticks[0].priority, 2);
+
+        tick_scheduler.remove_chunk_ticks(&chunk_key);
+        assert!(!tick_scheduler.is_scheduled(&inside));
+        assert!(tick_scheduler.is_scheduled(&outside));
+
+        // the chunk is loaded again two ticks later
+        tick_scheduler.advance();
+        tick_scheduler.advance();
+        tick_scheduler.restore(&pending_ticks);
+        assert!(tick_scheduler.is_scheduled(&inside));
+        for _ in 0..3 {
+            assert!(!tick_scheduler.advance().contains(&inside));
+        }
+        assert_eq!(tick_scheduler.advance(), vec![inside]);
+    }
+}
@@ -1,12 +1,23 @@
-use avoxel_chunk::{Chunk, Lz4CompressedChunk};
+use crate::{block_ticks::PendingTick, task_token::TaskToken};
+use avoxel_chunk::{Chunk, LodChunk, Lz4CompressedChunk};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use parking_lot::Mutex;
 use std::{sync::Arc, time::Instant};
 
+/// A chunk, whether it came from the generator, the pending ticks and unknown
+/// blocks of chunks loaded from storage and Instant for timing purposes
+type LoadedChunk = (
+    Chunk,
+    bool,
+    Vec<PendingTick>,
+    Vec<String>,
+    TaskToken,
+    Instant,
+);
+
 pub struct ChunkGenChannels {
-    /// Sending Instant for timing purposes
-    pub(crate) tx: Sender<(Chunk, Instant)>,
-    pub(crate) rx: Receiver<(Chunk, Instant)>,
+    pub(crate) tx: Sender<LoadedChunk>,
+    pub(crate) rx: Receiver<LoadedChunk>,
 }
 
 impl Default for ChunkGenChannels {
@@ -16,6 +27,20 @@ impl Default for ChunkGenChannels {
     }
 }
 
+/// Downsampled chunks that are only meshed at a coarser level
+pub struct LodChannels {
+    /// Sending Instant for timing purposes
+    pub(crate) tx: Sender<(LodChunk, TaskToken, Instant)>,
+    pub(crate) rx: Receiver<(LodChunk, TaskToken, Instant)>,
+}
+
+impl Default for LodChannels {
+    fn default() -> Self {
+        let (tx, rx) = unbounded();
+        Self { tx, rx }
+    }
+}
+
 pub struct DecompressionChannels {
     pub(crate) tx: Sender<Arc<Mutex<Chunk>>>,
     pub(crate) rx: Receiver<Arc<Mutex<Chunk>>>,
@@ -30,8 +55,8 @@ impl Default for DecompressionChannels {
 
 pub struct CompressionChannels {
     /// Sending Instant for timing purposes
-    pub(crate) tx: Sender<(Lz4CompressedChunk, Instant)>,
-    pub(crate) rx: Receiver<(Lz4CompressedChunk, Instant)>,
+    pub(crate) tx: Sender<(Lz4CompressedChunk, TaskToken, Instant)>,
+    pub(crate) rx: Receiver<(Lz4CompressedChunk, TaskToken, Instant)>,
 }
 
 impl Default for CompressionChannels {
@@ -1,20 +1,33 @@
 use crate::{
-    channels::{ChunkGenChannels, CompressionChannels, DecompressionChannels},
+    block_ticks::TickScheduler,
+    channels::{ChunkGenChannels, CompressionChannels, DecompressionChannels, LodChannels},
+    fluids::FluidUpdates,
+    lod::LodSettings,
+    storage::{ChunkStorage, StoredChunk},
+    task_token::TaskToken,
     tools,
     tools::VoxelRayCastResult,
 };
-use avoxel_blocks::{Block, BlockLibrary};
-use avoxel_chunk::{Chunk, Lz4CompressedChunk, Voxel, CHUNK_SIZE};
+use avoxel_blocks::{Block, BlockLibrary, BlockPalette, BlockTickContext};
+use avoxel_chunk::{Chunk, LodChunk, Lz4CompressedChunk, Voxel, CHUNK_SIZE, CHUNK_STORAGE_SIZE};
 use avoxel_generator::default_generator;
 use avoxel_math::{DivFloor, Pos};
-use bevy::{
-    prelude::*,
-    tasks::AsyncComputeTaskPool,
-    utils::{HashMap, HashSet},
-};
-use indexmap::set::IndexSet;
+use bevy_log::warn;
+use bevy_math::Vec3;
+use bevy_tasks::AsyncComputeTaskPool;
+use bevy_utils::{HashMap, HashSet};
 use parking_lot::Mutex;
-use std::{collections::hash_map::Keys, iter::Chain, sync::Arc, time::Instant};
+use std::{
+    collections::hash_map::Keys,
+    io,
+    iter::Chain,
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 pub struct ChunkMap {
     /// The storage for `Chunks`. A chunk doesn't need to be accessed by more
@@ -26,19 +39,86 @@ pub struct ChunkMap {
     pub(crate) compression_level: u32,
     /// Whether to use LittleEndian byteorder when compressing voxels or native byteorder
     pub(crate) compress_byteorder: bool,
-    /// Visible chunks contains coordinates for chunks that should be loaded
+    /// Visible chunks contains coordinates for chunks within render distance of a viewer.
+    /// These are the chunks that get meshed.
     pub visible_chunks: HashSet<Pos>,
+    /// Simulated chunks contains coordinates for chunks that should be loaded.
+    /// Contains the visible chunks except for the ones that are only meshed at a coarser level.
+    pub simulated_chunks: HashSet<Pos>,
     /// Dirty chunks are chunks that need to be re-meshed
     pub dirty_chunks: HashSet<Pos>,
+    /// Level of detail, `None` meshes every visible chunk at full resolution
+    pub(crate) lod: Option<LodSettings>,
+    /// Set when the levels need to be picked again without a viewer moving
+    pub(crate) lod_dirty: bool,
+    /// Levels of the visible chunks that are meshed at a coarser level
+    pub lod_levels: HashMap<Pos, u32>,
+    /// Visible chunks outside of the simulate distance that are meshed at a coarser level.
+    /// Only their downsampled voxels are loaded.
+    pub lod_only_chunks: HashSet<Pos>,
+    /// The downsampled voxels of the lod only chunks
+    pub lod_chunks: HashMap<Pos, LodChunk>,
+    /// Whether a mesher consumes the dirty chunks. Without one, e.g. on a
+    /// headless server, chunks are never marked dirty and get compressed right away.
+    pub(crate) meshing: bool,
+    /// Whether missing chunks are generated or loaded from the storage. Replication
+    /// clients receive their chunks from a server instead.
+    pub(crate) generating: bool,
+    /// Whether block ticks and fluids run. Replication clients get the
+    /// changes they make from the server instead.
+    pub(crate) simulating: bool,
+    /// Edits made with `set_voxel` since the last `take_voxel_edits`, only recorded
+    /// when enabled so they can be replicated
+    pub(crate) record_edits: bool,
+    voxel_edits: Vec<(Pos, Voxel)>,
     /// Chunks that are currently being generated or loaded in other threads
-    /// Needed so we don't load the same chunk twice
-    loading_chunks: IndexSet<Pos>,
+    /// Needed so we don't load the same chunk twice.
+    /// The token is used to cancel the task when the chunk leaves the view.
+    loading_chunks: HashMap<Pos, TaskToken>,
+    /// Chunks that are currently being compressed in other threads
+    compressing_chunks: HashMap<Pos, TaskToken>,
+    /// Chunks that were edited without getting meshed, compressed again on the next frame
+    pub(crate) compression_queue: HashSet<Pos>,
+    /// Unloaded simulated chunks sorted by distance to the nearest viewer, closest last
+    pub(crate) load_queue: Vec<Pos>,
+    /// Set when the load queue needs to be rebuilt, e.g. when a viewer moved
+    pub(crate) load_queue_dirty: bool,
+    /// Max number of generation tasks spawned per frame
+    pub(crate) gen_tasks_per_frame: usize,
+    /// Chunk positions of all viewers, used to prioritize loading and meshing
+    pub(crate) viewer_positions: Vec<Pos>,
+    /// Chunks that were edited since they were loaded.
+    /// These get saved to the storage before they are unloaded.
+    pub modified_chunks: HashSet<Pos>,
+    /// Loaded chunks whose voxels, padding included, are still the ones the generator
+    /// made. The padding between two of them already matches so it isn't synced.
+    pub(crate) generated_chunks: HashSet<Pos>,
+    /// Where modified chunks are saved and loaded from
+    pub(crate) storage: Option<ChunkStorage>,
+    /// Names of the blocks that weren't in the block library when a chunk was loaded
+    /// from the storage, see `BlockPalette::unknown`
+    pub(crate) unknown_blocks: HashMap<Pos, Vec<String>>,
+    /// Max number of bytes used by `chunks` and `compressed_chunks` combined
+    pub(crate) memory_budget: Option<usize>,
+    /// Last access of every chunk, the least recently used chunks are evicted first.
+    /// Entries are added when a chunk is inserted so reads don't need a lock.
+    access_times: HashMap<Pos, AtomicU64>,
+    access_clock: AtomicU64,
+    /// Scheduled block ticks of the loaded chunks
+    pub tick_scheduler: TickScheduler,
+    /// Fluids waiting to flow or retract
+    pub fluid_updates: FluidUpdates,
     /// Channels to send and receive generated chunks
     pub(crate) gen_channels: ChunkGenChannels,
+    pub(crate) lod_channels: LodChannels,
     pub(crate) compression_channels: CompressionChannels,
     pub(crate) decompression_channels: DecompressionChannels,
     /// Block Library used by mesher for meshing and texturing
     pub block_library: Arc<BlockLibrary>,
+    /// Whether `block_library` follows the `BlockLibrary` resource
+    pub(crate) shared_block_library: bool,
+    /// Makes the chunk at a position. The padding has to hold the voxels the generator
+    /// gives the neighbors there since it isn't synced between generated chunks.
     pub generator: &'static (dyn Fn(&Pos) -> Chunk + Send + Sync + 'static),
 }
 
@@ -50,17 +130,59 @@ impl Default for ChunkMap {
             compression_level: 10,
             compress_byteorder: false,
             visible_chunks: Default::default(),
+            simulated_chunks: Default::default(),
             dirty_chunks: Default::default(),
+            lod: None,
+            lod_dirty: false,
+            lod_levels: Default::default(),
+            lod_only_chunks: Default::default(),
+            lod_chunks: Default::default(),
+            meshing: false,
+            generating: true,
+            simulating: true,
+            record_edits: false,
+            voxel_edits: Default::default(),
             loading_chunks: Default::default(),
+            compressing_chunks: Default::default(),
+            compression_queue: Default::default(),
+            load_queue: Default::default(),
+            load_queue_dirty: true,
+            gen_tasks_per_frame: 16,
+            viewer_positions: Default::default(),
+            modified_chunks: Default::default(),
+            generated_chunks: Default::default(),
+            storage: None,
+            unknown_blocks: Default::default(),
+            memory_budget: None,
+            access_times: Default::default(),
+            access_clock: Default::default(),
+            tick_scheduler: Default::default(),
+            fluid_updates: Default::default(),
             gen_channels: Default::default(),
+            lod_channels: Default::default(),
             compression_channels: Default::default(),
             decompression_channels: Default::default(),
-            block_library: Arc::new(Default::default()),
+            block_library: with_placeholder(Arc::new(Default::default())),
+            shared_block_library: true,
             generator: &default_generator::generate_chunk,
         }
     }
 }
 
+/// Bytes used by the chunks of a `ChunkMap`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkMemoryUsage {
+    pub chunk_bytes: usize,
+    pub compressed_bytes: usize,
+    pub lod_bytes: usize,
+}
+
+impl ChunkMemoryUsage {
+    pub fn total(&self) -> usize {
+        self.chunk_bytes + self.compressed_bytes + self.lod_bytes
+    }
+}
+
 pub enum ChunkState {
     Unloaded,
     /// Can be either loading or generating
@@ -93,18 +215,141 @@ impl ChunkMap {
         self.compress_byteorder
     }
 
+    pub fn set_meshing(&mut self, meshing: bool) {
+        self.meshing = meshing;
+    }
+
+    pub fn is_meshing(&self) -> bool {
+        self.meshing
+    }
+
+    /// Gives the chunk map its own block library instead of the `BlockLibrary` resource.
+    /// The `core:unknown` placeholder is added to the library if it doesn't have one.
+    pub fn set_block_library(&mut self, block_library: Arc<BlockLibrary>) {
+        self.block_library = with_placeholder(block_library);
+        self.shared_block_library = false;
+        if self.meshing {
+            self.dirty_chunks = self.visible_chunks.clone();
+        }
+    }
+
+    /// Meshes distant chunks at coarser levels. Takes effect on the next frame.
+    pub fn set_lod(&mut self, lod: Option<LodSettings>) {
+        self.lod = lod;
+        self.lod_dirty = true;
+    }
+
+    pub fn get_lod(&self) -> Option<&LodSettings> {
+        self.lod.as_ref()
+    }
+
+    /// The level the chunk is meshed at, 0 is full resolution
+    pub fn lod_level(&self, pos: &Pos) -> u32 {
+        self.lod_levels.get(pos).copied().unwrap_or(0)
+    }
+
+    pub fn set_generating(&mut self, generating: bool) {
+        self.generating = generating;
+    }
+
+    pub fn is_generating(&self) -> bool {
+        self.generating
+    }
+
+    pub fn set_simulating(&mut self, simulating: bool) {
+        self.simulating = simulating;
+    }
+
+    pub fn is_simulating(&self) -> bool {
+        self.simulating
+    }
+
+    pub fn set_record_edits(&mut self, record_edits: bool) {
+        self.record_edits = record_edits;
+        if !record_edits {
+            self.voxel_edits.clear();
+        }
+    }
+
+    /// Returns the recorded edits in the order they were made
+    pub fn take_voxel_edits(&mut self) -> Vec<(Pos, Voxel)> {
+        std::mem::take(&mut self.voxel_edits)
+    }
+
+    pub fn set_gen_tasks_per_frame(&mut self, gen_tasks_per_frame: usize) {
+        self.gen_tasks_per_frame = gen_tasks_per_frame;
+    }
+
+    /// Modified chunks are saved to the storage when they're unloaded and
+    /// chunks in the storage are loaded instead of generated.
+    pub fn set_storage(&mut self, storage: Option<ChunkStorage>) {
+        self.storage = storage;
+    }
+
+    pub fn get_storage(&self) -> Option<&ChunkStorage> {
+        self.storage.as_ref()
+    }
+
+    /// Once the chunks use more than `memory_budget` bytes the least recently
+    /// used chunks are unloaded. Set to `None` for no limit.
+    pub fn set_memory_budget(&mut self, memory_budget: Option<usize>) {
+        self.memory_budget = memory_budget;
+    }
+
+    pub fn get_memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Squared distance in chunks to the nearest viewer
+    pub fn viewer_distance_squared(&self, pos: &Pos) -> i32 {
+        self.viewer_positions
+            .iter()
+            .map(|viewer_pos| {
+                let d = *pos - *viewer_pos;
+                d.x * d.x + d.y * d.y + d.z * d.z
+            })
+            .min()
+            .unwrap_or(i32::MAX)
+    }
+
+    /// Sorts positions so the ones closest to a viewer come first
+    pub fn sort_by_viewer_distance(&self, positions: &mut [Pos]) {
+        positions.sort_by_cached_key(|pos| self.viewer_distance_squared(pos));
+    }
+
+    /// Rebuilds the load queue from the simulated chunks that aren't loaded yet
+    pub(crate) fn rebuild_load_queue(&mut self) {
+        let mut load_queue: Vec<Pos> = self
+            .simulated_chunks
+            .iter()
+            .chain(self.lod_only_chunks.iter())
+            .filter(|pos| matches!(self.chunk_state(pos), ChunkState::Unloaded))
+            .copied()
+            .collect();
+        self.sort_by_viewer_distance(&mut load_queue);
+        // closest last so we can pop from the queue
+        load_queue.reverse();
+        self.load_queue = load_queue;
+        self.load_queue_dirty = false;
+    }
+
     pub fn contains_chunk(&self, pos: &Pos) -> bool {
         self.chunks.contains_key(pos) || self.compressed_chunks.contains_key(pos)
     }
 
+    /// True if the chunk's voxels or downsampled voxels are loaded
+    pub fn is_meshable(&self, pos: &Pos) -> bool {
+        self.contains_chunk(pos) || self.lod_chunks.contains_key(pos)
+    }
+
     pub fn chunk_keys(&self) -> ChunkKeys {
         self.chunks.keys().chain(self.compressed_chunks.keys())
     }
 
     pub(crate) fn chunk_state(&self, pos: &Pos) -> ChunkState {
-        if self.contains_chunk(pos) {
+        if self.is_meshable(pos) {
             ChunkState::Loaded
-        } else if self.loading_chunks.contains(pos) {
+        } else if self.loading_chunks.contains_key(pos) {
             ChunkState::Loading
         } else if self.dirty_chunks.contains(pos) {
             ChunkState::Dirty
@@ -129,26 +374,26 @@ impl ChunkMap {
     /// Multiple chunks can contain the same position due to padding so if you plan
     /// on modify the chunks make sure you also modify the neighboring chunks if any
     fn get_mut_chunks_containing_pos(&mut self, pos: &Pos) -> Vec<Arc<Mutex<Chunk>>> {
-        let mut chunk_keys = vec![];
         let chunk_key = pos.div_floor(CHUNK_SIZE);
-        chunk_keys.push(chunk_key);
-        let x_rem = pos.x % CHUNK_SIZE;
-        if x_rem == 0 {
-            chunk_keys.push(chunk_key + Pos::new(-1, 0, 0));
-        } else if x_rem == 63 || x_rem == -1 {
-            chunk_keys.push(chunk_key + Pos::new(1, 0, 0));
-        }
-        let y_rem = pos.y % CHUNK_SIZE;
-        if y_rem == 0 {
-            chunk_keys.push(chunk_key + Pos::new(0, -1, 0));
-        } else if y_rem == 63 || y_rem == -1 {
-            chunk_keys.push(chunk_key + Pos::new(0, 1, 0));
-        }
-        let z_rem = pos.z % CHUNK_SIZE;
-        if z_rem == 0 {
-            chunk_keys.push(chunk_key + Pos::new(0, 0, -1));
-        } else if z_rem == 63 || z_rem == -1 {
-            chunk_keys.push(chunk_key + Pos::new(0, 0, 1));
+        let local = *pos - chunk_key * CHUNK_SIZE;
+        // voxels on a border are in the padding of the chunks next to it,
+        // including the ones across edges and corners
+        let offsets = |c: i32| {
+            if c == 0 {
+                -1..=0
+            } else if c == CHUNK_SIZE - 1 {
+                0..=1
+            } else {
+                0..=0
+            }
+        };
+        let mut chunk_keys = vec![];
+        for x in offsets(local.x) {
+            for y in offsets(local.y) {
+                for z in offsets(local.z) {
+                    chunk_keys.push(chunk_key + Pos::new(x, y, z));
+                }
+            }
         }
         let mut chunks = vec![];
         for chunk_key in &chunk_keys {
@@ -156,14 +401,17 @@ impl ChunkMap {
                 chunks.push(chunk.clone());
             } else if let Some(chunk) = self.decompress_chunk(&chunk_key) {
                 chunks.push(chunk.clone());
+            } else {
+                continue;
             };
+            self.touch(chunk_key);
         }
         chunks
     }
 
     /// It's best to not mutate the chunk if using the method since if the chunk was compressed
     /// it will be sent via a channel and if the send fails the mutation won't be applied.
-    fn get_chunk_containing_pos(&self, pos: &Pos) -> Option<Arc<Mutex<Chunk>>> {
+    pub(crate) fn get_chunk_containing_pos(&self, pos: &Pos) -> Option<Arc<Mutex<Chunk>>> {
         let chunk_key = pos.div_floor(CHUNK_SIZE);
         if let Some(chunk) = self.chunks.get(&chunk_key) {
             return Some(chunk.clone());
@@ -183,7 +431,7 @@ impl ChunkMap {
             match self.decompression_channels.tx.send(chunk) {
                 Ok(_) => {}
                 Err(e) => {
-                    bevy::log::warn!(
+                    warn!(
                         "failed to send chunk over decompression channel: {:#?}",
                         e.0.lock().pos
                     )
@@ -205,7 +453,7 @@ impl ChunkMap {
             match self.decompression_channels.tx.send(chunk.clone()) {
                 Ok(_) => {}
                 Err(e) => {
-                    bevy::log::warn!(
+                    warn!(
                         "failed to send chunk over decompression channel: {:#?}",
                         e.0.lock().pos
                     )
@@ -217,9 +465,29 @@ impl ChunkMap {
         };
     }
 
+    /// Calls `f` with the chunk containing `pos`. Compressed chunks are read from a
+    /// temporary decompressed copy so reading doesn't keep them decompressed.
+    pub(crate) fn read_chunk_containing_pos<R>(
+        &self,
+        pos: &Pos,
+        f: impl FnOnce(&Chunk) -> R,
+    ) -> Option<R> {
+        let chunk_key = pos.div_floor(CHUNK_SIZE);
+        let result = if let Some(chunk) = self.chunks.get(&chunk_key) {
+            f(&chunk.lock())
+        } else {
+            let compressed_chunk = self.compressed_chunks.get(&chunk_key)?;
+            f(&compressed_chunk.decompress(self.compress_byteorder))
+        };
+        self.touch(&chunk_key);
+        Some(result)
+    }
+
     pub fn get_voxel(&self, pos: &Pos) -> Option<Voxel> {
         return if let Some(chunk) = self.get_chunk_containing_pos(&pos) {
-            Some(chunk.lock().get_voxel(*pos))
+            let chunk = chunk.lock();
+            self.touch(&chunk.pos);
+            Some(chunk.get_voxel(*pos))
         } else {
             None
         };
@@ -246,39 +514,361 @@ impl ChunkMap {
         tools::voxel_ray_cast(is_hit, origin, direction, max_d)
     }
 
-    pub(crate) fn remove_chunk(&mut self, pos: &Pos) {
+    /// Adds a chunk that was generated or received from elsewhere, replacing
+    /// the loaded chunk at its position. The chunk gets meshed if it's visible,
+    /// otherwise it's compressed. Its padding and the padding of the loaded
+    /// neighbors are copied from each other's border voxels.
+    pub fn insert_chunk(&mut self, pool: AsyncComputeTaskPool, chunk: Chunk) {
+        self.insert_chunk_from(pool, chunk, false);
+    }
+
+    /// Like `insert_chunk` for chunks that come straight from the generator
+    pub(crate) fn insert_generated_chunk(&mut self, pool: AsyncComputeTaskPool, chunk: Chunk) {
+        self.insert_chunk_from(pool, chunk, true);
+    }
+
+    fn insert_chunk_from(&mut self, pool: AsyncComputeTaskPool, chunk: Chunk, generated: bool) {
+        let pos = chunk.pos;
+        // chunks that are only meshed at a coarser level just keep their downsampled voxels
+        if self.lod_only_chunks.contains(&pos) {
+            if let Some(lod) = &self.lod {
+                let block_library = &self.block_library;
+                let lod_chunk =
+                    LodChunk::from_chunk(&chunk, self.lod_level(&pos), lod.voting, |id| {
+                        block_library.is_solid_cube(id)
+                    });
+                self.insert_lod_chunk(lod_chunk);
+                return;
+            }
+        }
+        self.cancel_chunk_tasks(&pos);
+        self.compressed_chunks.remove(&pos);
+        self.unknown_blocks.remove(&pos);
+        self.chunks.insert(pos, Arc::new(Mutex::new(chunk)));
+        if generated {
+            self.generated_chunks.insert(pos);
+        } else {
+            self.generated_chunks.remove(&pos);
+        }
+        self.access_times.entry(pos).or_default();
+        self.sync_padding(&pool, &pos);
+        self.touch(&pos);
+        if self.meshing && self.visible_chunks.contains(&pos) {
+            self.make_dirty(&pos);
+        } else {
+            self.compress_chunk(pool, pos);
+        }
+    }
+
+    /// Returns the chunk compressed with the given byteorder, the compressed chunk
+    /// is reused if it already has that byteorder
+    pub fn get_compressed_chunk(&self, pos: &Pos, byteorder: bool) -> Option<Lz4CompressedChunk> {
+        if let Some(chunk) = self.chunks.get(pos) {
+            return Some(chunk.lock().compress(self.compression_level, byteorder));
+        }
+        let compressed_chunk = self.compressed_chunks.get(pos)?;
+        if byteorder == self.compress_byteorder {
+            Some(compressed_chunk.clone())
+        } else {
+            Some(
+                compressed_chunk
+                    .decompress(self.compress_byteorder)
+                    .compress(self.compression_level, byteorder),
+            )
+        }
+    }
+
+    /// Saves modified chunks before removing them. Returns false if the chunk
+    /// couldn't be saved, in which case it stays loaded so the edits aren't lost.
+    pub fn remove_chunk(&mut self, pos: &Pos) -> bool {
+        if let Err(e) = self.save_chunk(pos) {
+            warn!("failed to save chunk {:?}: {}", pos, e);
+            return false;
+        }
+        self.cancel_chunk_tasks(pos);
         if self.chunks.remove(pos).is_none() {
             self.compressed_chunks.remove(pos);
         }
+        self.lod_chunks.remove(pos);
+        self.modified_chunks.remove(pos);
+        self.generated_chunks.remove(pos);
+        self.unknown_blocks.remove(pos);
+        self.tick_scheduler.remove_chunk_ticks(pos);
+        self.access_times.remove(pos);
+        if self.simulated_chunks.contains(pos) {
+            self.load_queue_dirty = true;
+        }
+        true
+    }
+
+    /// Saves the chunk and its pending ticks to the storage if it was modified
+    /// since it was loaded or has pending ticks
+    pub fn save_chunk(&mut self, pos: &Pos) -> io::Result<()> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+        let pending_ticks = self.tick_scheduler.chunk_ticks(pos);
+        if !self.modified_chunks.contains(pos) && pending_ticks.is_empty() {
+            return Ok(());
+        }
+        let (chunk, block_ids) = if let Some(chunk) = self.chunks.get(pos) {
+            let chunk = chunk.lock();
+            (
+                chunk.compress(self.compression_level, self.compress_byteorder),
+                chunk.block_ids(),
+            )
+        } else if let Some(compressed_chunk) = self.compressed_chunks.get(pos) {
+            let block_ids = compressed_chunk
+                .decompress(self.compress_byteorder)
+                .block_ids();
+            (compressed_chunk.clone(), block_ids)
+        } else {
+            return Ok(());
+        };
+        let has_pending_ticks = !pending_ticks.is_empty();
+        storage.save(&StoredChunk::new(
+            chunk,
+            pending_ticks,
+            BlockPalette::new(block_ids, &self.block_library)
+                .with_unknown(self.unknown_blocks.get(pos).cloned().unwrap_or_default()),
+        ))?;
+        // the stored ticks run out of date as soon as they run so the chunk has to be saved again
+        if !has_pending_ticks {
+            self.modified_chunks.remove(pos);
+        }
+        Ok(())
+    }
+
+    /// Saves all modified chunks to the storage
+    pub fn save_modified_chunks(&mut self) -> io::Result<()> {
+        let modified_chunks: Vec<Pos> = self.modified_chunks.iter().copied().collect();
+        for pos in &modified_chunks {
+            self.save_chunk(pos)?;
+        }
+        Ok(())
+    }
+
+    /// Marks the chunk as the most recently used one
+    pub(crate) fn touch(&self, pos: &Pos) {
+        if let Some(access_time) = self.access_times.get(pos) {
+            let time = self.access_clock.fetch_add(1, Ordering::Relaxed);
+            access_time.store(time, Ordering::Relaxed);
+        }
+    }
+
+    fn chunk_memory(&self, pos: &Pos) -> usize {
+        if let Some(chunk) = self.chunks.get(pos) {
+            // chunks locked by another thread are most likely full chunks
+            chunk.try_lock().map_or_else(
+                || CHUNK_STORAGE_SIZE * size_of::<Voxel>(),
+                |chunk| chunk.memory_usage(),
+            )
+        } else if let Some(compressed_chunk) = self.compressed_chunks.get(pos) {
+            compressed_chunk.memory_usage()
+        } else {
+            0
+        }
+    }
+
+    pub fn memory_usage(&self) -> ChunkMemoryUsage {
+        ChunkMemoryUsage {
+            chunk_bytes: self.chunks.keys().map(|pos| self.chunk_memory(pos)).sum(),
+            compressed_bytes: self
+                .compressed_chunks
+                .keys()
+                .map(|pos| self.chunk_memory(pos))
+                .sum(),
+            lod_bytes: self
+                .lod_chunks
+                .values()
+                .map(|lod_chunk| lod_chunk.memory_usage())
+                .sum(),
+        }
+    }
+
+    /// Frees the least recently used chunks until the memory budget is met.
+    /// Chunks outside of simulate distance, e.g. ones that failed to save, are unloaded.
+    /// Modified chunks are saved to the storage first, without a storage they are kept.
+    /// Simulated chunks are never unloaded since they would be loaded again right away,
+    /// decompressed ones are queued for compression instead.
+    pub(crate) fn evict_to_budget(&mut self, usage: &ChunkMemoryUsage) {
+        let memory_budget = match self.memory_budget {
+            Some(memory_budget) => memory_budget,
+            None => return,
+        };
+        let mut total = usage.total();
+        if total <= memory_budget {
+            return;
+        }
+        let mut candidates: Vec<(u64, Pos)> = self
+            .chunk_keys()
+            .map(|pos| {
+                let access_time = self.access_times.get(pos);
+                (
+                    access_time.map_or(0, |time| time.load(Ordering::Relaxed)),
+                    *pos,
+                )
+            })
+            .collect();
+        candidates.sort_unstable_by_key(|(time, _)| *time);
+        for (_, pos) in candidates {
+            if total <= memory_budget {
+                break;
+            }
+            if self.simulated_chunks.contains(&pos) {
+                if self.needs_compression(&pos) && self.compression_queue.insert(pos) {
+                    // roughly what compression saves, the compressed chunk is a lot smaller
+                    total = total.saturating_sub(self.chunk_memory(&pos));
+                }
+                continue;
+            }
+            if self.storage.is_none() && self.modified_chunks.contains(&pos) {
+                continue;
+            }
+            let bytes = self.chunk_memory(&pos);
+            if self.remove_chunk(&pos) {
+                total = total.saturating_sub(bytes);
+            }
+        }
     }
 
     pub fn set_voxel(&mut self, voxel: Voxel, pos: &Pos) {
+        let mut filled_chunks = vec![];
         for chunk in self.get_mut_chunks_containing_pos(&pos) {
             let mut chunk = chunk.lock();
+            if chunk.is_empty() {
+                filled_chunks.push(chunk.pos);
+            }
             chunk.set_voxel(voxel, *pos);
-            if cfg!(feature = "mesher") {
+            self.modified_chunks.insert(chunk.pos);
+            self.generated_chunks.remove(&chunk.pos);
+            if self.meshing && self.visible_chunks.contains(&chunk.pos) {
                 self.make_dirty(&chunk.pos);
+            } else {
+                // meshing compresses the dirty chunks, the others would stay decompressed
+                self.compression_queue.insert(chunk.pos);
             }
         }
+        // empty chunks don't keep their padding up to date until they have voxels
+        for chunk_pos in filled_chunks {
+            self.pull_padding(&chunk_pos);
+        }
+        if self.record_edits {
+            self.voxel_edits.push((*pos, voxel));
+        }
+        self.schedule_fluid_updates(pos);
+    }
+
+    /// Returns the token the loading task has to check for cancellation
+    pub(crate) fn set_chunk_state_loading(&mut self, pos: &Pos) -> TaskToken {
+        let token = TaskToken::new();
+        if let Some(previous) = self.loading_chunks.insert(*pos, token.clone()) {
+            previous.cancel();
+        }
+        token
     }
 
-    pub(crate) fn set_chunk_state_loading(&mut self, pos: &Pos) {
-        self.loading_chunks.insert(*pos);
+    /// Returns false if the task was cancelled or replaced by a newer task,
+    /// in which case its result should be discarded.
+    pub(crate) fn set_chunk_state_loaded(&mut self, pos: &Pos, token: &TaskToken) -> bool {
+        match self.loading_chunks.get(pos) {
+            Some(current) if current.same_task(token) && !token.is_cancelled() => {
+                self.loading_chunks.remove(pos);
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub(crate) fn set_chunk_state_loaded(&mut self, pos: &Pos) {
-        self.loading_chunks.remove(pos);
+    /// Returns false if the compression was cancelled or replaced by a newer compression,
+    /// in which case the compressed chunk should be discarded.
+    pub(crate) fn set_chunk_compressed(&mut self, pos: &Pos, token: &TaskToken) -> bool {
+        match self.compressing_chunks.get(pos) {
+            Some(current) if current.same_task(token) && !token.is_cancelled() => {
+                self.compressing_chunks.remove(pos);
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub fn compress_chunk(&self, pool: AsyncComputeTaskPool, pos: Pos) {
+    /// Replaces the chunk with its compressed version. Returns false if the compression
+    /// was cancelled or the chunk was modified since compression began,
+    /// in which case the compressed chunk is discarded.
+    pub(crate) fn store_compressed_chunk(
+        &mut self,
+        compressed_chunk: Lz4CompressedChunk,
+        token: &TaskToken,
+    ) -> bool {
+        let pos = compressed_chunk.pos;
+        if !self.set_chunk_compressed(&pos, token) {
+            return false;
+        }
+        let current_version = self.chunks.get(&pos).map(|chunk| chunk.lock().version);
+        if current_version != Some(compressed_chunk.version) {
+            return false;
+        }
+        self.chunks.remove(&pos);
+        self.compressed_chunks.insert(pos, compressed_chunk);
+        true
+    }
+
+    /// True if the chunk is decompressed and nothing else is going to compress it.
+    /// Dirty chunks get compressed after meshing.
+    pub(crate) fn needs_compression(&self, pos: &Pos) -> bool {
+        self.chunks.contains_key(pos)
+            && !self.compressing_chunks.contains_key(pos)
+            && !self.dirty_chunks.contains(pos)
+    }
+
+    /// Compresses the queued chunks that nothing else is going to compress
+    pub(crate) fn compress_queued_chunks(&mut self, pool: &AsyncComputeTaskPool) {
+        for pos in std::mem::take(&mut self.compression_queue) {
+            if self.needs_compression(&pos) {
+                self.compress_chunk(pool.clone(), pos);
+            }
+        }
+    }
+
+    /// Cancels generation and compression of a chunk that left the view
+    /// and makes sure it isn't re-meshed.
+    pub(crate) fn cancel_chunk_tasks(&mut self, pos: &Pos) {
+        if let Some(token) = self.loading_chunks.remove(pos) {
+            token.cancel();
+        }
+        if let Some(token) = self.compressing_chunks.remove(pos) {
+            token.cancel();
+        }
+        self.dirty_chunks.remove(pos);
+    }
+
+    /// Positions of chunks that are currently being generated or loaded
+    pub(crate) fn loading_chunk_keys(&self) -> impl Iterator<Item = &Pos> {
+        self.loading_chunks.keys()
+    }
+
+    pub fn compress_chunk(&mut self, pool: AsyncComputeTaskPool, pos: Pos) {
         let sender = self.compression_channels.tx.clone();
         let compression_level = self.compression_level;
+        let byteorder = self.compress_byteorder;
         if let Some(chunk) = self.chunks.get(&pos) {
             let chunk = chunk.clone();
+            let token = TaskToken::new();
+            if let Some(previous) = self.compressing_chunks.insert(pos, token.clone()) {
+                previous.cancel();
+            }
             pool.spawn(async move {
+                if token.is_cancelled() {
+                    return;
+                }
                 let chunk = chunk.lock();
                 let start_instant = Instant::now();
-                match sender.send((chunk.compress(compression_level, false), start_instant)) {
+                let compressed_chunk = chunk.compress(compression_level, byteorder);
+                if token.is_cancelled() {
+                    return;
+                }
+                match sender.send((compressed_chunk, token, start_instant)) {
                     Ok(_) => {}
                     Err(_e) => {
                         warn!("failed to send compressed chunk with channel");
@@ -296,4 +886,165 @@ impl ChunkMap {
     }
 }
 
+impl BlockTickContext for ChunkMap {
+    fn get_voxel(&self, pos: &Pos) -> Option<u32> {
+        ChunkMap::get_voxel(self, pos)
+    }
+
+    fn set_voxel(&mut self, voxel: u32, pos: &Pos) {
+        ChunkMap::set_voxel(self, voxel, pos)
+    }
+
+    fn schedule_tick(&mut self, pos: &Pos, delay: u64, priority: i32) -> bool {
+        self.tick_scheduler.schedule(*pos, delay, priority)
+    }
+}
+
+/// Adds the placeholder unknown saved blocks are loaded as to the library if it's missing
+fn with_placeholder(mut block_library: Arc<BlockLibrary>) -> Arc<BlockLibrary> {
+    if block_library
+        .get_block_id(BlockLibrary::PLACEHOLDER)
+        .is_none()
+    {
+        Arc::make_mut(&mut block_library).add_placeholder();
+    }
+    block_library
+}
+
 type ChunkKeys<'a> = Chain<Keys<'a, Pos, Arc<Mutex<Chunk>>>, Keys<'a, Pos, Lz4CompressedChunk>>;
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk_map::ChunkMap;
+    use avoxel_blocks::{Block, BlockLibrary};
+    use avoxel_chunk::Chunk;
+    use avoxel_math::Pos;
+    use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+    use bevy_utils::HashMap;
+    use parking_lot::Mutex;
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    fn store_compressed_chunks(chunk_map: &mut ChunkMap, pool: &AsyncComputeTaskPool) {
+        for (compressed_chunk, token, _) in chunk_map.compression_channels.rx.clone().try_iter() {
+            let pos = compressed_chunk.pos;
+            if !chunk_map.store_compressed_chunk(compressed_chunk, &token)
+                && chunk_map.needs_compression(&pos)
+            {
+                chunk_map.compress_chunk(pool.clone(), pos);
+            }
+        }
+    }
+
+    /// Stores compressed chunks until no compression is running, panics after a minute
+    fn wait_for_compression(chunk_map: &mut ChunkMap, pool: &AsyncComputeTaskPool) {
+        let start = Instant::now();
+        while !chunk_map.compressing_chunks.is_empty() {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "compression timed out"
+            );
+            store_compressed_chunks(chunk_map, pool);
+        }
+    }
+
+    #[test]
+    fn edits_during_compression_are_not_lost() {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        let chunk_pos = Pos::new(0, 0, 0);
+        chunk_map
+            .chunks
+            .insert(chunk_pos, Arc::new(Mutex::new(Chunk::new(chunk_pos, 0))));
+
+        let mut expected = HashMap::default();
+        for i in 0..500 {
+            if i % 3 == 0 {
+                chunk_map.compress_chunk(pool.clone(), chunk_pos);
+            }
+            let pos = Pos::new(1 + i % 62, 1 + (i * 7) % 62, 1 + (i * 13) % 62);
+            let voxel = i as u32 + 1;
+            chunk_map.set_voxel(voxel, &pos);
+            expected.insert(pos, voxel);
+            store_compressed_chunks(&mut chunk_map, &pool);
+        }
+        // wait for the last compressions to finish
+        wait_for_compression(&mut chunk_map, &pool);
+
+        for (pos, voxel) in expected {
+            assert_eq!(chunk_map.get_voxel(&pos), Some(voxel));
+        }
+    }
+
+    #[test]
+    fn edited_chunks_that_are_not_meshed_get_compressed() {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.set_meshing(true);
+        let chunk_pos = Pos::new(0, 0, 0);
+        chunk_map.simulated_chunks.insert(chunk_pos);
+        chunk_map.insert_chunk(pool.clone(), Chunk::new(chunk_pos, 0));
+        wait_for_compression(&mut chunk_map, &pool);
+        assert!(chunk_map.compressed_chunks.contains_key(&chunk_pos));
+
+        // simulated but not visible, nothing meshes the chunk
+        chunk_map.set_voxel(1, &Pos::new(5, 5, 5));
+        assert!(chunk_map.chunks.contains_key(&chunk_pos));
+        assert!(chunk_map.dirty_chunks.is_empty());
+        chunk_map.compress_queued_chunks(&pool);
+        wait_for_compression(&mut chunk_map, &pool);
+        assert!(chunk_map.compressed_chunks.contains_key(&chunk_pos));
+        assert_eq!(chunk_map.get_voxel(&Pos::new(5, 5, 5)), Some(1));
+    }
+
+    #[test]
+    fn block_libraries_get_a_placeholder() {
+        let mut chunk_map = ChunkMap::default();
+        let placeholder = chunk_map.block_library.placeholder_block_id();
+        assert_ne!(placeholder, Block::AIR);
+
+        let mut block_library = BlockLibrary::new();
+        block_library.add_block(Block {
+            name: "air".to_string(),
+            ..Default::default()
+        });
+        chunk_map.set_block_library(Arc::new(block_library));
+        assert_eq!(chunk_map.block_library.placeholder_block_id(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_chunks() {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        let positions: Vec<Pos> = (0..4).map(|x| Pos::new(x * 2, 0, 0)).collect();
+        for pos in &positions {
+            let mut chunk = Chunk::new(*pos, 0);
+            chunk.set_voxel(1, *pos * 64);
+            chunk_map.insert_chunk(pool.clone(), chunk);
+        }
+        wait_for_compression(&mut chunk_map, &pool);
+        // the first chunk is used again, it's kept over the second
+        chunk_map.get_voxel(&(positions[0] * 64));
+        // simulated chunks aren't evicted
+        chunk_map.simulated_chunks.insert(positions[3]);
+        // modified chunks without a storage to save them to are kept
+        chunk_map.modified_chunks.insert(positions[2]);
+
+        let usage = chunk_map.memory_usage();
+        chunk_map.set_memory_budget(Some(usage.total() - 1));
+        chunk_map.evict_to_budget(&usage);
+        assert!(chunk_map.contains_chunk(&positions[0]));
+        assert!(!chunk_map.contains_chunk(&positions[1]));
+        assert!(chunk_map.contains_chunk(&positions[2]));
+        assert!(chunk_map.contains_chunk(&positions[3]));
+
+        chunk_map.set_memory_budget(Some(0));
+        let usage = chunk_map.memory_usage();
+        chunk_map.evict_to_budget(&usage);
+        assert!(!chunk_map.contains_chunk(&positions[0]));
+        assert!(chunk_map.contains_chunk(&positions[2]));
+        assert!(chunk_map.contains_chunk(&positions[3]));
+    }
+}
@@ -1,5 +1,5 @@
-use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
-use bevy::prelude::*;
+use bevy_diagnostic::{Diagnostic, DiagnosticId, Diagnostics};
+use bevy_ecs::system::ResMut;
 
 pub const GEN_TIMES: DiagnosticId =
     DiagnosticId::from_u128(188146834822086093741974488528456902483);
@@ -9,6 +9,10 @@ pub const CHUNK_COMPRESSION: DiagnosticId =
     DiagnosticId::from_u128(188146834822086093741974488528456902485);
 pub const COMPRESSION_TIMES: DiagnosticId =
     DiagnosticId::from_u128(188146834822086093741974488528456902486);
+pub const CHUNK_MEMORY: DiagnosticId =
+    DiagnosticId::from_u128(188146834822086093741974488528456902487);
+pub const COMPRESSED_CHUNK_MEMORY: DiagnosticId =
+    DiagnosticId::from_u128(188146834822086093741974488528456902488);
 
 pub fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
     diagnostics.add(Diagnostic::new(GEN_TIMES, "chunk_gen_times", 20));
@@ -23,4 +27,10 @@ pub fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
         "chunk_compression_times",
         20,
     ));
+    diagnostics.add(Diagnostic::new(CHUNK_MEMORY, "chunk_memory_bytes", 20));
+    diagnostics.add(Diagnostic::new(
+        COMPRESSED_CHUNK_MEMORY,
+        "compressed_chunk_memory_bytes",
+        20,
+    ));
 }
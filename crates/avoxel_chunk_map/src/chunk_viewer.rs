@@ -1,41 +1,134 @@
+use crate::chunk_worlds::WorldId;
 use avoxel_chunk::CHUNK_SIZE;
 use avoxel_math::{BevyVec3, Pos};
-use bevy::ecs::event::Events;
-use bevy::prelude::{Query, ResMut, Vec3};
+use bevy_ecs::event::Events;
+use bevy_ecs::system::{Query, ResMut};
+use bevy_math::Vec3;
 use indexmap::set::IndexSet;
+use std::sync::Arc;
 
+/// Sent when a viewer of the world moved or changed its distances
 #[derive(Default)]
-pub struct ChunkViewerMoveEvent;
+pub struct ChunkViewerMoveEvent(pub WorldId);
+
+/// The shape of the area that is loaded around a viewer.
+/// Distances are in chunks.
+#[derive(Clone)]
+pub enum ViewShape {
+    /// Every chunk within the distance on each axis
+    Cube,
+    /// Every chunk within the distance of the viewer
+    Sphere,
+    /// Every chunk within the distance horizontally and within
+    /// `vertical_distance` vertically. The vertical distance is the same
+    /// for the render and the simulate distance.
+    Cylinder { vertical_distance: i32 },
+    /// Every chunk the predicate returns true for. The predicate gets the
+    /// offset of the chunk from the viewer and the distance. Only offsets
+    /// within a cube of the distance are checked.
+    Custom(Arc<dyn Fn(Pos, i32) -> bool + Send + Sync>),
+}
+
+impl Default for ViewShape {
+    fn default() -> Self {
+        ViewShape::Cube
+    }
+}
+
+impl ViewShape {
+    pub fn contains(&self, offset: Pos, distance: i32) -> bool {
+        match self {
+            ViewShape::Cube => {
+                offset.x.abs() <= distance
+                    && offset.y.abs() <= distance
+                    && offset.z.abs() <= distance
+            }
+            ViewShape::Sphere => {
+                offset.x * offset.x + offset.y * offset.y + offset.z * offset.z
+                    <= distance * distance
+            }
+            ViewShape::Cylinder { vertical_distance } => {
+                offset.x * offset.x + offset.z * offset.z <= distance * distance
+                    && offset.y.abs() <= *vertical_distance
+            }
+            ViewShape::Custom(predicate) => predicate(offset, distance),
+        }
+    }
+
+    fn vertical_distance(&self, distance: i32) -> i32 {
+        match self {
+            ViewShape::Cylinder { vertical_distance } => *vertical_distance,
+            _ => distance,
+        }
+    }
+}
 
 /// Viewer entities are what causes chunks to get generated or loaded.
-/// Chunks load, generate, and render around a viewer.
+/// Chunks load and simulate within the simulate distance of a viewer
+/// and get meshed within the render distance.
 pub struct ChunkViewer {
     pos: Pos,
     render_distance: i32,
+    simulate_distance: i32,
+    shape: ViewShape,
     visible_chunks: IndexSet<Pos>,
+    simulated_chunks: IndexSet<Pos>,
     moved: bool,
+    /// The world the viewer was in when move events were last sent for it
+    world: Option<WorldId>,
 }
 
 impl ChunkViewer {
+    /// Creates a cube shaped viewer that simulates as far as it renders
     pub fn new(render_distance: i32, pos: Pos) -> Self {
         let mut chunk_viewer = Self {
             pos,
             render_distance,
+            simulate_distance: render_distance,
+            shape: ViewShape::default(),
             visible_chunks: IndexSet::new(),
+            simulated_chunks: IndexSet::new(),
             moved: true,
+            world: None,
         };
         chunk_viewer.update_visible_chunks();
         chunk_viewer
     }
 
+    pub fn with_view_shape(mut self, shape: ViewShape) -> Self {
+        self.shape = shape;
+        self.moved = true;
+        self.update_visible_chunks();
+        self
+    }
+
+    /// Chunks outside of the render distance but within the simulate distance
+    /// are loaded without being meshed.
+    pub fn with_simulate_distance(mut self, simulate_distance: i32) -> Self {
+        self.simulate_distance = simulate_distance;
+        self.moved = true;
+        self.update_visible_chunks();
+        self
+    }
+
     pub(crate) fn get_visible_chunks(&self) -> &IndexSet<Pos> {
         &self.visible_chunks
     }
 
+    pub fn get_simulated_chunks(&self) -> &IndexSet<Pos> {
+        &self.simulated_chunks
+    }
+
+    /// True if the chunk is within the simulate distance, unlike `get_simulated_chunks`
+    /// this doesn't include the chunks that are only within render distance
+    pub(crate) fn simulates(&self, pos: &Pos) -> bool {
+        self.shape.contains(*pos - self.pos, self.simulate_distance)
+    }
+
     pub fn set_translation(&mut self, translation: Vec3) {
         let chunk_pos = translation / CHUNK_SIZE as f32;
         let prev_pos = self.pos;
-        self.pos = Pos::from_vec3(&chunk_pos);
+        self.pos = Pos::from_vec3(&chunk_pos.floor());
         if prev_pos != self.pos {
             self.moved = true;
             self.update_visible_chunks();
@@ -43,42 +136,117 @@ impl ChunkViewer {
     }
 
     fn update_visible_chunks(&mut self) {
-        // reset visible chunks
-        self.visible_chunks = IndexSet::new();
-
-        let start_x = self.pos.x - self.render_distance;
-        let start_z = self.pos.z - self.render_distance;
-        let start_y = self.pos.y - self.render_distance;
-        let end_x = self.pos.x + self.render_distance;
-        let end_z = self.pos.z + self.render_distance;
-        let end_y = self.pos.y + self.render_distance;
-
-        for x in start_x..end_x {
-            for z in start_z..end_z {
-                for y in (start_y..end_y).rev() {
-                    self.visible_chunks.insert(Pos::new(x, y, z));
+        self.visible_chunks = self.chunks_within(self.render_distance);
+        self.simulated_chunks = self.chunks_within(self.simulate_distance);
+        // rendered chunks always have to be loaded
+        self.simulated_chunks
+            .extend(self.visible_chunks.iter().copied());
+    }
+
+    fn chunks_within(&self, distance: i32) -> IndexSet<Pos> {
+        let mut chunks = IndexSet::new();
+        let vertical_distance = self.shape.vertical_distance(distance);
+        for x in -distance..=distance {
+            for z in -distance..=distance {
+                for y in (-vertical_distance..=vertical_distance).rev() {
+                    let offset = Pos::new(x, y, z);
+                    if self.shape.contains(offset, distance) {
+                        chunks.insert(self.pos + offset);
+                    }
                 }
             }
         }
+        chunks
     }
 
     pub fn get_pos(&self) -> Pos {
         self.pos
     }
+
+    pub fn render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    pub fn simulate_distance(&self) -> i32 {
+        self.simulate_distance
+    }
 }
 
+/// Sends a move event for the world of every viewer that moved. Viewers that
+/// changed their world send one for the world they left and one for the new world.
 pub(crate) fn chunk_viewer_moved(
     mut events: ResMut<Events<ChunkViewerMoveEvent>>,
-    mut viewers: Query<&mut ChunkViewer>,
+    mut viewers: Query<(&mut ChunkViewer, Option<&WorldId>)>,
 ) {
-    let mut moved = false;
-    for mut viewer in viewers.iter_mut() {
+    let mut moved_worlds = vec![];
+    for (mut viewer, world) in viewers.iter_mut() {
+        let world = world.copied().unwrap_or_default();
+        if viewer.world != Some(world) {
+            if let Some(previous_world) = viewer.world {
+                moved_worlds.push(previous_world);
+            }
+            viewer.world = Some(world);
+            viewer.moved = true;
+        }
         if viewer.moved {
-            moved = true;
+            moved_worlds.push(world);
             viewer.moved = false;
         }
     }
-    if moved {
-        events.send(ChunkViewerMoveEvent);
+    moved_worlds.sort_unstable();
+    moved_worlds.dedup();
+    for world in moved_worlds {
+        events.send(ChunkViewerMoveEvent(world));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chunk_viewer::{chunk_viewer_moved, ChunkViewer, ChunkViewerMoveEvent},
+        chunk_worlds::WorldId,
+    };
+    use avoxel_math::Pos;
+    use bevy_ecs::{
+        event::Events,
+        schedule::{Stage, SystemStage},
+        system::IntoSystem,
+        world::World,
+    };
+
+    /// Runs `chunk_viewer_moved` and returns the worlds it sent move events for
+    fn moved_worlds(world: &mut World) -> Vec<WorldId> {
+        let mut stage = SystemStage::single(chunk_viewer_moved.system());
+        stage.run(world);
+        let mut events = world
+            .get_resource_mut::<Events<ChunkViewerMoveEvent>>()
+            .unwrap();
+        let mut moved_worlds: Vec<WorldId> = events.drain().map(|event| event.0).collect();
+        moved_worlds.sort_unstable();
+        moved_worlds
+    }
+
+    #[test]
+    fn changing_worlds_moves_both_worlds() {
+        const CAVES: WorldId = WorldId(1);
+        let mut world = World::new();
+        world.insert_resource(Events::<ChunkViewerMoveEvent>::default());
+        let viewer = world
+            .spawn()
+            .insert(ChunkViewer::new(1, Pos::new(0, 0, 0)))
+            .id();
+        assert_eq!(moved_worlds(&mut world), vec![WorldId::DEFAULT]);
+        assert_eq!(moved_worlds(&mut world), vec![]);
+
+        // a world is added
+        world.entity_mut(viewer).insert(CAVES);
+        assert_eq!(moved_worlds(&mut world), vec![WorldId::DEFAULT, CAVES]);
+        // the world changes
+        world.entity_mut(viewer).insert(WorldId(2));
+        assert_eq!(moved_worlds(&mut world), vec![CAVES, WorldId(2)]);
+        // the world is removed
+        world.entity_mut(viewer).remove::<WorldId>();
+        assert_eq!(moved_worlds(&mut world), vec![WorldId::DEFAULT, WorldId(2)]);
+        assert_eq!(moved_worlds(&mut world), vec![]);
     }
 }
@@ -0,0 +1,81 @@
+use crate::chunk_map::ChunkMap;
+use bevy_utils::HashMap;
+
+/// Identifies a world like an overworld, a cave dimension or an editor preview.
+/// Chunk viewers, boxes and chunk meshes without a `WorldId` belong to `WorldId::DEFAULT`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorldId(pub u32);
+
+impl WorldId {
+    pub const DEFAULT: WorldId = WorldId(0);
+}
+
+/// The chunk maps of all worlds. Every world has its own generator, storage,
+/// block library and viewers.
+pub struct ChunkWorlds {
+    maps: HashMap<WorldId, ChunkMap>,
+    /// Applied to the chunk maps of worlds that are added later on
+    meshing: bool,
+}
+
+impl Default for ChunkWorlds {
+    fn default() -> Self {
+        Self::new(ChunkMap::default())
+    }
+}
+
+impl ChunkWorlds {
+    /// Creates the worlds with `chunk_map` as the default world
+    pub fn new(chunk_map: ChunkMap) -> Self {
+        let mut maps = HashMap::default();
+        maps.insert(WorldId::DEFAULT, chunk_map);
+        Self {
+            maps,
+            meshing: false,
+        }
+    }
+
+    /// Adds a world or replaces the chunk map of a world, returning the previous one
+    pub fn insert(&mut self, world: WorldId, mut chunk_map: ChunkMap) -> Option<ChunkMap> {
+        chunk_map.set_meshing(self.meshing);
+        self.maps.insert(world, chunk_map)
+    }
+
+    /// Removes a world. Modified chunks aren't saved, use `ChunkMap::save_modified_chunks`
+    /// on the returned chunk map to keep them.
+    pub fn remove(&mut self, world: WorldId) -> Option<ChunkMap> {
+        self.maps.remove(&world)
+    }
+
+    pub fn get(&self, world: WorldId) -> Option<&ChunkMap> {
+        self.maps.get(&world)
+    }
+
+    pub fn get_mut(&mut self, world: WorldId) -> Option<&mut ChunkMap> {
+        self.maps.get_mut(&world)
+    }
+
+    pub fn contains(&self, world: WorldId) -> bool {
+        self.maps.contains_key(&world)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (WorldId, &ChunkMap)> {
+        self.maps
+            .iter()
+            .map(|(world, chunk_map)| (*world, chunk_map))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (WorldId, &mut ChunkMap)> {
+        self.maps
+            .iter_mut()
+            .map(|(world, chunk_map)| (*world, chunk_map))
+    }
+
+    /// Whether a mesher consumes the dirty chunks of all worlds, see `ChunkMap::set_meshing`
+    pub fn set_meshing(&mut self, meshing: bool) {
+        self.meshing = meshing;
+        for chunk_map in self.maps.values_mut() {
+            chunk_map.set_meshing(meshing);
+        }
+    }
+}
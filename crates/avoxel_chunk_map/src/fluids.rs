@@ -0,0 +1,337 @@
+use crate::chunk_map::ChunkMap;
+use avoxel_blocks::{Block, Fluid};
+use avoxel_chunk::{voxel_from_parts, VoxelBits, FLUID_FALLING, FLUID_SOURCE};
+use avoxel_math::Pos;
+use bevy_utils::HashSet;
+use std::collections::BTreeMap;
+
+fn horizontal_neighbors() -> [Pos; 4] {
+    [
+        Pos::new(1, 0, 0),
+        Pos::new(-1, 0, 0),
+        Pos::new(0, 0, 1),
+        Pos::new(0, 0, -1),
+    ]
+}
+
+/// Positions of fluids waiting to be updated
+pub struct FluidUpdates {
+    /// Keyed by the tick the update is due and the order updates were scheduled in
+    queue: BTreeMap<(u64, u64), Pos>,
+    scheduled: HashSet<Pos>,
+    next_order: u64,
+    /// Max number of fluid updates per tick, updates over budget are delayed
+    pub(crate) updates_per_tick: usize,
+}
+
+impl Default for FluidUpdates {
+    fn default() -> Self {
+        Self {
+            queue: Default::default(),
+            scheduled: Default::default(),
+            next_order: 0,
+            updates_per_tick: 1024,
+        }
+    }
+}
+
+impl FluidUpdates {
+    pub fn set_updates_per_tick(&mut self, updates_per_tick: usize) {
+        self.updates_per_tick = updates_per_tick;
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn schedule(&mut self, pos: Pos, due: u64) {
+        if self.scheduled.insert(pos) {
+            self.queue.insert((due, self.next_order), pos);
+            self.next_order += 1;
+        }
+    }
+
+    fn pop_due(&mut self, current_tick: u64) -> Option<Pos> {
+        let key = *self.queue.keys().next()?;
+        if key.0 > current_tick {
+            return None;
+        }
+        let pos = self.queue.remove(&key)?;
+        self.scheduled.remove(&pos);
+        Some(pos)
+    }
+}
+
+impl ChunkMap {
+    /// Schedules updates for the fluids at and around `pos`, called when a voxel changes.
+    /// Nothing is scheduled while the chunk map isn't simulating or there are no fluids.
+    pub(crate) fn schedule_fluid_updates(&mut self, pos: &Pos) {
+        if !self.simulating || !self.block_library.has_fluids() {
+            return;
+        }
+        let current_tick = self.tick_scheduler.current_tick();
+        let offsets = horizontal_neighbors();
+        let vertical_offsets = [Pos::zero(), Pos::unit_y(), -Pos::unit_y()];
+        for offset in offsets.iter().chain(vertical_offsets.iter()) {
+            let neighbor = *pos + *offset;
+            // compressed neighbors are read without decompressing them
+            let voxel = match self
+                .read_chunk_containing_pos(&neighbor, |chunk| chunk.get_voxel(neighbor))
+            {
+                Some(voxel) => voxel,
+                None => continue,
+            };
+            let tick_delay = match self.block_library.get_fluid(voxel.id()) {
+                Some(fluid) => fluid.tick_delay,
+                None => continue,
+            };
+            self.fluid_updates
+                .schedule(neighbor, current_tick + tick_delay.max(1));
+        }
+    }
+
+    /// Runs the fluid updates that are due, up to the update budget
+    pub fn update_fluids(&mut self) {
+        let current_tick = self.tick_scheduler.current_tick();
+        for _ in 0..self.fluid_updates.updates_per_tick {
+            match self.fluid_updates.pop_due(current_tick) {
+                Some(pos) => self.update_fluid(&pos),
+                None => break,
+            }
+        }
+    }
+
+    fn update_fluid(&mut self, pos: &Pos) {
+        let voxel = match self.get_voxel(pos) {
+            Some(voxel) => voxel,
+            None => return,
+        };
+        let id = voxel.id();
+        let fluid = match self.block_library.get_fluid(id) {
+            Some(fluid) => *fluid,
+            None => return,
+        };
+        let level = voxel.level();
+        if level != FLUID_SOURCE {
+            // flowing fluids retract when they're no longer fed.
+            // Changing the voxel schedules another update for the spreading.
+            match self.fed_fluid_level(pos, id, &fluid) {
+                None => {
+                    self.set_voxel(Block::AIR, pos);
+                    return;
+                }
+                Some(fed_level) if fed_level != level => {
+                    self.set_voxel(voxel.with_level(fed_level), pos);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        // fall before spreading sideways
+        let below = *pos - Pos::unit_y();
+        match self.get_voxel(&below) {
+            Some(Block::AIR) => {
+                self.set_voxel(voxel_from_parts(id, FLUID_FALLING), &below);
+                return;
+            }
+            Some(below_voxel) if below_voxel.id() != id => {}
+            // unloaded or the same fluid
+            _ => return,
+        }
+
+        let spread_level = spread_level(level);
+        if spread_level > fluid.flow_distance {
+            return;
+        }
+        for offset in horizontal_neighbors().iter() {
+            let neighbor = *pos + *offset;
+            let flows_into = match self.get_voxel(&neighbor) {
+                Some(Block::AIR) => true,
+                Some(neighbor_voxel) if neighbor_voxel.id() == id => {
+                    let neighbor_level = neighbor_voxel.level();
+                    neighbor_level != FLUID_SOURCE
+                        && neighbor_level & FLUID_FALLING == 0
+                        && neighbor_level > spread_level
+                }
+                _ => false,
+            };
+            if flows_into {
+                self.set_voxel(voxel_from_parts(id, spread_level), &neighbor);
+            }
+        }
+    }
+
+    /// Returns the level a flowing fluid gets from its neighbors, `None` if nothing feeds it
+    fn fed_fluid_level(&self, pos: &Pos, id: u32, fluid: &Fluid) -> Option<u32> {
+        if let Some(above) = self.get_voxel(&(*pos + Pos::unit_y())) {
+            if above.id() == id {
+                return Some(FLUID_FALLING);
+            }
+        }
+        let mut fed_level: Option<u32> = None;
+        for offset in horizontal_neighbors().iter() {
+            let neighbor = *pos + *offset;
+            let neighbor_voxel = match self.get_voxel(&neighbor) {
+                Some(voxel) if voxel.id() == id => voxel,
+                _ => continue,
+            };
+            // fluids only flow sideways when they rest on something
+            if !self.is_solid_for_fluid(&(neighbor - Pos::unit_y()), id) {
+                continue;
+            }
+            let level = spread_level(neighbor_voxel.level());
+            fed_level = Some(fed_level.map_or(level, |fed_level| fed_level.min(level)));
+        }
+        fed_level.filter(|level| *level <= fluid.flow_distance)
+    }
+
+    fn is_solid_for_fluid(&self, pos: &Pos, id: u32) -> bool {
+        match self.get_voxel(pos) {
+            Some(voxel) => voxel != Block::AIR && voxel.id() != id,
+            None => false,
+        }
+    }
+}
+
+/// Level of the fluid flowing sideways out of a fluid with `level`
+fn spread_level(level: u32) -> u32 {
+    if level & FLUID_FALLING != 0 {
+        1
+    } else {
+        level + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk_map::ChunkMap;
+    use avoxel_blocks::{Block, BlockLibrary, Fluid};
+    use avoxel_chunk::{voxel_from_parts, Chunk, VoxelBits, FLUID_FALLING, FLUID_SOURCE};
+    use avoxel_math::Pos;
+    use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+    use std::sync::Arc;
+
+    const STONE: u32 = 1;
+    const WATER: u32 = 2;
+
+    /// Two chunks next to each other along x with a stone floor up to y 9
+    fn chunk_map() -> ChunkMap {
+        let mut block_library = BlockLibrary::new();
+        block_library
+            .add_block(Block {
+                name: "air".to_string(),
+                ..Default::default()
+            })
+            .add_block(Block {
+                name: "stone".to_string(),
+                ..Default::default()
+            })
+            .add_block(Block {
+                name: "water".to_string(),
+                fluid: Some(Fluid {
+                    flow_distance: 3,
+                    tick_delay: 1,
+                }),
+                ..Default::default()
+            });
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.set_block_library(Arc::new(block_library));
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        for pos in [Pos::new(0, 0, 0), Pos::new(1, 0, 0)].iter() {
+            let mut chunk = Chunk::new(*pos, 0);
+            let origin = *pos * 64;
+            chunk.fill_area(STONE, origin - 1, origin + Pos::new(65, 10, 65));
+            chunk_map.insert_chunk(pool.clone(), chunk);
+        }
+        chunk_map
+    }
+
+    fn settle(chunk_map: &mut ChunkMap) {
+        for _ in 0..100 {
+            chunk_map.tick_scheduler.advance();
+            chunk_map.update_fluids();
+        }
+        assert_eq!(chunk_map.fluid_updates.pending_count(), 0);
+    }
+
+    fn water_level(chunk_map: &ChunkMap, x: i32, y: i32, z: i32) -> Option<u32> {
+        let voxel = chunk_map.get_voxel(&Pos::new(x, y, z)).unwrap();
+        if voxel_from_parts(WATER, voxel.level()) == voxel {
+            Some(voxel.level())
+        } else {
+            assert_eq!(voxel, Block::AIR, "at {} {} {}", x, y, z);
+            None
+        }
+    }
+
+    #[test]
+    fn spreads_up_to_flow_distance() {
+        let mut chunk_map = chunk_map();
+        chunk_map.set_voxel(WATER, &Pos::new(10, 10, 10));
+        settle(&mut chunk_map);
+        assert_eq!(water_level(&chunk_map, 10, 10, 10), Some(FLUID_SOURCE));
+        assert_eq!(water_level(&chunk_map, 11, 10, 10), Some(1));
+        assert_eq!(water_level(&chunk_map, 10, 10, 8), Some(2));
+        assert_eq!(water_level(&chunk_map, 11, 10, 11), Some(2));
+        assert_eq!(water_level(&chunk_map, 7, 10, 10), Some(3));
+        assert_eq!(water_level(&chunk_map, 6, 10, 10), None);
+        assert_eq!(water_level(&chunk_map, 12, 10, 12), None);
+        // water doesn't climb
+        assert_eq!(water_level(&chunk_map, 10, 11, 10), None);
+    }
+
+    #[test]
+    fn retracts_without_source() {
+        let mut chunk_map = chunk_map();
+        chunk_map.set_voxel(WATER, &Pos::new(10, 10, 10));
+        settle(&mut chunk_map);
+        chunk_map.set_voxel(Block::AIR, &Pos::new(10, 10, 10));
+        settle(&mut chunk_map);
+        for x in 6..=14 {
+            for z in 6..=14 {
+                assert_eq!(water_level(&chunk_map, x, 10, z), None);
+            }
+        }
+    }
+
+    #[test]
+    fn falls_before_spreading() {
+        let mut chunk_map = chunk_map();
+        chunk_map.set_voxel(WATER, &Pos::new(10, 15, 10));
+        settle(&mut chunk_map);
+        for y in 10..15 {
+            assert_eq!(water_level(&chunk_map, 10, y, 10), Some(FLUID_FALLING));
+        }
+        // the source pours down instead of spreading
+        assert_eq!(water_level(&chunk_map, 11, 15, 10), None);
+        assert_eq!(water_level(&chunk_map, 11, 14, 10), None);
+        // the falling water spreads once it lands
+        assert_eq!(water_level(&chunk_map, 11, 10, 10), Some(1));
+        assert_eq!(water_level(&chunk_map, 13, 10, 10), Some(3));
+        assert_eq!(water_level(&chunk_map, 14, 10, 10), None);
+    }
+
+    #[test]
+    fn flows_across_chunks() {
+        let mut chunk_map = chunk_map();
+        chunk_map.set_voxel(WATER, &Pos::new(62, 10, 10));
+        settle(&mut chunk_map);
+        assert_eq!(water_level(&chunk_map, 63, 10, 10), Some(1));
+        assert_eq!(water_level(&chunk_map, 64, 10, 10), Some(2));
+        assert_eq!(water_level(&chunk_map, 65, 10, 10), Some(3));
+        assert_eq!(water_level(&chunk_map, 66, 10, 10), None);
+        // the padding of both chunks follows the flow
+        for chunk_pos in [Pos::new(0, 0, 0), Pos::new(1, 0, 0)].iter() {
+            let chunk = chunk_map.chunks[chunk_pos].lock();
+            assert_eq!(
+                chunk.get_voxel(Pos::new(63, 10, 10)),
+                voxel_from_parts(WATER, 1)
+            );
+            assert_eq!(
+                chunk.get_voxel(Pos::new(64, 10, 10)),
+                voxel_from_parts(WATER, 2)
+            );
+        }
+    }
+}
@@ -1,18 +1,44 @@
+mod block_ticks;
 mod channels;
 mod chunk_map;
 pub mod chunk_map_diagnostics;
 mod chunk_viewer;
+mod chunk_worlds;
+mod fluids;
+mod lod;
+mod padding;
+mod storage;
 mod systems;
+mod task_token;
 mod tools;
+mod voxel_queries;
 
-pub use crate::{chunk_map::ChunkMap, chunk_viewer::ChunkViewer};
+pub use crate::{
+    block_ticks::{PendingTick, TickScheduler},
+    chunk_map::{ChunkMap, ChunkMemoryUsage},
+    chunk_viewer::{ChunkViewer, ChunkViewerMoveEvent, ViewShape},
+    chunk_worlds::{ChunkWorlds, WorldId},
+    fluids::FluidUpdates,
+    lod::LodSettings,
+    storage::{ChunkStorage, StoredChunk, STORAGE_FORMAT_VERSION},
+    task_token::TaskToken,
+    tools::{
+        block_map_colors, load_texture_atlas, render_top_down_map,
+        render_top_down_map_from_generator, TopDownMap,
+    },
+    voxel_queries::VoxelNeighborhood,
+};
 use crate::{
-    chunk_map_diagnostics::setup_diagnostics,
-    chunk_viewer::{chunk_viewer_moved, ChunkViewerMoveEvent},
-    systems::*,
+    chunk_map_diagnostics::setup_diagnostics, chunk_viewer::chunk_viewer_moved, systems::*,
 };
 use avoxel_blocks::BlockLibrary;
-use bevy::prelude::*;
+use bevy_app::{AppBuilder, Plugin};
+use bevy_core::FixedTimestep;
+use bevy_diagnostic::Diagnostics;
+use bevy_ecs::{schedule::SystemSet, system::IntoSystem};
+
+/// Block ticks per second
+pub const TICKS_PER_SECOND: f64 = 20.0;
 
 pub struct AvoxelChunkMapPlugin;
 
@@ -21,8 +47,12 @@ impl Plugin for AvoxelChunkMapPlugin {
         if app.world().get_resource::<BlockLibrary>().is_none() {
             app.init_resource::<BlockLibrary>();
         }
-        if app.world().get_resource::<ChunkMap>().is_none() {
-            app.init_resource::<ChunkMap>();
+        if app.world().get_resource::<ChunkWorlds>().is_none() {
+            app.init_resource::<ChunkWorlds>();
+        }
+        // a headless app using `MinimalPlugins` doesn't have the `DiagnosticsPlugin`
+        if app.world().get_resource::<Diagnostics>().is_none() {
+            app.init_resource::<Diagnostics>();
         }
 
         app.add_event::<ChunkViewerMoveEvent>()
@@ -31,6 +61,13 @@ impl Plugin for AvoxelChunkMapPlugin {
             .add_system(update_block_library.system())
             .add_system(update_visible_chunks.system())
             .add_system(gen_chunks_system.system())
-            .add_system(store_decompressed_compressed_chunks.system());
+            .add_system(store_decompressed_compressed_chunks.system())
+            .add_system(chunk_memory_system.system())
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(FixedTimestep::step(1.0 / TICKS_PER_SECOND))
+                    .with_system(block_tick_system.system())
+                    .with_system(fluid_system.system()),
+            );
     }
 }
@@ -0,0 +1,148 @@
+use crate::{chunk_map::ChunkMap, chunk_viewer::ChunkViewer};
+use avoxel_chunk::{LodChunk, LodVoting, MAX_LOD_LEVEL};
+use avoxel_math::Pos;
+
+fn neighbors() -> [Pos; 6] {
+    [
+        Pos::new(1, 0, 0),
+        Pos::new(-1, 0, 0),
+        Pos::new(0, 1, 0),
+        Pos::new(0, -1, 0),
+        Pos::new(0, 0, 1),
+        Pos::new(0, 0, -1),
+    ]
+}
+
+/// When visible chunks are meshed at a coarser level. Distant chunks outside of the
+/// simulate distance of every viewer only keep their downsampled voxels.
+#[derive(Clone, Debug, Default)]
+pub struct LodSettings {
+    /// Distance in chunks to the nearest viewer at which each level starts,
+    /// the first one is for level 1. Levels past `MAX_LOD_LEVEL` are ignored.
+    pub distances: Vec<i32>,
+    pub voting: LodVoting,
+}
+
+impl LodSettings {
+    pub fn new(distances: Vec<i32>) -> Self {
+        Self {
+            distances,
+            voting: Default::default(),
+        }
+    }
+
+    pub fn with_voting(mut self, voting: LodVoting) -> Self {
+        self.voting = voting;
+        self
+    }
+
+    /// The level of a chunk at the squared distance to the nearest viewer
+    pub fn level(&self, distance_squared: i32) -> u32 {
+        self.distances
+            .iter()
+            .take(MAX_LOD_LEVEL as usize)
+            .take_while(|distance| distance_squared >= *distance * *distance)
+            .count() as u32
+    }
+}
+
+impl ChunkMap {
+    /// Picks the level of every visible chunk. Has to run after the visible and
+    /// simulated chunks and viewer positions were updated.
+    pub(crate) fn update_lod_levels(&mut self, viewers: &[&ChunkViewer]) {
+        self.lod_dirty = false;
+        let previous_levels = std::mem::take(&mut self.lod_levels);
+        self.lod_only_chunks.clear();
+        let (levels, voting): (Vec<(Pos, u32)>, LodVoting) = match &self.lod {
+            Some(lod) => (
+                self.visible_chunks
+                    .iter()
+                    .map(|pos| (*pos, lod.level(self.viewer_distance_squared(pos))))
+                    .filter(|(_, level)| *level > 0)
+                    .collect(),
+                lod.voting,
+            ),
+            None => (vec![], LodVoting::default()),
+        };
+        for (pos, level) in levels {
+            self.lod_levels.insert(pos, level);
+            // voxels of chunks outside of simulate distance aren't needed
+            if !viewers.iter().any(|viewer| viewer.simulates(&pos)) {
+                self.lod_only_chunks.insert(pos);
+                self.simulated_chunks.remove(&pos);
+            }
+        }
+
+        // coarse data is kept at the level the chunk is meshed at, chunks
+        // that need a finer level than the one cached are loaded again
+        let lod_only_chunks = &self.lod_only_chunks;
+        let lod_levels = &self.lod_levels;
+        self.lod_chunks.retain(|pos, lod_chunk| {
+            lod_only_chunks.contains(pos) && lod_chunk.level <= lod_levels[pos]
+        });
+        for (pos, lod_chunk) in self.lod_chunks.iter_mut() {
+            let level = self.lod_levels[pos];
+            if lod_chunk.level < level {
+                *lod_chunk = lod_chunk.downsample(level, voting);
+            }
+        }
+
+        // the skirts of neighbors depend on the level as well
+        if self.meshing {
+            let changed: Vec<Pos> = self
+                .visible_chunks
+                .iter()
+                .filter(|pos| previous_levels.get(pos) != self.lod_levels.get(pos))
+                .copied()
+                .collect();
+            for pos in changed {
+                for offset in std::iter::once(Pos::new(0, 0, 0)).chain(neighbors().iter().copied())
+                {
+                    let pos = pos + offset;
+                    if self.visible_chunks.contains(&pos) && self.is_meshable(&pos) {
+                        self.make_dirty(&pos);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Caches the coarse data of a chunk that is only meshed at a coarser level
+    pub fn insert_lod_chunk(&mut self, lod_chunk: LodChunk) {
+        let pos = lod_chunk.pos;
+        let level = self.lod_level(&pos);
+        let voting = match &self.lod {
+            Some(lod) => lod.voting,
+            None => return,
+        };
+        // the chunk may have come closer while it was loading
+        if !self.lod_only_chunks.contains(&pos) || lod_chunk.level > level {
+            self.load_queue_dirty = true;
+            return;
+        }
+        let lod_chunk = if lod_chunk.level < level {
+            lod_chunk.downsample(level, voting)
+        } else {
+            lod_chunk
+        };
+        self.lod_chunks.insert(pos, lod_chunk);
+        if self.meshing && self.visible_chunks.contains(&pos) {
+            self.make_dirty(&pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lod::LodSettings;
+
+    #[test]
+    fn levels_by_distance() {
+        let lod = LodSettings::new(vec![4, 8, 16, 32]);
+        assert_eq!(lod.level(0), 0);
+        assert_eq!(lod.level(15), 0);
+        assert_eq!(lod.level(16), 1);
+        assert_eq!(lod.level(100), 2);
+        assert_eq!(lod.level(32 * 32), 3);
+    }
+}
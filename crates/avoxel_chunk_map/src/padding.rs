@@ -0,0 +1,252 @@
+use crate::chunk_map::ChunkMap;
+use avoxel_chunk::{Chunk, CHUNK_SIZE};
+use avoxel_math::{Extent3, Pos};
+use bevy_tasks::AsyncComputeTaskPool;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// The 26 chunks whose voxels are in the padding of a chunk
+fn neighbor_offsets() -> impl Iterator<Item = Pos> {
+    (-1..=1).flat_map(|x| {
+        (-1..=1).flat_map(move |y| {
+            (-1..=1)
+                .map(move |z| Pos::new(x, y, z))
+                .filter(|offset| *offset != Pos::zero())
+        })
+    })
+}
+
+/// The voxels that belong to the chunk, without the padding
+fn interior(pos: Pos) -> Extent3 {
+    Extent3 {
+        min: pos * CHUNK_SIZE,
+        max: pos * CHUNK_SIZE + CHUNK_SIZE - 1,
+    }
+}
+
+/// Copies the voxels of `from` into the padding of `to` where they overlap and
+/// returns true if the padding changed. The padding of empty chunks isn't filled
+/// in since they aren't meshed, it's pulled in when they get their first voxel.
+pub(crate) fn copy_border(from: &Chunk, to: &mut Chunk) -> bool {
+    if to.is_empty() {
+        return false;
+    }
+    let source = interior(from.pos);
+    let target = to.extent();
+    let min = Pos::partial_max(source.min, target.min);
+    let max = Pos::partial_min(source.max, target.max);
+    let mut changed = false;
+    for x in min.x..=max.x {
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                let pos = Pos::new(x, y, z);
+                let voxel = from.get_voxel(pos);
+                if to.get_voxel(pos) != voxel {
+                    to.set_voxel(voxel, pos);
+                    changed = true;
+                }
+                // densities are only stored once a chunk has any
+                if from.densities.is_empty() && to.densities.is_empty() {
+                    continue;
+                }
+                let density = |chunk: &Chunk| {
+                    chunk
+                        .densities
+                        .get(chunk.block_index(pos))
+                        .copied()
+                        .unwrap_or(u8::MAX)
+                };
+                if density(to) != density(from) {
+                    to.set_density(density(from), pos);
+                    changed = true;
+                }
+            }
+        }
+    }
+    changed
+}
+
+impl ChunkMap {
+    /// Copies the border voxels of the loaded neighbors into the padding of the chunk,
+    /// the neighbors know their own voxels better than the generator of the chunk
+    pub(crate) fn pull_padding(&self, pos: &Pos) {
+        let chunk = match self.chunks.get(pos) {
+            Some(chunk) => chunk.clone(),
+            None => return,
+        };
+        for offset in neighbor_offsets() {
+            let neighbor_pos = *pos + offset;
+            if let Some(neighbor) = self.chunks.get(&neighbor_pos) {
+                copy_border(&neighbor.lock(), &mut chunk.lock());
+            } else if let Some(compressed) = self.compressed_chunks.get(&neighbor_pos) {
+                let neighbor = compressed.decompress(self.compress_byteorder);
+                copy_border(&neighbor, &mut chunk.lock());
+            }
+        }
+    }
+
+    /// Brings the padding of the chunk and of its neighbors up to date, copying the
+    /// border voxels both ways in one pass so compressed neighbors are only decompressed
+    /// once. Neighbors whose padding changed are meshed or compressed again.
+    pub(crate) fn sync_padding(&mut self, pool: &AsyncComputeTaskPool, pos: &Pos) {
+        let chunk = match self.chunks.get(pos) {
+            Some(chunk) => chunk.clone(),
+            None => return,
+        };
+        let generated = self.generated_chunks.contains(pos);
+        let mut pulled = false;
+        let mut changed_neighbors = vec![];
+        for offset in neighbor_offsets() {
+            let neighbor_pos = *pos + offset;
+            // the generator already gave both the same voxels where they overlap
+            if generated && self.generated_chunks.contains(&neighbor_pos) {
+                continue;
+            }
+            if let Some(neighbor) = self.chunks.get(&neighbor_pos) {
+                let mut neighbor = neighbor.lock();
+                let mut chunk = chunk.lock();
+                pulled |= copy_border(&neighbor, &mut chunk);
+                if copy_border(&chunk, &mut neighbor) {
+                    changed_neighbors.push(neighbor_pos);
+                }
+            } else if let Some(compressed) = self.compressed_chunks.get(&neighbor_pos) {
+                let mut neighbor = compressed.decompress(self.compress_byteorder);
+                let mut chunk = chunk.lock();
+                pulled |= copy_border(&neighbor, &mut chunk);
+                if copy_border(&chunk, &mut neighbor) {
+                    self.compressed_chunks.remove(&neighbor_pos);
+                    self.chunks
+                        .insert(neighbor_pos, Arc::new(Mutex::new(neighbor)));
+                    changed_neighbors.push(neighbor_pos);
+                }
+            }
+        }
+        if pulled {
+            self.generated_chunks.remove(pos);
+        }
+        for neighbor_pos in changed_neighbors {
+            self.generated_chunks.remove(&neighbor_pos);
+            if self.meshing && self.visible_chunks.contains(&neighbor_pos) {
+                self.make_dirty(&neighbor_pos);
+            } else {
+                self.compress_chunk(pool.clone(), neighbor_pos);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk_map::ChunkMap;
+    use avoxel_chunk::Chunk;
+    use avoxel_math::Pos;
+    use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+    use std::time::{Duration, Instant};
+
+    /// Stone up to y 10 with its padding filled with dirt, like a generator
+    /// that decorates past the border of the chunk
+    fn decorated(pos: Pos) -> Chunk {
+        let mut chunk = Chunk::new(pos, 0);
+        let extent = chunk.extent();
+        chunk.fill_area(2, extent.min, extent.max + 1);
+        chunk.fill_area(0, Pos::new(extent.min.x, 11, extent.min.z), extent.max + 1);
+        let origin = pos * 64;
+        chunk.fill_area(1, origin, origin + Pos::new(64, 11, 64));
+        chunk
+    }
+
+    fn padding_voxel(chunk_map: &ChunkMap, chunk_pos: Pos, pos: Pos) -> u32 {
+        chunk_map.chunks[&chunk_pos].lock().get_voxel(pos)
+    }
+
+    #[test]
+    fn padding_mirrors_neighbors() {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.insert_chunk(pool.clone(), decorated(Pos::new(0, 0, 0)));
+        // the neighbors get compressed, the padding has to reach them as well
+        let start = Instant::now();
+        while !chunk_map.compressing_chunks.is_empty() {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "compression timed out"
+            );
+            for (compressed_chunk, token, _) in chunk_map.compression_channels.rx.clone().try_iter()
+            {
+                chunk_map.store_compressed_chunk(compressed_chunk, &token);
+            }
+        }
+        assert!(chunk_map.compressed_chunks.contains_key(&Pos::new(0, 0, 0)));
+        chunk_map.insert_chunk(pool.clone(), decorated(Pos::new(1, 0, 0)));
+        chunk_map.insert_chunk(pool.clone(), decorated(Pos::new(1, 0, 1)));
+
+        // shared faces
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 0), Pos::new(64, 5, 5)),
+            1
+        );
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(1, 0, 0), Pos::new(63, 5, 5)),
+            1
+        );
+        // a shared edge
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 0), Pos::new(64, 5, 64)),
+            1
+        );
+        // neighbors that aren't loaded keep the padding of the generator
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 0), Pos::new(-1, 5, 5)),
+            2
+        );
+
+        // an empty chunk pulls in its padding with its first voxel
+        chunk_map.insert_chunk(pool.clone(), Chunk::new(Pos::new(0, 0, 1), 0));
+        chunk_map.set_voxel(3, &Pos::new(10, 20, 70));
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 1), Pos::new(64, 5, 70)),
+            1
+        );
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 1), Pos::new(5, 5, 63)),
+            1
+        );
+    }
+
+    #[test]
+    fn generated_chunks_keep_their_padding() {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.insert_generated_chunk(pool.clone(), decorated(Pos::new(0, 0, 0)));
+        chunk_map.insert_generated_chunk(pool.clone(), decorated(Pos::new(1, 0, 0)));
+        // the generator gave both chunks their padding, so it isn't synced
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 0), Pos::new(64, 5, 5)),
+            2
+        );
+        assert!(chunk_map.generated_chunks.contains(&Pos::new(0, 0, 0)));
+
+        // edits on the border change the padding of the chunk next to it as well
+        chunk_map.set_voxel(3, &Pos::new(64, 20, 5));
+        assert!(!chunk_map.generated_chunks.contains(&Pos::new(0, 0, 0)));
+        assert!(!chunk_map.generated_chunks.contains(&Pos::new(1, 0, 0)));
+
+        // generated chunks are synced with edited ones
+        chunk_map.insert_generated_chunk(pool.clone(), decorated(Pos::new(2, 0, 0)));
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(2, 0, 0), Pos::new(127, 5, 5)),
+            1
+        );
+
+        // and so are chunks from elsewhere
+        chunk_map.insert_chunk(pool, decorated(Pos::new(0, 0, 1)));
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 0), Pos::new(5, 5, 64)),
+            1
+        );
+        assert_eq!(
+            padding_voxel(&chunk_map, Pos::new(0, 0, 1), Pos::new(5, 5, 63)),
+            1
+        );
+    }
+}
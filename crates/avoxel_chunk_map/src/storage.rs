@@ -0,0 +1,271 @@
+use crate::block_ticks::PendingTick;
+use avoxel_blocks::{BlockLibrary, BlockPalette};
+use avoxel_chunk::{voxel_from_parts, Chunk, Lz4CompressedChunk, Voxel, VoxelBits};
+use avoxel_math::Pos;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+/// Version of the `StoredChunk` layout, files of other versions aren't loaded
+pub const STORAGE_FORMAT_VERSION: u32 = 4;
+
+/// A chunk as it's stored on disk
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredChunk {
+    /// Stays the first field so the version can be read before the rest
+    pub format_version: u32,
+    pub chunk: Lz4CompressedChunk,
+    pub pending_ticks: Vec<PendingTick>,
+    /// Names of the block ids in the chunk
+    pub palette: BlockPalette,
+}
+
+impl StoredChunk {
+    pub fn new(
+        chunk: Lz4CompressedChunk,
+        pending_ticks: Vec<PendingTick>,
+        palette: BlockPalette,
+    ) -> Self {
+        Self {
+            format_version: STORAGE_FORMAT_VERSION,
+            chunk,
+            pending_ticks,
+            palette,
+        }
+    }
+}
+
+/// What the voxels of a saved block become
+enum RestoredBlock {
+    /// Keeps the state bits
+    Block(u32),
+    Voxel(Voxel),
+}
+
+/// Maps the block ids of a stored chunk to the ids of the block library, block ids
+/// change when blocks are added or removed from the library. Blocks that aren't in
+/// the library become placeholders that keep the index of their name in their state
+/// bits, see `BlockPalette::unknown`. Returns the names of these blocks.
+pub(crate) fn restore_block_ids(
+    chunk: &mut Chunk,
+    palette: &BlockPalette,
+    block_library: &BlockLibrary,
+) -> Vec<String> {
+    let placeholder = block_library.placeholder_block_id();
+    let mut unknown: Vec<String> = vec![];
+    let mut restore = |name: &str| match block_library.get_block_id(name) {
+        Some(block_id) => RestoredBlock::Block(block_id),
+        None => {
+            let index = match unknown.iter().position(|unknown_name| unknown_name == name) {
+                Some(index) => index,
+                None => {
+                    unknown.push(name.to_string());
+                    unknown.len() - 1
+                }
+            };
+            RestoredBlock::Voxel(voxel_from_parts(placeholder, index as u32 + 1))
+        }
+    };
+    let mut saved_placeholder = None;
+    let mut by_id: HashMap<u32, RestoredBlock> = HashMap::default();
+    for (saved_id, name) in palette.entries() {
+        if BlockLibrary::namespaced(name) == BlockLibrary::PLACEHOLDER {
+            saved_placeholder = Some(*saved_id);
+        }
+        by_id.insert(*saved_id, restore(name));
+    }
+    // placeholders of blocks that were already unknown when the chunk was saved
+    let by_state: Vec<RestoredBlock> = palette.unknown().iter().map(|name| restore(name)).collect();
+
+    let unchanged = by_state.is_empty()
+        && by_id.iter().all(|(saved_id, restored)| {
+            matches!(restored, RestoredBlock::Block(block_id) if block_id == saved_id)
+        });
+    if unchanged {
+        return unknown;
+    }
+    let apply = |restored: &RestoredBlock, state: u32| match restored {
+        RestoredBlock::Block(block_id) => voxel_from_parts(*block_id, state),
+        RestoredBlock::Voxel(voxel) => *voxel,
+    };
+    chunk.remap_voxels(|voxel| {
+        if Some(voxel.id()) == saved_placeholder && voxel.state() > 0 {
+            if let Some(restored) = by_state.get(voxel.state() as usize - 1) {
+                // the state bits of unknown blocks aren't kept
+                return apply(restored, 0);
+            }
+        }
+        match by_id.get(&voxel.id()) {
+            Some(restored) => apply(restored, voxel.state()),
+            None => voxel,
+        }
+    });
+    unknown
+}
+
+/// Stores compressed chunks on disk, one file per chunk.
+/// Chunks are stored the way they were compressed so the byteorder of the
+/// `ChunkMap` has to stay the same for the files to be read back.
+#[derive(Clone)]
+pub struct ChunkStorage {
+    dir: PathBuf,
+}
+
+impl ChunkStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn chunk_path(&self, pos: &Pos) -> PathBuf {
+        self.dir
+            .join(format!("{}_{}_{}.chunk", pos.x, pos.y, pos.z))
+    }
+
+    pub fn contains(&self, pos: &Pos) -> bool {
+        self.chunk_path(pos).is_file()
+    }
+
+    pub fn save(&self, stored_chunk: &StoredChunk) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let bytes = bincode::serialize(stored_chunk)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(self.chunk_path(&stored_chunk.chunk.pos), bytes)
+    }
+
+    /// Returns `None` if the chunk was never stored and an error if it was
+    /// stored with another `STORAGE_FORMAT_VERSION`
+    pub fn load(&self, pos: &Pos) -> io::Result<Option<StoredChunk>> {
+        let bytes = match fs::read(self.chunk_path(pos)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let format_version: u32 =
+            bincode::deserialize(&bytes).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        if format_version != STORAGE_FORMAT_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "chunk was stored with format version {}, expected {}",
+                    format_version, STORAGE_FORMAT_VERSION
+                ),
+            ));
+        }
+        bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        block_ticks::PendingTick,
+        storage::{restore_block_ids, ChunkStorage, StoredChunk},
+    };
+    use avoxel_blocks::{Block, BlockLibrary, BlockPalette};
+    use avoxel_chunk::{Chunk, VoxelBits};
+    use avoxel_math::Pos;
+    use std::{fs, io::ErrorKind};
+
+    fn storage(name: &str) -> ChunkStorage {
+        let dir = std::env::temp_dir().join(format!("avoxel_storage_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        ChunkStorage::new(dir)
+    }
+
+    fn stored_chunk(pos: Pos) -> StoredChunk {
+        let mut chunk = Chunk::new(pos, 0);
+        chunk.set_voxel(1, pos * 64 + Pos::new(3, 4, 5));
+        let block_library = BlockLibrary::default();
+        StoredChunk::new(
+            chunk.compress(10, true),
+            vec![PendingTick {
+                pos: pos * 64,
+                delay: 3,
+                priority: -1,
+            }],
+            BlockPalette::new(chunk.block_ids(), &block_library),
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let storage = storage("round_trip");
+        let pos = Pos::new(1, -2, 3);
+        assert!(storage.load(&pos).unwrap().is_none());
+        storage.save(&stored_chunk(pos)).unwrap();
+        assert!(storage.contains(&pos));
+
+        let loaded = storage.load(&pos).unwrap().unwrap();
+        let chunk = loaded.chunk.decompress(true);
+        assert_eq!(chunk.pos, pos);
+        assert_eq!(chunk.get_voxel(pos * 64 + Pos::new(3, 4, 5)), 1);
+        assert_eq!(chunk.get_voxel(pos * 64), 0);
+        assert_eq!(loaded.pending_ticks.len(), 1);
+        assert_eq!(loaded.pending_ticks[0].delay, 3);
+        assert_eq!(loaded.pending_ticks[0].priority, -1);
+    }
+
+    fn block_library(names: &[&str]) -> BlockLibrary {
+        let mut block_library = BlockLibrary::new();
+        for name in names {
+            block_library.add_block(Block {
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+        block_library.add_placeholder();
+        block_library
+    }
+
+    #[test]
+    fn unknown_blocks_keep_their_names() {
+        let with_ores = block_library(&["air", "stone", "copper", "mymod:tin"]);
+        let without_ores = block_library(&["air", "dirt", "stone"]);
+        let placeholder = without_ores.placeholder_block_id();
+        let origin = Pos::new(0, 0, 0);
+        let mut chunk = Chunk::new(origin, 0);
+        chunk.set_voxel(1u32.with_level(2), Pos::new(1, 0, 0));
+        chunk.set_voxel(2, Pos::new(2, 0, 0));
+        chunk.set_voxel(3, Pos::new(3, 0, 0));
+
+        // loaded without the ores they become placeholders
+        let palette = BlockPalette::new(chunk.block_ids(), &with_ores);
+        let unknown = restore_block_ids(&mut chunk, &palette, &without_ores);
+        assert_eq!(unknown, vec!["core:copper", "mymod:tin"]);
+        assert_eq!(chunk.get_voxel(Pos::new(1, 0, 0)), 2u32.with_level(2));
+        assert_eq!(chunk.get_voxel(Pos::new(2, 0, 0)).id(), placeholder);
+        assert_eq!(chunk.get_voxel(Pos::new(3, 0, 0)).id(), placeholder);
+
+        // saved again and loaded without the ores they stay placeholders
+        let palette = BlockPalette::new(chunk.block_ids(), &without_ores).with_unknown(unknown);
+        let mut reloaded = chunk.clone();
+        let unknown = restore_block_ids(&mut reloaded, &palette, &without_ores);
+        assert_eq!(unknown, vec!["core:copper", "mymod:tin"]);
+        assert_eq!(reloaded.voxels, chunk.voxels);
+
+        // the ores come back once they are in the library again
+        let unknown = restore_block_ids(&mut chunk, &palette, &with_ores);
+        assert!(unknown.is_empty());
+        assert_eq!(chunk.get_voxel(Pos::new(1, 0, 0)), 1u32.with_level(2));
+        assert_eq!(chunk.get_voxel(Pos::new(2, 0, 0)), 2);
+        assert_eq!(chunk.get_voxel(Pos::new(3, 0, 0)), 3);
+        assert_eq!(chunk.get_voxel(Pos::new(4, 0, 0)), 0);
+    }
+
+    #[test]
+    fn other_format_versions_are_not_loaded() {
+        let storage = storage("format_version");
+        let pos = Pos::new(0, 0, 0);
+        let mut stored_chunk = stored_chunk(pos);
+        stored_chunk.format_version += 1;
+        storage.save(&stored_chunk).unwrap();
+        let error = storage.load(&pos).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
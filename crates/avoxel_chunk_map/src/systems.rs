@@ -1,89 +1,273 @@
 use crate::{
-    chunk_map::{ChunkMap, ChunkState},
-    chunk_map_diagnostics::{CHUNK_COMPRESSION, COMPRESSION_TIMES, GEN_TIMES},
+    chunk_map::{ChunkMap, ChunkMemoryUsage, ChunkState},
+    chunk_map_diagnostics::{
+        CHUNK_COMPRESSION, CHUNK_MEMORY, COMPRESSED_CHUNK_MEMORY, COMPRESSION_TIMES, GEN_TIMES,
+    },
     chunk_viewer::{ChunkViewer, ChunkViewerMoveEvent},
+    chunk_worlds::{ChunkWorlds, WorldId},
+    storage::restore_block_ids,
 };
 use avoxel_blocks::BlockLibrary;
-use bevy::{diagnostic::Diagnostics, prelude::*, tasks::AsyncComputeTaskPool};
-use parking_lot::Mutex;
+use avoxel_chunk::{LodChunk, VoxelBits, CHUNK_SIZE};
+use avoxel_math::Pos;
+use bevy_diagnostic::Diagnostics;
+use bevy_ecs::prelude::*;
+use bevy_log::error;
+use bevy_tasks::AsyncComputeTaskPool;
 use std::{mem::size_of, sync::Arc, time::Instant};
 
-pub fn update_block_library(block_library: Res<BlockLibrary>, mut chunk_map: ResMut<ChunkMap>) {
-    if !block_library.is_changed() {
-        return;
+/// Gives the worlds without their own block library the `BlockLibrary` resource
+pub fn update_block_library(
+    block_library: Res<BlockLibrary>,
+    mut shared_block_library: Local<Arc<BlockLibrary>>,
+    mut worlds: ResMut<ChunkWorlds>,
+) {
+    if block_library.is_changed() {
+        let mut block_library = block_library.clone();
+        block_library.add_placeholder();
+        *shared_block_library = Arc::new(block_library);
+    }
+    for (_, chunk_map) in worlds.iter_mut() {
+        if !chunk_map.shared_block_library
+            || Arc::ptr_eq(&chunk_map.block_library, &shared_block_library)
+        {
+            continue;
+        }
+        chunk_map.block_library = shared_block_library.clone();
+        if chunk_map.meshing {
+            chunk_map.dirty_chunks = chunk_map.visible_chunks.clone();
+        }
     }
-    chunk_map.block_library = Arc::new(block_library.clone());
-    chunk_map.dirty_chunks = chunk_map.visible_chunks.clone();
 }
 
 pub fn update_visible_chunks(
-    mut chunk_map: ResMut<ChunkMap>,
+    mut worlds: ResMut<ChunkWorlds>,
     mut move_event_reader: EventReader<ChunkViewerMoveEvent>,
-    viewers: Query<&ChunkViewer>,
+    viewers: Query<(&ChunkViewer, Option<&WorldId>)>,
 ) {
-    if let Some(_event) = move_event_reader.iter().last() {
-        chunk_map.visible_chunks.clear();
-        for viewer in viewers.iter() {
-            chunk_map.visible_chunks.extend(viewer.get_visible_chunks());
-        }
-        // get a list of chunks to remove
-        let mut chunks_to_remove = vec![];
-        for pos in chunk_map.chunk_keys() {
-            if !chunk_map.visible_chunks.contains(pos) {
-                chunks_to_remove.push(*pos);
-            }
+    let mut moved_worlds: Vec<WorldId> = move_event_reader.iter().map(|event| event.0).collect();
+    // changed lod settings need the levels to be picked again
+    moved_worlds.extend(
+        worlds
+            .iter()
+            .filter(|(_, chunk_map)| chunk_map.lod_dirty)
+            .map(|(world, _)| world),
+    );
+    moved_worlds.sort_unstable();
+    moved_worlds.dedup();
+    for world in moved_worlds {
+        let chunk_map = match worlds.get_mut(world) {
+            Some(chunk_map) => chunk_map,
+            None => continue,
+        };
+        let world_viewers: Vec<&ChunkViewer> = viewers
+            .iter()
+            .filter(|(_, viewer_world)| viewer_world.copied().unwrap_or_default() == world)
+            .map(|(viewer, _)| viewer)
+            .collect();
+        update_world_visible_chunks(chunk_map, &world_viewers);
+    }
+}
+
+fn update_world_visible_chunks(chunk_map: &mut ChunkMap, viewers: &[&ChunkViewer]) {
+    let previous_visible_chunks = std::mem::take(&mut chunk_map.visible_chunks);
+    chunk_map.simulated_chunks.clear();
+    chunk_map.viewer_positions.clear();
+    for viewer in viewers {
+        chunk_map.visible_chunks.extend(viewer.get_visible_chunks());
+        chunk_map
+            .simulated_chunks
+            .extend(viewer.get_simulated_chunks());
+        chunk_map.viewer_positions.push(viewer.get_pos());
+    }
+    chunk_map.update_lod_levels(viewers);
+    // loaded chunks that came into render distance need a mesh
+    if chunk_map.meshing {
+        let newly_visible: Vec<Pos> = chunk_map
+            .visible_chunks
+            .iter()
+            .filter(|pos| !previous_visible_chunks.contains(*pos) && chunk_map.is_meshable(pos))
+            .copied()
+            .collect();
+        for pos in &newly_visible {
+            chunk_map.make_dirty(pos);
         }
-        // remove chunks
-        for pos in &chunks_to_remove {
-            chunk_map.remove_chunk(pos);
+    }
+    // chunks outside of render distance don't get meshed
+    let visible_chunks = &chunk_map.visible_chunks;
+    chunk_map
+        .dirty_chunks
+        .retain(|pos| visible_chunks.contains(pos));
+    // get a list of chunks to remove
+    let mut chunks_to_remove = vec![];
+    for pos in chunk_map.chunk_keys() {
+        if !chunk_map.simulated_chunks.contains(pos) {
+            chunks_to_remove.push(*pos);
         }
     }
+    // remove chunks, modified chunks that fail to save stay loaded
+    for pos in &chunks_to_remove {
+        chunk_map.remove_chunk(pos);
+    }
+    // cancel chunks that are still being generated
+    let cancelled: Vec<Pos> = chunk_map
+        .loading_chunk_keys()
+        .filter(|pos| {
+            !chunk_map.simulated_chunks.contains(*pos) && !chunk_map.lod_only_chunks.contains(*pos)
+        })
+        .copied()
+        .collect();
+    for pos in &cancelled {
+        chunk_map.cancel_chunk_tasks(pos);
+    }
+    // re-prioritize loading around the new viewer positions
+    chunk_map.load_queue_dirty = true;
 }
 
 pub fn gen_chunks_system(
     pool: Res<AsyncComputeTaskPool>,
-    mut chunk_map: ResMut<ChunkMap>,
+    mut worlds: ResMut<ChunkWorlds>,
     mut diagnostics: ResMut<Diagnostics>,
 ) {
-    let visible_chunks = chunk_map.visible_chunks.clone();
-    for pos in &visible_chunks {
-        match chunk_map.chunk_state(&pos.clone()) {
+    for (_, chunk_map) in worlds.iter_mut() {
+        gen_chunks(&pool, chunk_map, &mut diagnostics);
+    }
+}
+
+fn gen_chunks(
+    pool: &AsyncComputeTaskPool,
+    chunk_map: &mut ChunkMap,
+    diagnostics: &mut Diagnostics,
+) {
+    if chunk_map.load_queue_dirty {
+        chunk_map.rebuild_load_queue();
+    }
+    let mut spawned = 0;
+    while chunk_map.generating && spawned < chunk_map.gen_tasks_per_frame {
+        let pos = match chunk_map.load_queue.pop() {
+            Some(pos) => pos,
+            None => break,
+        };
+        let lod_only = chunk_map.lod_only_chunks.contains(&pos);
+        if !chunk_map.simulated_chunks.contains(&pos) && !lod_only {
+            continue;
+        }
+        match chunk_map.chunk_state(&pos) {
             ChunkState::Unloaded => {}
             _ => continue,
         }
-        chunk_map.set_chunk_state_loading(&pos.clone());
+        spawned += 1;
+        let token = chunk_map.set_chunk_state_loading(&pos);
         let sender = chunk_map.gen_channels.tx.clone();
-        let pos = *pos;
         let generator = (chunk_map.generator).clone();
+        let storage = chunk_map.storage.clone();
+        let byteorder = chunk_map.get_byteorder();
+        let block_library = chunk_map.block_library.clone();
+        // only the downsampled voxels of lod only chunks are kept
+        let lod = match &chunk_map.lod {
+            Some(lod) if lod_only => Some((chunk_map.lod_level(&pos), lod.voting)),
+            _ => None,
+        };
+        let lod_sender = chunk_map.lod_channels.tx.clone();
         pool.spawn(async move {
+            if token.is_cancelled() {
+                return;
+            }
             let start_instant = Instant::now();
+            // chunks that were saved are loaded instead of generated
+            let (chunk, generated, pending_ticks, unknown_blocks) =
+                match storage.map(|storage| storage.load(&pos)) {
+                    Some(Ok(Some(stored_chunk))) => {
+                        let mut chunk = stored_chunk.chunk.decompress(byteorder);
+                        let unknown_blocks =
+                            restore_block_ids(&mut chunk, &stored_chunk.palette, &block_library);
+                        (chunk, false, stored_chunk.pending_ticks, unknown_blocks)
+                    }
+                    // a generated chunk would overwrite the stored one once it's saved, so the
+                    // chunk stays loading and isn't simulated until it leaves the view
+                    Some(Err(e)) => {
+                        error!("failed to load chunk {:?}, leaving it unloaded: {}", pos, e);
+                        return;
+                    }
+                    _ => ((generator)(&pos), true, vec![], vec![]),
+                };
+            if token.is_cancelled() {
+                return;
+            }
+            if let Some((level, voting)) = lod {
+                let lod_chunk = LodChunk::from_chunk(&chunk, level, voting, |id| {
+                    block_library.is_solid_cube(id)
+                });
+                lod_sender
+                    .send((lod_chunk, token, start_instant))
+                    .expect("Failed to send lod chunk");
+                return;
+            }
             sender
-                .send(((generator)(&pos), start_instant))
+                .send((
+                    chunk,
+                    generated,
+                    pending_ticks,
+                    unknown_blocks,
+                    token,
+                    start_instant,
+                ))
                 .expect("Failed to send chunk");
         })
         .detach();
     }
 
     let receiver = chunk_map.gen_channels.rx.clone();
-    for (chunk, start_instant) in receiver.try_iter() {
+    for (chunk, generated, pending_ticks, unknown_blocks, token, start_instant) in
+        receiver.try_iter()
+    {
         let pos = chunk.pos;
-        chunk_map.set_chunk_state_loaded(&pos);
-        chunk_map
-            .chunks
-            .insert(chunk.pos, Arc::new(Mutex::new(chunk)));
-        diagnostics.add_measurement(GEN_TIMES, start_instant.elapsed().as_secs_f64());
-        if cfg!(feature = "mesher") {
-            chunk_map.make_dirty(&pos);
+        // discard chunks that left the view while they were generated
+        if !chunk_map.set_chunk_state_loaded(&pos, &token) {
+            continue;
+        }
+        if generated {
+            chunk_map.insert_generated_chunk(pool.clone(), chunk);
         } else {
-            // if not meshing chunk we can compress the chunk already
-            chunk_map.compress_chunk(pool.clone(), pos);
+            chunk_map.insert_chunk(pool.clone(), chunk);
         }
+        // kept so the names of the unknown blocks are saved again
+        if !unknown_blocks.is_empty() && chunk_map.contains_chunk(&pos) {
+            chunk_map.unknown_blocks.insert(pos, unknown_blocks);
+        }
+        // lod only chunks don't keep their voxels so they can't be ticked
+        if !pending_ticks.is_empty() && chunk_map.contains_chunk(&pos) {
+            chunk_map.tick_scheduler.restore(&pending_ticks);
+            // the stored ticks are out of date once they run
+            chunk_map.modified_chunks.insert(pos);
+        }
+        diagnostics.add_measurement(GEN_TIMES, start_instant.elapsed().as_secs_f64());
+    }
+
+    let receiver = chunk_map.lod_channels.rx.clone();
+    for (lod_chunk, token, start_instant) in receiver.try_iter() {
+        if !chunk_map.set_chunk_state_loaded(&lod_chunk.pos, &token) {
+            continue;
+        }
+        chunk_map.insert_lod_chunk(lod_chunk);
+        diagnostics.add_measurement(GEN_TIMES, start_instant.elapsed().as_secs_f64());
     }
 }
 
 pub fn store_decompressed_compressed_chunks(
-    mut chunk_map: ResMut<ChunkMap>,
+    pool: Res<AsyncComputeTaskPool>,
+    mut worlds: ResMut<ChunkWorlds>,
     mut diagnostics: ResMut<Diagnostics>,
+) {
+    for (_, chunk_map) in worlds.iter_mut() {
+        store_chunks(&pool, chunk_map, &mut diagnostics);
+    }
+}
+
+fn store_chunks(
+    pool: &AsyncComputeTaskPool,
+    chunk_map: &mut ChunkMap,
+    diagnostics: &mut Diagnostics,
 ) {
     for chunk in chunk_map.decompression_channels.rx.clone().try_iter() {
         let chunk_key = chunk.lock().pos;
@@ -91,19 +275,160 @@ pub fn store_decompressed_compressed_chunks(
         // and store the decompressed chunk.
         if chunk_map.compressed_chunks.remove(&chunk_key).is_some() {
             chunk_map.chunks.insert(chunk_key, chunk);
+            // chunks that were decompressed for reads aren't meshed and would stay decompressed
+            chunk_map.compression_queue.insert(chunk_key);
         }
     }
+    chunk_map.compress_queued_chunks(pool);
 
     // store compressed chunks
-    for (compressed_chunk, start_instant) in chunk_map.compression_channels.rx.clone().try_iter() {
+    for (compressed_chunk, token, start_instant) in
+        chunk_map.compression_channels.rx.clone().try_iter()
+    {
+        let pos = compressed_chunk.pos;
         let compressed_size =
             compressed_chunk.compressed_voxels.len() as f64 * size_of::<u8>() as f64;
-        // TODO: make sure chunk hasn't changed since compression began. If chunk changed just discard the compressed chunk.
-        chunk_map.remove_chunk(&compressed_chunk.pos);
-        chunk_map
-            .compressed_chunks
-            .insert(compressed_chunk.pos, compressed_chunk);
+        // discard compressed chunks that were unloaded or modified while compressing
+        if !chunk_map.store_compressed_chunk(compressed_chunk, &token) {
+            // a modified chunk has to be compressed again unless meshing takes care of it
+            if chunk_map.needs_compression(&pos) {
+                chunk_map.compress_chunk(pool.clone(), pos);
+            }
+            continue;
+        }
         diagnostics.add_measurement(COMPRESSION_TIMES, start_instant.elapsed().as_secs_f64());
         diagnostics.add_measurement(CHUNK_COMPRESSION, 1_149_984_f64 / compressed_size);
     }
 }
+
+/// Reports the memory used by the chunks of all worlds and evicts chunks
+/// of worlds that are over their memory budget
+pub fn chunk_memory_system(mut worlds: ResMut<ChunkWorlds>, mut diagnostics: ResMut<Diagnostics>) {
+    let mut total = ChunkMemoryUsage::default();
+    for (_, chunk_map) in worlds.iter_mut() {
+        let usage = chunk_map.memory_usage();
+        chunk_map.evict_to_budget(&usage);
+        total.chunk_bytes += usage.chunk_bytes;
+        total.compressed_bytes += usage.compressed_bytes;
+        total.lod_bytes += usage.lod_bytes;
+    }
+    diagnostics.add_measurement(CHUNK_MEMORY, total.chunk_bytes as f64);
+    diagnostics.add_measurement(COMPRESSED_CHUNK_MEMORY, total.compressed_bytes as f64);
+}
+
+pub fn block_tick_system(mut worlds: ResMut<ChunkWorlds>) {
+    for (_, chunk_map) in worlds
+        .iter_mut()
+        .filter(|(_, chunk_map)| chunk_map.simulating)
+    {
+        run_block_ticks(chunk_map);
+    }
+}
+
+/// Runs the scheduled ticks that are due and random ticks in the simulated chunks.
+/// Ticks of positions that aren't loaded are dropped.
+fn run_block_ticks(chunk_map: &mut ChunkMap) {
+    let block_library = chunk_map.block_library.clone();
+    for pos in chunk_map.tick_scheduler.advance() {
+        let voxel = match chunk_map.read_chunk_containing_pos(&pos, |chunk| chunk.get_voxel(pos)) {
+            Some(voxel) => voxel,
+            None => continue,
+        };
+        if let Some(handler) = block_library.get_tick_handler(voxel.id()) {
+            handler(chunk_map, pos, voxel);
+        }
+    }
+
+    if !block_library.has_random_tick_handlers() {
+        return;
+    }
+    let chunk_keys: Vec<Pos> = chunk_map
+        .simulated_chunks
+        .iter()
+        .filter(|pos| chunk_map.contains_chunk(pos))
+        .copied()
+        .collect();
+    for chunk_key in chunk_keys {
+        // pick every position first so the chunk is only read once, compressed
+        // chunks are read from a temporary copy so they stay compressed
+        let positions: Vec<Pos> = (0..chunk_map.tick_scheduler.random_ticks_per_chunk)
+            .map(|_| chunk_key * CHUNK_SIZE + chunk_map.tick_scheduler.random_local_pos())
+            .collect();
+        let random_ticks =
+            chunk_map.read_chunk_containing_pos(&(chunk_key * CHUNK_SIZE), |chunk| {
+                positions
+                    .iter()
+                    .map(|pos| (*pos, chunk.get_voxel(*pos)))
+                    .collect::<Vec<_>>()
+            });
+        for (pos, voxel) in random_ticks.unwrap_or_default() {
+            if let Some(handler) = block_library.get_random_tick_handler(voxel.id()) {
+                handler(chunk_map, pos, voxel);
+            }
+        }
+    }
+}
+
+pub fn fluid_system(mut worlds: ResMut<ChunkWorlds>) {
+    for (_, chunk_map) in worlds
+        .iter_mut()
+        .filter(|(_, chunk_map)| chunk_map.simulating)
+    {
+        chunk_map.update_fluids();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{chunk_map::ChunkMap, systems::run_block_ticks};
+    use avoxel_blocks::{BlockLibrary, BlockTickContext};
+    use avoxel_chunk::Chunk;
+    use avoxel_math::Pos;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn block_ticks_keep_chunks_compressed() {
+        let random_ticks = Arc::new(AtomicUsize::new(0));
+        let scheduled_ticks = Arc::new(AtomicUsize::new(0));
+        let counter = random_ticks.clone();
+        let scheduled_counter = scheduled_ticks.clone();
+        let mut block_library = BlockLibrary::default();
+        block_library
+            .set_random_tick_handler(
+                1,
+                Arc::new(move |_: &mut dyn BlockTickContext, _: Pos, _: u32| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }),
+            )
+            .set_tick_handler(
+                1,
+                Arc::new(move |_: &mut dyn BlockTickContext, _: Pos, _: u32| {
+                    scheduled_counter.fetch_add(1, Ordering::Relaxed);
+                }),
+            );
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.set_block_library(Arc::new(block_library));
+        let chunk_pos = Pos::new(0, 0, 0);
+        let mut chunk = Chunk::new(chunk_pos, 0);
+        chunk.fill_area(1, Pos::new(-1, -1, -1), Pos::new(65, 65, 65));
+        let compressed_chunk = chunk.compress(10, chunk_map.get_byteorder());
+        chunk_map
+            .compressed_chunks
+            .insert(chunk_pos, compressed_chunk);
+        chunk_map.simulated_chunks.insert(chunk_pos);
+        chunk_map.tick_scheduler.schedule(Pos::new(5, 5, 5), 3, 0);
+
+        for _ in 0..10 {
+            run_block_ticks(&mut chunk_map);
+        }
+        let expected = 10 * chunk_map.tick_scheduler.random_ticks_per_chunk as usize;
+        assert_eq!(random_ticks.load(Ordering::Relaxed), expected);
+        assert_eq!(scheduled_ticks.load(Ordering::Relaxed), 1);
+        assert!(chunk_map.compressed_chunks.contains_key(&chunk_pos));
+        assert!(chunk_map.chunks.is_empty());
+        assert_eq!(chunk_map.decompression_channels.rx.try_iter().count(), 0);
+    }
+}
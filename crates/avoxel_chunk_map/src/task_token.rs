@@ -0,0 +1,29 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Used to cancel chunk work that runs in other threads.
+/// Every spawned task gets its own token so a late result can be told apart
+/// from the result of a newer task for the same chunk.
+#[derive(Clone, Default)]
+pub struct TaskToken(Arc<AtomicBool>);
+
+impl TaskToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if both tokens were handed out for the same task
+    pub fn same_task(&self, other: &TaskToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
@@ -1,3 +1,8 @@
+mod top_down_map;
 mod voxel_ray_cast;
 
+pub use top_down_map::{
+    block_map_colors, load_texture_atlas, render_top_down_map, render_top_down_map_from_generator,
+    TopDownMap,
+};
 pub use voxel_ray_cast::{voxel_ray_cast, VoxelRayCastResult};
@@ -0,0 +1,314 @@
+use crate::chunk_map::ChunkMap;
+use avoxel_blocks::{Block, BlockLibrary};
+use avoxel_chunk::{Chunk, Voxel, VoxelBits, CHUNK_SIZE};
+use avoxel_math::{DivFloor, Extent3, Pos};
+use parking_lot::Mutex;
+use std::{path::Path, sync::Arc};
+
+/// Colour used for blocks that have no `map_color` and no texture to average
+const FALLBACK_COLOR: [u8; 3] = [255, 0, 255];
+
+/// A top-down image of the world.
+/// Each pixel is the colour of the highest non-air block in that column.
+pub struct TopDownMap {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA8 pixels, row by row starting at the lowest z
+    pub pixels: Vec<u8>,
+    /// Height of the highest non-air block for each pixel
+    pub heights: Vec<Option<i32>>,
+}
+
+impl TopDownMap {
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> image::ImageResult<()> {
+        image::save_buffer(
+            path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ColorType::Rgba8,
+        )
+    }
+}
+
+/// Loads a texture atlas image for `block_map_colors`.
+/// Returns the RGBA8 pixels and the width of the atlas, which is the texture size.
+pub fn load_texture_atlas<P: AsRef<Path>>(path: P) -> image::ImageResult<(Vec<u8>, u32)> {
+    let atlas = image::open(path)?.to_rgba8();
+    let width = atlas.width();
+    Ok((atlas.into_raw(), width))
+}
+
+/// Returns the map colour of every block in the library indexed by block id.
+/// Blocks without a `map_color` use the average colour of their top texture
+/// if a texture atlas is given.
+/// * `texture_atlas` - RGBA8 pixels of the block textures stacked vertically,
+///   each `texture_size` of the library wide and high
+pub fn block_map_colors(
+    block_library: &BlockLibrary,
+    texture_atlas: Option<&[u8]>,
+) -> Vec<[u8; 3]> {
+    let texture_size = block_library.get_texture_size();
+    (0..block_library.block_count())
+        .map(|block_id| {
+            let block = block_library.get_block(block_id);
+            if let Some(color) = block.map_color {
+                return color;
+            }
+            texture_atlas
+                .and_then(|atlas| {
+                    average_layer_color(atlas, texture_size, block.texture_ids[Block::TOP])
+                })
+                .unwrap_or(FALLBACK_COLOR)
+        })
+        .collect()
+}
+
+/// Works with both a stacked 2d texture and a texture that was already
+/// reinterpreted as an array since the layers are stored the same way.
+fn average_layer_color(texture_atlas: &[u8], texture_size: u32, layer: u32) -> Option<[u8; 3]> {
+    let side = texture_size as usize;
+    let layer_size = side * side * 4;
+    let start = layer as usize * layer_size;
+    let pixels = texture_atlas.get(start..start + layer_size)?;
+
+    let mut sum = [0u64; 3];
+    let mut count = 0u64;
+    for pixel in pixels.chunks_exact(4) {
+        // skip fully transparent pixels so cut-out textures don't get darkened
+        if pixel[3] == 0 {
+            continue;
+        }
+        sum[0] += pixel[0] as u64;
+        sum[1] += pixel[1] as u64;
+        sum[2] += pixel[2] as u64;
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    Some([
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    ])
+}
+
+/// Renders the loaded chunks of the `ChunkMap` within `extent`.
+/// Chunks that aren't loaded are left transparent.
+/// * `extent` - inclusive world coordinates. Columns are scanned from `max.y` down to `min.y`
+/// * `colors` - block colours indexed by block id, see `block_map_colors`
+pub fn render_top_down_map(
+    chunk_map: &ChunkMap,
+    extent: Extent3,
+    colors: &[[u8; 3]],
+    height_shading: bool,
+) -> TopDownMap {
+    render_columns(
+        extent,
+        |pos| {
+            if let Some(chunk) = chunk_map.chunks.get(&pos) {
+                return Some(chunk.clone());
+            }
+            // decompress without storing the chunk, a map can cover a lot of chunks
+            chunk_map
+                .compressed_chunks
+                .get(&pos)
+                .map(|compressed_chunk| {
+                    Arc::new(Mutex::new(
+                        compressed_chunk.decompress(chunk_map.get_byteorder()),
+                    ))
+                })
+        },
+        colors,
+        height_shading,
+    )
+}
+
+/// Same as `render_top_down_map` but generates the chunks with `generator` instead.
+/// Chunks are only generated until every column in them has found a block.
+pub fn render_top_down_map_from_generator(
+    generator: &dyn Fn(&Pos) -> Chunk,
+    extent: Extent3,
+    colors: &[[u8; 3]],
+    height_shading: bool,
+) -> TopDownMap {
+    render_columns(
+        extent,
+        |pos| Some(Arc::new(Mutex::new(generator(&pos)))),
+        colors,
+        height_shading,
+    )
+}
+
+fn render_columns(
+    extent: Extent3,
+    mut get_chunk: impl FnMut(Pos) -> Option<Arc<Mutex<Chunk>>>,
+    colors: &[[u8; 3]],
+    height_shading: bool,
+) -> TopDownMap {
+    let width = (extent.max.x - extent.min.x + 1).max(0);
+    let height = (extent.max.z - extent.min.z + 1).max(0);
+    let mut columns: Vec<Option<(i32, Voxel)>> = vec![None; (width * height) as usize];
+
+    let chunk_min = extent.min.div_floor(CHUNK_SIZE);
+    let chunk_max = extent.max.div_floor(CHUNK_SIZE);
+    for cz in chunk_min.z..=chunk_max.z {
+        for cx in chunk_min.x..=chunk_max.x {
+            let min_x = (cx * CHUNK_SIZE).max(extent.min.x);
+            let max_x = (cx * CHUNK_SIZE + CHUNK_SIZE - 1).min(extent.max.x);
+            let min_z = (cz * CHUNK_SIZE).max(extent.min.z);
+            let max_z = (cz * CHUNK_SIZE + CHUNK_SIZE - 1).min(extent.max.z);
+            let mut unresolved = ((max_x - min_x + 1) * (max_z - min_z + 1)) as usize;
+
+            for cy in (chunk_min.y..=chunk_max.y).rev() {
+                if unresolved == 0 {
+                    break;
+                }
+                let chunk = match get_chunk(Pos::new(cx, cy, cz)) {
+                    Some(chunk) => chunk,
+                    None => continue,
+                };
+                let chunk = chunk.lock();
+                if chunk.is_empty() && chunk.ambient_voxel == Block::AIR {
+                    continue;
+                }
+                let max_y = (cy * CHUNK_SIZE + CHUNK_SIZE - 1).min(extent.max.y);
+                let min_y = (cy * CHUNK_SIZE).max(extent.min.y);
+                for z in min_z..=max_z {
+                    for x in min_x..=max_x {
+                        let i = ((z - extent.min.z) * width + (x - extent.min.x)) as usize;
+                        if columns[i].is_some() {
+                            continue;
+                        }
+                        for y in (min_y..=max_y).rev() {
+                            let voxel = chunk.get_voxel(Pos::new(x, y, z));
+                            if voxel != Block::AIR {
+                                columns[i] = Some((y, voxel));
+                                unresolved -= 1;
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let y_range = (extent.max.y - extent.min.y).max(1) as f32;
+    let mut pixels = Vec::with_capacity(columns.len() * 4);
+    for column in &columns {
+        match column {
+            Some((y, voxel)) => {
+                let color = colors
+                    .get(voxel.id() as usize)
+                    .copied()
+                    .unwrap_or(FALLBACK_COLOR);
+                // lower columns get darker and higher columns brighter
+                let shade = if height_shading {
+                    0.6 + 0.8 * (*y - extent.min.y) as f32 / y_range
+                } else {
+                    1.
+                };
+                for c in &color {
+                    pixels.push((*c as f32 * shade).min(255.) as u8);
+                }
+                pixels.push(255);
+            }
+            None => pixels.extend(&[0, 0, 0, 0]),
+        }
+    }
+
+    TopDownMap {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+        heights: columns.iter().map(|c| c.map(|(y, _)| y)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        block_map_colors, render_top_down_map, render_top_down_map_from_generator, FALLBACK_COLOR,
+    };
+    use crate::chunk_map::ChunkMap;
+    use avoxel_blocks::{Block, BlockLibrary};
+    use avoxel_chunk::Chunk;
+    use avoxel_math::{Extent3, Pos};
+    use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+
+    const COLORS: [[u8; 3]; 3] = [[0, 0, 0], [10, 20, 30], [40, 50, 60]];
+
+    /// Block 1 at the top of column 1,2 and block 2 at the top of column 3,3
+    fn generate(pos: &Pos) -> Chunk {
+        let mut chunk = Chunk::new(*pos, 0);
+        if *pos == Pos::new(0, 0, 0) {
+            chunk.fill_area(1, Pos::new(1, 0, 2), Pos::new(2, 6, 3));
+            chunk.fill_area(1, Pos::new(3, 0, 3), Pos::new(4, 10, 4));
+            chunk.set_voxel(2, Pos::new(3, 10, 3));
+        }
+        chunk
+    }
+
+    fn extent() -> Extent3 {
+        Extent3 {
+            min: Pos::new(0, 0, 0),
+            max: Pos::new(3, 15, 3),
+        }
+    }
+
+    fn pixel(pixels: &[u8], x: usize, z: usize) -> &[u8] {
+        let i = (z * 4 + x) * 4;
+        &pixels[i..i + 4]
+    }
+
+    #[test]
+    fn renders_the_highest_blocks() {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        chunk_map.insert_chunk(pool, generate(&Pos::new(0, 0, 0)));
+        let map = render_top_down_map(&chunk_map, extent(), &COLORS, false);
+        let generated = render_top_down_map_from_generator(&generate, extent(), &COLORS, false);
+        for map in [map, generated].iter() {
+            assert_eq!((map.width, map.height), (4, 4));
+            assert_eq!(map.heights[2 * 4 + 1], Some(5));
+            assert_eq!(map.heights[3 * 4 + 3], Some(10));
+            assert_eq!(map.heights.iter().filter(|h| h.is_some()).count(), 2);
+            assert_eq!(pixel(&map.pixels, 1, 2), &[10, 20, 30, 255]);
+            assert_eq!(pixel(&map.pixels, 3, 3), &[40, 50, 60, 255]);
+            assert_eq!(pixel(&map.pixels, 0, 0), &[0, 0, 0, 0]);
+        }
+
+        // the lower column gets darker, the higher one brighter
+        let shaded = render_top_down_map_from_generator(&generate, extent(), &COLORS, true);
+        assert!(pixel(&shaded.pixels, 1, 2)[2] < 30);
+        assert!(pixel(&shaded.pixels, 3, 3)[2] > 60);
+    }
+
+    #[test]
+    fn averages_top_textures() {
+        let mut block_library = BlockLibrary::new();
+        block_library.set_texture_size(2);
+        block_library
+            .add_block(Block::default())
+            .add_block(Block {
+                texture_ids: [1, 0, 0, 0, 0, 0],
+                ..Default::default()
+            })
+            .add_block(Block {
+                map_color: Some([1, 2, 3]),
+                ..Default::default()
+            });
+        // the first layer is transparent, the second has one transparent pixel
+        let mut atlas = vec![0; 16];
+        atlas.extend(&[
+            10, 20, 30, 255, 30, 40, 50, 255, 99, 99, 99, 0, 20, 30, 40, 255,
+        ]);
+
+        let colors = block_map_colors(&block_library, Some(&atlas[..]));
+        assert_eq!(colors, vec![FALLBACK_COLOR, [20, 30, 40], [1, 2, 3]]);
+        let colors = block_map_colors(&block_library, None);
+        assert_eq!(colors, vec![FALLBACK_COLOR, FALLBACK_COLOR, [1, 2, 3]]);
+    }
+}
@@ -1,5 +1,5 @@
 use avoxel_math::Pos;
-use bevy::prelude::Vec3;
+use bevy_math::Vec3;
 
 pub struct VoxelRayCastResult {
     pub hit_pos: Vec3,
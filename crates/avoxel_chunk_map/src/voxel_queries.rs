@@ -0,0 +1,261 @@
+use crate::chunk_map::ChunkMap;
+use avoxel_blocks::Block;
+use avoxel_chunk::{Chunk, Voxel, VoxelBits, CHUNK_SIZE};
+use avoxel_math::{DivFloor, Extent3, Pos};
+
+/// The voxels in a 3x3x3 cube around a position
+pub struct VoxelNeighborhood {
+    pub center: Pos,
+    /// Indexed by `x + y * 3 + z * 9` with coordinates relative to `center` + 1
+    pub voxels: [Voxel; 27],
+}
+
+impl VoxelNeighborhood {
+    /// * `offset` - offset from the center, each axis between -1 and 1
+    pub fn get(&self, offset: Pos) -> Voxel {
+        self.voxels[Self::index(offset)]
+    }
+
+    pub fn center_voxel(&self) -> Voxel {
+        self.voxels[13]
+    }
+
+    fn index(offset: Pos) -> usize {
+        debug_assert!(offset.x.abs() <= 1 && offset.y.abs() <= 1 && offset.z.abs() <= 1);
+        ((offset.x + 1) + (offset.y + 1) * 3 + (offset.z + 1) * 9) as usize
+    }
+}
+
+/// Read queries that lock every chunk once instead of once per voxel.
+/// Chunks that aren't loaded are skipped, compressed chunks are read from a
+/// temporary copy and stay compressed.
+impl ChunkMap {
+    /// Calls `f` with the part of `extent` that lies within each loaded chunk.
+    /// * `extent` - inclusive world coordinates
+    pub fn for_each_chunk_in_extent(&self, extent: Extent3, mut f: impl FnMut(&Chunk, Extent3)) {
+        let chunk_min = extent.min.div_floor(CHUNK_SIZE);
+        let chunk_max = extent.max.div_floor(CHUNK_SIZE);
+        for cx in chunk_min.x..=chunk_max.x {
+            for cz in chunk_min.z..=chunk_max.z {
+                for cy in chunk_min.y..=chunk_max.y {
+                    let chunk_key = Pos::new(cx, cy, cz);
+                    let chunk_extent = Extent3 {
+                        min: (chunk_key * CHUNK_SIZE).map2(extent.min, i32::max),
+                        max: (chunk_key * CHUNK_SIZE + CHUNK_SIZE - 1).map2(extent.max, i32::min),
+                    };
+                    self.read_chunk_containing_pos(&(chunk_key * CHUNK_SIZE), |chunk| {
+                        f(chunk, chunk_extent)
+                    });
+                }
+            }
+        }
+    }
+
+    /// Calls `f` for every loaded voxel in `extent`, chunk by chunk
+    /// * `extent` - inclusive world coordinates
+    pub fn for_each_voxel(&self, extent: Extent3, mut f: impl FnMut(Pos, Voxel)) {
+        self.for_each_chunk_in_extent(extent, |chunk, chunk_extent| {
+            for x in chunk_extent.min.x..=chunk_extent.max.x {
+                for z in chunk_extent.min.z..=chunk_extent.max.z {
+                    for y in chunk_extent.min.y..=chunk_extent.max.y {
+                        let pos = Pos::new(x, y, z);
+                        f(pos, chunk.get_voxel(pos));
+                    }
+                }
+            }
+        });
+    }
+
+    /// Returns the voxels around `pos`. Thanks to the chunk padding
+    /// this only needs the chunk containing `pos`.
+    pub fn get_neighborhood(&self, pos: &Pos) -> Option<VoxelNeighborhood> {
+        self.read_chunk_containing_pos(pos, |chunk| {
+            let mut voxels = [chunk.ambient_voxel; 27];
+            for z in -1..=1 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        let offset = Pos::new(x, y, z);
+                        voxels[VoxelNeighborhood::index(offset)] = chunk.get_voxel(*pos + offset);
+                    }
+                }
+            }
+            VoxelNeighborhood {
+                center: *pos,
+                voxels,
+            }
+        })
+    }
+
+    /// Returns the position and voxel of the highest non air voxel in the column at `x`, `z`
+    /// between `min_y` and `max_y` inclusive.
+    pub fn highest_solid_voxel(
+        &self,
+        x: i32,
+        z: i32,
+        min_y: i32,
+        max_y: i32,
+    ) -> Option<(Pos, Voxel)> {
+        let chunk_min_y = min_y.div_euclid(CHUNK_SIZE);
+        let chunk_max_y = max_y.div_euclid(CHUNK_SIZE);
+        for cy in (chunk_min_y..=chunk_max_y).rev() {
+            let top = (cy * CHUNK_SIZE + CHUNK_SIZE - 1).min(max_y);
+            let bottom = (cy * CHUNK_SIZE).max(min_y);
+            let highest =
+                self.read_chunk_containing_pos(&Pos::new(x, cy * CHUNK_SIZE, z), |chunk| {
+                    if chunk.is_empty() && chunk.ambient_voxel.id() == Block::AIR {
+                        return None;
+                    }
+                    (bottom..=top).rev().find_map(|y| {
+                        let pos = Pos::new(x, y, z);
+                        let voxel = chunk.get_voxel(pos);
+                        if voxel.id() != Block::AIR {
+                            Some((pos, voxel))
+                        } else {
+                            None
+                        }
+                    })
+                });
+            if let Some(Some(highest)) = highest {
+                return Some(highest);
+            }
+        }
+        None
+    }
+
+    /// Counts the loaded voxels of a block in `extent`, whatever their state bits
+    /// * `extent` - inclusive world coordinates
+    pub fn count_voxels(&self, extent: Extent3, block_id: u32) -> usize {
+        let mut count = 0;
+        self.for_each_chunk_in_extent(extent, |chunk, chunk_extent| {
+            if chunk.is_empty() {
+                // empty chunks are filled with the ambient voxel
+                if chunk.ambient_voxel.id() == block_id {
+                    let size = chunk_extent.max - chunk_extent.min + 1;
+                    count += (size.x * size.y * size.z) as usize;
+                }
+                return;
+            }
+            for x in chunk_extent.min.x..=chunk_extent.max.x {
+                for z in chunk_extent.min.z..=chunk_extent.max.z {
+                    for y in chunk_extent.min.y..=chunk_extent.max.y {
+                        if chunk.get_voxel(Pos::new(x, y, z)).id() == block_id {
+                            count += 1;
+                        }
+                    }
+                }
+            }
+        });
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk_map::ChunkMap;
+    use avoxel_chunk::{Chunk, VoxelBits};
+    use avoxel_math::{Extent3, Pos};
+    use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+    use std::time::{Duration, Instant};
+
+    /// Two chunks next to each other with stone up to y 9, the one at the origin compressed
+    fn chunk_map() -> ChunkMap {
+        let pool = AsyncComputeTaskPool(TaskPool::new());
+        let mut chunk_map = ChunkMap::default();
+        for pos in [Pos::new(0, 0, 0), Pos::new(1, 0, 0)].iter() {
+            let mut chunk = Chunk::new(*pos, 0);
+            chunk.fill_area(1, *pos * 64, *pos * 64 + Pos::new(64, 10, 64));
+            chunk_map.insert_chunk(pool.clone(), chunk);
+        }
+        let start = Instant::now();
+        while chunk_map.chunks.len() > 1 {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "compression timed out"
+            );
+            for (compressed_chunk, token, _) in chunk_map.compression_channels.rx.clone().try_iter()
+            {
+                if compressed_chunk.pos == Pos::new(0, 0, 0) {
+                    chunk_map.store_compressed_chunk(compressed_chunk, &token);
+                }
+            }
+        }
+        assert!(chunk_map.compressed_chunks.contains_key(&Pos::new(0, 0, 0)));
+        // a voxel with state bits
+        chunk_map.set_voxel(2u32.with_level(3), &Pos::new(70, 10, 5));
+        chunk_map
+    }
+
+    fn assert_still_compressed(chunk_map: &ChunkMap) {
+        assert!(chunk_map.compressed_chunks.contains_key(&Pos::new(0, 0, 0)));
+        assert!(!chunk_map.chunks.contains_key(&Pos::new(0, 0, 0)));
+        assert_eq!(chunk_map.decompression_channels.rx.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn count_voxels() {
+        let chunk_map = chunk_map();
+        let extent = Extent3 {
+            min: Pos::new(60, 8, 0),
+            max: Pos::new(71, 12, 9),
+        };
+        // the voxels at y 8 and 9 are stone in both chunks
+        assert_eq!(chunk_map.count_voxels(extent, 1), 12 * 2 * 10);
+        // state bits don't matter
+        assert_eq!(chunk_map.count_voxels(extent, 2), 1);
+        assert_eq!(chunk_map.count_voxels(extent, 0), 12 * 3 * 10 - 1);
+        // chunks that aren't loaded are skipped
+        let unloaded = Extent3 {
+            min: Pos::new(0, 0, -10),
+            max: Pos::new(0, 0, 0),
+        };
+        assert_eq!(chunk_map.count_voxels(unloaded, 1), 1);
+        assert_still_compressed(&chunk_map);
+    }
+
+    #[test]
+    fn highest_solid_voxel() {
+        let chunk_map = chunk_map();
+        assert_eq!(
+            chunk_map.highest_solid_voxel(5, 5, -20, 100),
+            Some((Pos::new(5, 9, 5), 1))
+        );
+        assert_eq!(
+            chunk_map.highest_solid_voxel(70, 5, -20, 100),
+            Some((Pos::new(70, 10, 5), 2u32.with_level(3)))
+        );
+        assert_eq!(
+            chunk_map.highest_solid_voxel(5, 5, 0, 8),
+            Some((Pos::new(5, 8, 5), 1))
+        );
+        assert_eq!(chunk_map.highest_solid_voxel(5, 5, 10, 100), None);
+        assert_eq!(chunk_map.highest_solid_voxel(5, -5, -20, 100), None);
+        assert_still_compressed(&chunk_map);
+    }
+
+    #[test]
+    fn neighborhood() {
+        let chunk_map = chunk_map();
+        // the padding holds the voxels across the border
+        let neighborhood = chunk_map.get_neighborhood(&Pos::new(63, 9, 5)).unwrap();
+        assert_eq!(neighborhood.center_voxel(), 1);
+        assert_eq!(neighborhood.get(Pos::new(1, 0, 0)), 1);
+        assert_eq!(neighborhood.get(Pos::new(1, 1, 0)), 0);
+        assert_eq!(neighborhood.get(Pos::new(0, -1, 1)), 1);
+        assert!(chunk_map.get_neighborhood(&Pos::new(5, 5, -5)).is_none());
+        assert_still_compressed(&chunk_map);
+
+        let mut voxels = 0;
+        chunk_map.for_each_voxel(
+            Extent3 {
+                min: Pos::new(62, 9, 5),
+                max: Pos::new(65, 9, 5),
+            },
+            |pos, voxel| {
+                assert_eq!(voxel, 1, "{:?}", pos);
+                voxels += 1;
+            },
+        );
+        assert_eq!(voxels, 4);
+        assert_still_compressed(&chunk_map);
+    }
+}
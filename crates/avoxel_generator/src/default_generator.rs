@@ -1,4 +1,4 @@
-use avoxel_chunk::Chunk;
+use avoxel_chunk::{Chunk, ColumnTint};
 use avoxel_math::Pos;
 use noise::{NoiseFn, Perlin, Seedable};
 
@@ -22,6 +22,7 @@ pub fn generate_chunk(pos: &Pos) -> Chunk {
     perlin.set_seed(1);
     for z in extent.min.z..extent.max.z {
         for x in extent.min.x..extent.max.x {
+            chunk.set_column_tint(x, z, column_tint(&perlin, x, z));
             // height
             let h = perlin.get([x as f64 * noise_scale, z as f64 * noise_scale]);
             let mut h = (noise_factor * h).floor() as i32;
@@ -35,3 +36,58 @@ pub fn generate_chunk(pos: &Pos) -> Chunk {
     }
     chunk
 }
+
+/// Grass and leaves are greener in warm columns and more yellow in cold ones
+fn column_tint(perlin: &Perlin, x: i32, z: i32) -> ColumnTint {
+    let temperature = (perlin.get([x as f64 * 0.002, 100.0, z as f64 * 0.002]) + 1.0) / 2.0;
+    let mix = |cold: u8, warm: u8| (cold as f64 + (warm as f64 - cold as f64) * temperature) as u8;
+    ColumnTint {
+        grass: [mix(191, 121), mix(183, 192), mix(85, 90)],
+        foliage: [mix(174, 89), mix(164, 174), mix(42, 48)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::default_generator::generate_chunk;
+    use avoxel_chunk::{ColumnTint, CHUNK_SIZE, CHUNK_SIZE_WITH_PADDING};
+    use avoxel_math::Pos;
+
+    #[test]
+    fn tints_every_column() {
+        let chunk = generate_chunk(&Pos::new(0, 0, 0));
+        assert_eq!(
+            chunk.column_tints.len(),
+            (CHUNK_SIZE_WITH_PADDING * CHUNK_SIZE_WITH_PADDING) as usize
+        );
+        // tints lie between the cold and the warm tint
+        for tint in chunk.column_tints.iter() {
+            assert_ne!(*tint, ColumnTint::default());
+            assert!((183..=192).contains(&tint.grass[1]), "{:?}", tint);
+            assert!((42..=48).contains(&tint.foliage[2]), "{:?}", tint);
+        }
+    }
+
+    #[test]
+    fn padding_tints_match_the_neighbor() {
+        let chunk = generate_chunk(&Pos::new(0, 0, 0));
+        let neighbor = generate_chunk(&Pos::new(1, 0, 0));
+        for z in -1..=CHUNK_SIZE {
+            for x in [CHUNK_SIZE - 1, CHUNK_SIZE].iter() {
+                assert_eq!(
+                    chunk.get_column_tint(*x, z),
+                    neighbor.get_column_tint(*x, z),
+                    "{} {}",
+                    x,
+                    z
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_out_of_the_terrain_have_no_tints() {
+        assert!(generate_chunk(&Pos::new(0, 5, 0)).column_tints.is_empty());
+        assert!(generate_chunk(&Pos::new(0, -5, 0)).column_tints.is_empty());
+    }
+}
@@ -4,8 +4,8 @@ use avoxel_blocks::BlockLibrary;
 use avoxel_chunk::Chunk;
 use avoxel_generator::default_generator;
 use avoxel_math::Pos;
-use avoxel_mesher::mesher_culling::generate_mesh_culled;
-use bevy::prelude::Vec3;
+use avoxel_mesher::{generate_mesh_culled, generate_mesh_smooth};
+use bevy_math::Vec3;
 use criterion::Criterion;
 use noise::{NoiseFn, Seedable};
 use rand_core::{RngCore, SeedableRng};
@@ -25,6 +25,24 @@ fn bench_mesher_culling(c: &mut Criterion) {
     });
 }
 
+fn bench_mesher_smooth(c: &mut Criterion) {
+    let chunk = generate_data();
+    let block_library = Arc::new(BlockLibrary::default());
+    c.bench_function("mesher_smooth", |b| {
+        b.iter(|| generate_mesh_smooth(&chunk, block_library.clone()))
+    });
+
+    // partially filled voxels move the surface between the voxel centers
+    let mut chunk = generate_data();
+    for i in 0..chunk.voxels.len() {
+        let pos = chunk.index_to_pos(i);
+        chunk.set_density((i * 7 % 256) as u8, pos);
+    }
+    c.bench_function("mesher_smooth_densities", |b| {
+        b.iter(|| generate_mesh_smooth(&chunk, block_library.clone()))
+    });
+}
+
 fn bench_random_rotation(c: &mut Criterion) {
     let perlin = noise::Perlin::new();
     perlin.set_seed(1);
@@ -68,5 +86,10 @@ fn random_rotation_custom(pos: Vec3) -> f32 {
     (r % 4 * 90) as f32
 }
 
-criterion_group!(benches, bench_mesher_culling, bench_random_rotation,);
+criterion_group!(
+    benches,
+    bench_mesher_culling,
+    bench_mesher_smooth,
+    bench_random_rotation,
+);
 criterion_main!(benches);
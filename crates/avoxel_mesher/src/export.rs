@@ -0,0 +1,335 @@
+use crate::mesher::{generate_mesh_culled, ChunkMeshData};
+use avoxel_blocks::BlockLibrary;
+use avoxel_chunk::CHUNK_SIZE;
+use avoxel_chunk_map::ChunkMap;
+use avoxel_math::Pos;
+use serde_json::json;
+use std::io::{self, Write};
+
+// The exporters place the chunks at their world positions. Texture coordinates
+// address the texture of the block library, which has the layers of the texture
+// array stacked from top to bottom. Texture animations are exported at their first frame.
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+const GL_FLOAT: u32 = 5126;
+const GL_UNSIGNED_INT: u32 = 5125;
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GL_NEAREST: u32 = 9728;
+
+/// Meshes the loaded chunks between `min` and `max`, inclusive, in chunk positions
+pub fn mesh_region(chunk_map: &ChunkMap, min: Pos, max: Pos) -> Vec<(Pos, ChunkMeshData)> {
+    let mut meshes = vec![];
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                let pos = Pos::new(x, y, z);
+                let mesh = match chunk_map.chunks.get(&pos) {
+                    Some(chunk) => {
+                        generate_mesh_culled(&chunk.lock(), chunk_map.block_library.clone())
+                    }
+                    None => match chunk_map.compressed_chunks.get(&pos) {
+                        Some(compressed) => generate_mesh_culled(
+                            &compressed.decompress(chunk_map.get_byteorder()),
+                            chunk_map.block_library.clone(),
+                        ),
+                        None => None,
+                    },
+                };
+                if let Some(mesh) = mesh {
+                    meshes.push((pos, mesh));
+                }
+            }
+        }
+    }
+    meshes
+}
+
+/// Writes chunk meshes as Wavefront OBJ with the block tints as vertex colors
+pub fn write_obj<W: Write>(
+    writer: &mut W,
+    meshes: &[(Pos, ChunkMeshData)],
+    block_library: &BlockLibrary,
+) -> io::Result<()> {
+    if let Some(texture_path) = block_library.get_texture_path() {
+        writeln!(writer, "# texture {}", texture_path)?;
+    }
+    // OBJ indices start at 1 and count over the whole file
+    let mut first_vertex = 1;
+    for (pos, mesh) in meshes {
+        writeln!(writer, "o chunk_{}_{}_{}", pos.x, pos.y, pos.z)?;
+        let origin = *pos * CHUNK_SIZE;
+        for (position, tint) in mesh.positions.iter().zip(mesh.tints.iter()) {
+            let [r, g, b] = unpack_tint(*tint);
+            writeln!(
+                writer,
+                "v {} {} {} {} {} {}",
+                position[0] + origin.x as f32,
+                position[1] + origin.y as f32,
+                position[2] + origin.z as f32,
+                r,
+                g,
+                b
+            )?;
+        }
+        for vertex in 0..mesh.positions.len() {
+            let [u, v] = atlas_uv(mesh, vertex, block_library);
+            // OBJ texture coordinates start at the bottom left
+            writeln!(writer, "vt {} {}", u, 1. - v)?;
+        }
+        for normal in &mesh.normals {
+            writeln!(writer, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+        }
+        for triangle in mesh.indices.chunks(3) {
+            write!(writer, "f")?;
+            for index in triangle {
+                let i = index + first_vertex;
+                write!(writer, " {}/{}/{}", i, i, i)?;
+            }
+            writeln!(writer)?;
+        }
+        first_vertex += mesh.positions.len() as u32;
+    }
+    Ok(())
+}
+
+/// Writes chunk meshes as binary glTF, one node per chunk. The block tints are
+/// written as vertex colors and the texture of the block library is referenced
+/// by its path if it has one.
+pub fn write_glb<W: Write>(
+    writer: &mut W,
+    meshes: &[(Pos, ChunkMeshData)],
+    block_library: &BlockLibrary,
+) -> io::Result<()> {
+    let mut buffer = GlbBuffer::default();
+    let mut gltf_meshes = vec![];
+    let mut nodes = vec![];
+    let material = block_library.get_texture_path().map(|_| 0);
+    for (pos, mesh) in meshes {
+        let count = mesh.positions.len();
+        let positions = buffer.add_accessor(
+            floats(mesh.positions.iter().flatten().copied()),
+            count,
+            "VEC3",
+            GL_FLOAT,
+            GL_ARRAY_BUFFER,
+        );
+        let normals = buffer.add_accessor(
+            floats(mesh.normals.iter().flatten().copied()),
+            count,
+            "VEC3",
+            GL_FLOAT,
+            GL_ARRAY_BUFFER,
+        );
+        let uvs = buffer.add_accessor(
+            floats((0..count).flat_map(|vertex| atlas_uv(mesh, vertex, block_library).to_vec())),
+            count,
+            "VEC2",
+            GL_FLOAT,
+            GL_ARRAY_BUFFER,
+        );
+        // glTF colors are linear, tints are given in sRGB
+        let colors = buffer.add_accessor(
+            floats(
+                mesh.tints
+                    .iter()
+                    .flat_map(|tint| unpack_tint(*tint).to_vec())
+                    .map(|channel| channel.powf(2.2)),
+            ),
+            count,
+            "VEC3",
+            GL_FLOAT,
+            GL_ARRAY_BUFFER,
+        );
+        let indices = buffer.add_accessor(
+            mesh.indices
+                .iter()
+                .flat_map(|i| i.to_le_bytes().to_vec())
+                .collect(),
+            mesh.indices.len(),
+            "SCALAR",
+            GL_UNSIGNED_INT,
+            GL_ELEMENT_ARRAY_BUFFER,
+        );
+        let (min, max) = bounds(&mesh.positions);
+        buffer.accessors[positions]["min"] = json!(min);
+        buffer.accessors[positions]["max"] = json!(max);
+
+        let mut primitive = json!({
+            "attributes": {
+                "POSITION": positions,
+                "NORMAL": normals,
+                "TEXCOORD_0": uvs,
+                "COLOR_0": colors,
+            },
+            "indices": indices,
+        });
+        if let Some(material) = material {
+            primitive["material"] = json!(material);
+        }
+        gltf_meshes.push(json!({ "primitives": [primitive] }));
+        let origin = *pos * CHUNK_SIZE;
+        nodes.push(json!({
+            "name": format!("chunk_{}_{}_{}", pos.x, pos.y, pos.z),
+            "mesh": gltf_meshes.len() - 1,
+            "translation": [origin.x, origin.y, origin.z],
+        }));
+    }
+
+    let mut gltf = json!({
+        "asset": { "version": "2.0", "generator": "avoxel" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<usize>>() }],
+        "nodes": nodes,
+        "meshes": gltf_meshes,
+        "accessors": buffer.accessors,
+        "bufferViews": buffer.buffer_views,
+        "buffers": [{ "byteLength": buffer.bin.len() }],
+    });
+    if let Some(texture_path) = block_library.get_texture_path() {
+        gltf["images"] = json!([{ "uri": texture_path }]);
+        gltf["samplers"] = json!([{ "magFilter": GL_NEAREST, "minFilter": GL_NEAREST }]);
+        gltf["textures"] = json!([{ "source": 0, "sampler": 0 }]);
+        gltf["materials"] = json!([{
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": 0.0,
+            },
+        }]);
+    }
+
+    // both chunks of the file are padded to 4 bytes
+    let mut json = serde_json::to_vec(&gltf)?;
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    let mut bin = buffer.bin;
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(length as u32).to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(&GLB_CHUNK_JSON.to_le_bytes())?;
+    writer.write_all(&json)?;
+    writer.write_all(&(bin.len() as u32).to_le_bytes())?;
+    writer.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+    writer.write_all(&bin)?;
+    Ok(())
+}
+
+/// The binary chunk of a glTF file with the views and accessors into it
+#[derive(Default)]
+struct GlbBuffer {
+    bin: Vec<u8>,
+    buffer_views: Vec<serde_json::Value>,
+    accessors: Vec<serde_json::Value>,
+}
+
+impl GlbBuffer {
+    /// Adds a buffer view with an accessor and returns the index of the accessor
+    fn add_accessor(
+        &mut self,
+        bytes: Vec<u8>,
+        count: usize,
+        kind: &str,
+        component: u32,
+        target: u32,
+    ) -> usize {
+        self.buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.bin.extend(bytes);
+        self.accessors.push(json!({
+            "bufferView": self.buffer_views.len() - 1,
+            "componentType": component,
+            "count": count,
+            "type": kind,
+        }));
+        self.accessors.len() - 1
+    }
+}
+
+/// The uv of a vertex in the texture with all layers, (0, 0) is the top left
+fn atlas_uv(mesh: &ChunkMeshData, vertex: usize, block_library: &BlockLibrary) -> [f32; 2] {
+    let layers = block_library.get_texture_count().max(1) as f32;
+    let [u, v] = mesh.uv(vertex);
+    [u, (mesh.layer(vertex) as f32 + v) / layers]
+}
+
+fn unpack_tint(tint: u32) -> [f32; 3] {
+    [
+        (tint >> 16 & 0xFF) as f32 / 255.,
+        (tint >> 8 & 0xFF) as f32 / 255.,
+        (tint & 0xFF) as f32 / 255.,
+    ]
+}
+
+fn floats<I: Iterator<Item = f32>>(values: I) -> Vec<u8> {
+    values
+        .flat_map(|value| value.to_le_bytes().to_vec())
+        .collect()
+}
+
+fn bounds(positions: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for position in positions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        export::{write_glb, write_obj},
+        generate_mesh_culled,
+    };
+    use avoxel_blocks::BlockLibrary;
+    use avoxel_chunk::Chunk;
+    use avoxel_math::Pos;
+    use std::sync::Arc;
+
+    #[test]
+    fn exports_a_block() {
+        let block_library = Arc::new(BlockLibrary::default());
+        let mut chunk = Chunk::new(Pos::new(0, 0, 1), 0);
+        chunk.set_voxel(1, Pos::new(2, 3, 68));
+        let mesh = generate_mesh_culled(&chunk, block_library.clone()).unwrap();
+        assert_eq!(mesh.positions.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        let meshes = vec![(chunk.pos, mesh)];
+
+        let mut obj = vec![];
+        write_obj(&mut obj, &meshes, &block_library).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("v ")).count(),
+            24
+        );
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            12
+        );
+        // positions are in world space
+        assert!(obj.contains("v 2 3 68 "));
+
+        let mut glb = vec![];
+        write_glb(&mut glb, &meshes, &block_library).unwrap();
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(glb.len() % 4, 0);
+        let length = u32::from_le_bytes([glb[8], glb[9], glb[10], glb[11]]);
+        assert_eq!(length as usize, glb.len());
+    }
+}
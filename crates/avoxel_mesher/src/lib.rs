@@ -1,17 +1,33 @@
+#[cfg(feature = "rendering")]
 use avoxel_rendering::AvoxelRenderingPlugin;
+#[cfg(feature = "rendering")]
 use bevy::prelude::*;
 
+mod export;
 mod mesher;
+#[cfg(feature = "rendering")]
 mod state;
+#[cfg(feature = "rendering")]
 mod systems;
 
+pub use export::{mesh_region, write_glb, write_obj};
+#[cfg(feature = "rendering")]
+pub use mesher::Mesher;
+pub use mesher::{
+    generate_mesh_culled, generate_mesh_lod, generate_mesh_smooth, generate_mesh_with_skirts,
+    ChunkMeshData, MeshingMode,
+};
+
+#[cfg(feature = "rendering")]
 pub struct AvoxelMesherPlugin;
 
+#[cfg(feature = "rendering")]
 impl Plugin for AvoxelMesherPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(AvoxelRenderingPlugin)
-            .insert_resource(mesher::Mesher::default())
+            .init_resource::<Mesher>()
             .insert_resource(state::State::default())
+            .add_startup_system(systems::enable_meshing.system())
             .add_system(systems::mesh_dirty_chunks.system())
             .add_system(state::handle_state_system.system());
     }
@@ -1,15 +1,71 @@
+mod block_models;
+mod chunk_mesh_data;
+mod mesh_builder;
 mod mesh_tables;
 mod mesher_culling;
+mod mesher_lod;
+mod mesher_smooth;
+#[cfg(feature = "rendering")]
 mod meshing_channels;
+mod orientation;
 
+#[cfg(feature = "rendering")]
+use avoxel_chunk_map::{TaskToken, WorldId};
+#[cfg(feature = "rendering")]
 use avoxel_math::Pos;
+#[cfg(feature = "rendering")]
 use bevy::{prelude::*, utils::HashMap};
-pub use mesher_culling::generate_mesh_culled;
+pub use chunk_mesh_data::ChunkMeshData;
+pub use mesher_culling::{generate_mesh_culled, generate_mesh_with_skirts};
+pub use mesher_lod::generate_mesh_lod;
+pub use mesher_smooth::generate_mesh_smooth;
+#[cfg(feature = "rendering")]
 pub use meshing_channels::MeshingChannels;
+#[cfg(feature = "rendering")]
+use std::time::Instant;
 
-#[derive(Default)]
+/// How chunks at full detail are meshed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// Blocks with the faces between them culled, see `generate_mesh_culled`
+    Culled,
+    /// A smooth surface for terrain, see `generate_mesh_smooth`
+    Smooth,
+}
+
+impl Default for MeshingMode {
+    fn default() -> Self {
+        MeshingMode::Culled
+    }
+}
+
+#[cfg(feature = "rendering")]
 pub struct Mesher {
-    /// Mesh entities
-    pub mesh_entities: HashMap<Pos, Vec<Entity>>,
+    /// Mesh entities of the chunks of each world
+    pub mesh_entities: HashMap<(WorldId, Pos), Vec<Entity>>,
     pub meshing_channels: MeshingChannels,
+    /// Finished meshes waiting to be spawned. Only the newest mesh of a chunk is kept.
+    pub(crate) finished_meshes: HashMap<(WorldId, Pos), (Option<Mesh>, Instant)>,
+    /// Tokens of the meshing tasks that are currently running
+    pub(crate) meshing_tasks: HashMap<(WorldId, Pos), TaskToken>,
+    /// Max number of meshing tasks spawned per frame and world
+    pub meshing_tasks_per_frame: usize,
+    /// Max number of finished meshes spawned per frame and world
+    pub meshes_per_frame: usize,
+    pub mode: MeshingMode,
+}
+
+#[cfg(feature = "rendering")]
+impl Default for Mesher {
+    fn default() -> Self {
+        Self {
+            mesh_entities: Default::default(),
+            meshing_channels: Default::default(),
+            finished_meshes: Default::default(),
+            meshing_tasks: Default::default(),
+            meshing_tasks_per_frame: 16,
+            meshes_per_frame: 12,
+            mode: Default::default(),
+        }
+    }
 }
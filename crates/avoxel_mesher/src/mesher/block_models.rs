@@ -0,0 +1,212 @@
+use crate::mesher::{
+    mesh_tables::{pack_texture_data, Square},
+    orientation::Orientation,
+};
+use avoxel_blocks::{Block, BlockShape, ModelBox};
+use bevy_math::Vec3;
+
+/// Normals of the faces in the order of `Block::texture_ids`
+const FACE_NORMALS: [[f32; 3]; 6] = [
+    [0., 1., 0.],
+    [1., 0., 0.],
+    [0., -1., 0.],
+    [-1., 0., 0.],
+    [0., 0., 1.],
+    [0., 0., -1.],
+];
+
+pub fn opposite_face(face: usize) -> usize {
+    [
+        Block::BOTTOM,
+        Block::LEFT,
+        Block::TOP,
+        Block::RIGHT,
+        Block::BACK,
+        Block::FRONT,
+    ][face]
+}
+
+fn face_from_normal(normal: [f32; 3]) -> usize {
+    FACE_NORMALS
+        .iter()
+        .position(|n| {
+            (n[0] - normal[0]).abs() < 0.5
+                && (n[1] - normal[1]).abs() < 0.5
+                && (n[2] - normal[2]).abs() < 0.5
+        })
+        .unwrap_or(Block::TOP)
+}
+
+/// Corners of a face of the unit cube in the same order as the `square_*` functions
+fn unit_face_corners(face: usize) -> [[f32; 3]; 4] {
+    match face {
+        Block::TOP => [[1., 1., 0.], [0., 1., 0.], [0., 1., 1.], [1., 1., 1.]],
+        Block::RIGHT => [[1., 0., 0.], [1., 1., 0.], [1., 1., 1.], [1., 0., 1.]],
+        Block::BOTTOM => [[1., 0., 1.], [0., 0., 1.], [0., 0., 0.], [1., 0., 0.]],
+        Block::LEFT => [[0., 0., 1.], [0., 1., 1.], [0., 1., 0.], [0., 0., 0.]],
+        Block::FRONT => [[0., 0., 1.], [1., 0., 1.], [1., 1., 1.], [0., 1., 1.]],
+        _ => [[0., 1., 0.], [1., 1., 0.], [1., 0., 0.], [0., 0., 0.]],
+    }
+}
+
+/// Texture coordinates of a point on a face, matching the uvs of the `square_*` functions
+/// so the texture of a partial face is cut out instead of squished.
+fn face_uv(face: usize, [x, y, z]: [f32; 3]) -> [f32; 2] {
+    match face {
+        Block::TOP => [x, z],
+        Block::RIGHT => [1. - z, 1. - y],
+        Block::BOTTOM => [1. - x, 1. - z],
+        Block::LEFT => [z, 1. - y],
+        Block::FRONT => [x, 1. - y],
+        _ => [1. - x, 1. - y],
+    }
+}
+
+/// Returns true if the box reaches the side of the block on `face`
+fn touches_side(model_box: &ModelBox, face: usize) -> bool {
+    match face {
+        Block::TOP => model_box.max[1] >= 16.,
+        Block::RIGHT => model_box.max[0] >= 16.,
+        Block::BOTTOM => model_box.min[1] <= 0.,
+        Block::LEFT => model_box.min[0] <= 0.,
+        Block::FRONT => model_box.max[2] >= 16.,
+        _ => model_box.min[2] <= 0.,
+    }
+}
+
+/// Returns true if the box covers the whole side of the block on `face`
+fn covers_side(model_box: &ModelBox, face: usize) -> bool {
+    let spans = |axis: usize| model_box.min[axis] <= 0. && model_box.max[axis] >= 16.;
+    let spans_side = match face {
+        Block::TOP | Block::BOTTOM => spans(0) && spans(2),
+        Block::RIGHT | Block::LEFT => spans(1) && spans(2),
+        _ => spans(0) && spans(1),
+    };
+    spans_side && touches_side(model_box, face)
+}
+
+/// The faces of the block that cover the whole side of the block, rotated by the orientation
+pub fn full_faces(block: &Block, orientation: &Orientation) -> [bool; 6] {
+    if block.fluid.is_some() {
+        return [false; 6];
+    }
+    if block.shape.is_cube() {
+        return [true; 6];
+    }
+    let boxes = match block.shape.boxes() {
+        Some(boxes) => boxes,
+        None => return [false; 6],
+    };
+    let mut local_full_faces = [false; 6];
+    for model_box in boxes.iter() {
+        for (face, full) in local_full_faces.iter_mut().enumerate() {
+            *full |= covers_side(model_box, face);
+        }
+    }
+    let mut full_faces = [false; 6];
+    for (face, full) in full_faces.iter_mut().enumerate() {
+        *full = local_full_faces[orientation.local_face(face)];
+    }
+    full_faces
+}
+
+pub struct ModelFace {
+    pub square: Square,
+    /// The side of the block the face lies on, `None` for faces inside the block.
+    /// Only faces on a side can be hidden by a neighbor.
+    pub side: Option<usize>,
+}
+
+/// Returns the faces of a non cube block at `pos`
+pub fn model_faces(block: &Block, orientation: &Orientation, pos: Vec3) -> Vec<ModelFace> {
+    match &block.shape {
+        BlockShape::Cross => cross_faces(block.texture_ids[Block::FRONT], pos),
+        shape => shape
+            .boxes()
+            .map(|boxes| {
+                boxes
+                    .iter()
+                    .flat_map(|model_box| box_faces(block, model_box, orientation, pos))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn box_faces(
+    block: &Block,
+    model_box: &ModelBox,
+    orientation: &Orientation,
+    pos: Vec3,
+) -> Vec<ModelFace> {
+    let texture_ids = model_box.texture_ids.unwrap_or(block.texture_ids);
+    let min = Vec3::from(model_box.min) / 16.;
+    let size = Vec3::from(model_box.max) / 16. - min;
+    let mut faces = Vec::with_capacity(6);
+    for (face, normal) in FACE_NORMALS.iter().enumerate() {
+        let mut verts = [[0.; 3]; 4];
+        let mut uvs = [[0.; 2]; 4];
+        for (i, corner) in unit_face_corners(face).iter().enumerate() {
+            let local = min + Vec3::from(*corner) * size;
+            uvs[i] = face_uv(face, local.into());
+            let rotated = Vec3::from(orientation.rotate_point(local.into())) + pos;
+            verts[i] = rotated.into();
+        }
+        let rotated_normal = orientation.rotate(*normal);
+        let side = if touches_side(model_box, face) {
+            Some(face_from_normal(rotated_normal))
+        } else {
+            None
+        };
+        faces.push(ModelFace {
+            square: Square {
+                verts,
+                norms: [rotated_normal; 4],
+                texture_data: pack_texture_data(uvs, texture_ids[face]),
+            },
+            side,
+        });
+    }
+    faces
+}
+
+/// Two diagonal planes, each with a face on both sides
+fn cross_faces(tex_id: u32, pos: Vec3) -> Vec<ModelFace> {
+    let planes = [
+        (
+            [[0., 0., 0.], [1., 0., 1.], [1., 1., 1.], [0., 1., 0.]],
+            [-0.7071, 0., 0.7071],
+        ),
+        (
+            [[1., 0., 0.], [0., 0., 1.], [0., 1., 1.], [1., 1., 0.]],
+            [-0.7071, 0., -0.7071],
+        ),
+    ];
+    let uvs = [[0., 1.], [1., 1.], [1., 0.], [0., 0.]];
+    let mut faces = Vec::with_capacity(4);
+    for (corners, normal) in planes.iter() {
+        let mut verts = [[0.; 3]; 4];
+        for (i, corner) in corners.iter().enumerate() {
+            verts[i] = (Vec3::from(*corner) + pos).into();
+        }
+        faces.push(ModelFace {
+            square: Square {
+                verts,
+                norms: [*normal; 4],
+                texture_data: pack_texture_data(uvs, tex_id),
+            },
+            side: None,
+        });
+        // the back side has the opposite winding
+        let back_normal = [-normal[0], -normal[1], -normal[2]];
+        faces.push(ModelFace {
+            square: Square {
+                verts: [verts[1], verts[0], verts[3], verts[2]],
+                norms: [back_normal; 4],
+                texture_data: pack_texture_data([uvs[1], uvs[0], uvs[3], uvs[2]], tex_id),
+            },
+            side: None,
+        });
+    }
+    faces
+}
@@ -0,0 +1,79 @@
+#[cfg(feature = "rendering")]
+use bevy::{
+    prelude::Mesh,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        pipeline::PrimitiveTopology,
+    },
+};
+
+#[cfg(feature = "rendering")]
+const ATTRIBUTE_TEXTURE_DATUM: &str = "Texture_Datum";
+#[cfg(feature = "rendering")]
+const ATTRIBUTE_TEXTURE_ANIMATION: &str = "Texture_Animation";
+#[cfg(feature = "rendering")]
+const ATTRIBUTE_TINT: &str = "Vertex_Tint";
+
+/// The mesh of a chunk independent of the engine, positions are relative to the chunk.
+/// Every face has 4 vertices of its own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkMeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// Texture layer in bits 0-11, u in 12-16 and v in 17-21 in 16ths of the texture
+    /// and bit 31 for faces textured by their world position
+    pub texture_data: Vec<u32>,
+    /// Animation of the texture of each vertex as packed by
+    /// `BlockLibrary::texture_animation_data`
+    pub texture_animations: Vec<u32>,
+    /// Tint of each vertex as 0xRRGGBB
+    pub tints: Vec<u32>,
+    /// Triangles, counterclockwise seen from the front
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// The texture layer of a vertex
+    pub fn layer(&self, vertex: usize) -> u32 {
+        self.texture_data[vertex] & 0xFFF
+    }
+
+    /// The uv of a vertex within its texture layer, (0, 0) is the top left
+    pub fn uv(&self, vertex: usize) -> [f32; 2] {
+        let datum = self.texture_data[vertex];
+        [
+            (datum >> 12 & 0x1F) as f32 / 16.,
+            (datum >> 17 & 0x1F) as f32 / 16.,
+        ]
+    }
+}
+
+#[cfg(feature = "rendering")]
+impl From<ChunkMeshData> for Mesh {
+    fn from(data: ChunkMeshData) -> Self {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            VertexAttributeValues::from(data.positions),
+        );
+        mesh.set_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            VertexAttributeValues::from(data.normals),
+        );
+        mesh.set_attribute(
+            ATTRIBUTE_TEXTURE_DATUM,
+            VertexAttributeValues::from(data.texture_data),
+        );
+        mesh.set_attribute(
+            ATTRIBUTE_TEXTURE_ANIMATION,
+            VertexAttributeValues::from(data.texture_animations),
+        );
+        mesh.set_attribute(ATTRIBUTE_TINT, VertexAttributeValues::from(data.tints));
+        mesh.set_indices(Some(Indices::U32(data.indices)));
+        mesh
+    }
+}
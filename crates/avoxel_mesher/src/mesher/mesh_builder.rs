@@ -0,0 +1,126 @@
+use crate::mesher::{chunk_mesh_data::ChunkMeshData, mesh_tables, mesh_tables::Square};
+use avoxel_blocks::{BlockLibrary, TintMode};
+use avoxel_chunk::ColumnTint;
+
+/// Collects the faces of a chunk mesh with the attributes the block shader needs
+pub(crate) struct MeshBuilder<'a> {
+    block_library: &'a BlockLibrary,
+    animation_data: Vec<u32>,
+    vertices: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    texture_data: Vec<u32>,
+    texture_animations: Vec<u32>,
+    tints: Vec<u32>,
+    indices: Vec<u32>,
+}
+
+impl<'a> MeshBuilder<'a> {
+    pub fn new(block_library: &'a BlockLibrary) -> Self {
+        Self {
+            block_library,
+            animation_data: block_library.texture_animation_data(),
+            vertices: vec![],
+            normals: vec![],
+            texture_data: vec![],
+            texture_animations: vec![],
+            tints: vec![],
+            indices: vec![],
+        }
+    }
+
+    pub fn add_square(&mut self, square: &Square, column_tint: &ColumnTint) {
+        let vert_count = self.vertices.len() as u32;
+        self.vertices.extend(&square.verts);
+        self.normals.extend(&square.norms);
+        self.texture_data.extend(&square.texture_data);
+        // the texture id is in the first 12 bits of the texture datum
+        let animation_data = &self.animation_data;
+        self.texture_animations
+            .extend(square.texture_data.iter().map(|datum| {
+                animation_data
+                    .get((datum & 0xFFF) as usize)
+                    .copied()
+                    .unwrap_or(0)
+            }));
+        let block_library = self.block_library;
+        self.tints.extend(square.texture_data.iter().map(|datum| {
+            let tint = block_library.get_block_texture(datum & 0xFFF).tint;
+            pack_tint(tint, column_tint)
+        }));
+        self.indices.extend(&mesh_tables::indices(vert_count));
+    }
+
+    /// Returns `None` if no faces were added
+    pub fn build(self) -> Option<ChunkMeshData> {
+        if self.vertices.is_empty() {
+            return None;
+        }
+        Some(ChunkMeshData {
+            positions: self.vertices,
+            normals: self.normals,
+            texture_data: self.texture_data,
+            texture_animations: self.texture_animations,
+            tints: self.tints,
+            indices: self.indices,
+        })
+    }
+}
+
+/// Packs the tint of a face as 0xRRGGBB
+fn pack_tint(tint: TintMode, column_tint: &ColumnTint) -> u32 {
+    let [r, g, b] = match tint {
+        TintMode::None => [255; 3],
+        TintMode::Grass => column_tint.grass,
+        TintMode::Foliage => column_tint.foliage,
+        TintMode::Fixed(color) => color,
+    };
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate_mesh_culled, mesher::mesh_builder::pack_tint};
+    use avoxel_blocks::{BlockLibrary, BlockTexture, TintMode};
+    use avoxel_chunk::{Chunk, ColumnTint};
+    use avoxel_math::Pos;
+    use std::sync::Arc;
+
+    const COLUMN_TINT: ColumnTint = ColumnTint {
+        grass: [0x10, 0x20, 0x30],
+        foliage: [0x40, 0x50, 0x60],
+    };
+
+    #[test]
+    fn packs_tints() {
+        assert_eq!(pack_tint(TintMode::None, &COLUMN_TINT), 0xFFFFFF);
+        assert_eq!(pack_tint(TintMode::Grass, &COLUMN_TINT), 0x102030);
+        assert_eq!(pack_tint(TintMode::Foliage, &COLUMN_TINT), 0x405060);
+        assert_eq!(
+            pack_tint(TintMode::Fixed([0xAB, 0xCD, 0xEF]), &COLUMN_TINT),
+            0xABCDEF
+        );
+        assert_eq!(pack_tint(TintMode::Grass, &ColumnTint::default()), 0xFFFFFF);
+    }
+
+    #[test]
+    fn faces_take_the_tint_of_their_column() {
+        let mut block_library = BlockLibrary::default();
+        block_library.set_block_textures(vec![(
+            "grass".to_string(),
+            BlockTexture {
+                tint: TintMode::Grass,
+                ..Default::default()
+            },
+        )]);
+        let mut chunk = Chunk::new(Pos::new(0, 0, 0), 0);
+        chunk.set_voxel(1, Pos::new(2, 3, 4));
+        chunk.set_voxel(1, Pos::new(10, 3, 4));
+        chunk.set_column_tint(2, 4, COLUMN_TINT);
+        let mesh = generate_mesh_culled(&chunk, Arc::new(block_library)).unwrap();
+        assert_eq!(mesh.tints.len(), mesh.positions.len());
+        for (position, tint) in mesh.positions.iter().zip(mesh.tints.iter()) {
+            let expected = if position[0] < 5. { 0x102030 } else { 0xFFFFFF };
+            assert_eq!(*tint, expected, "{:?}", position);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use bevy::prelude::Vec3;
+use bevy_math::Vec3;
 
 pub struct Square {
     pub verts: [[f32; 3]; 4],
@@ -279,11 +279,12 @@ fn rotate_uvs(pos: Vec3, uvs: [[f32; 2]; 4]) -> [[f32; 2]; 4] {
 /// Packs uvs and array texture index into 32 bits.
 /// The array texture index is used to pick the right texture
 /// in the shader. It's essentially the texture id.
-fn pack_texture_data(uvs: [[f32; 2]; 4], tex_id: u32) -> [u32; 4] {
+/// Uvs are stored in sixteenths so partial faces of block models can be textured.
+pub fn pack_texture_data(uvs: [[f32; 2]; 4], tex_id: u32) -> [u32; 4] {
     let mut data: [u32; 4] = [0; 4];
     for (i, uv) in uvs.iter().enumerate() {
-        let u = (uv[0] as u32 & 0x1) << 12;
-        let v = (uv[1] as u32 & 0x1) << 13;
+        let u = ((uv[0] * 16.).round() as u32 & 0x1F) << 12;
+        let v = ((uv[1] * 16.).round() as u32 & 0x1F) << 17;
         data[i] = tex_id & 0xFFF | u | v;
     }
     data
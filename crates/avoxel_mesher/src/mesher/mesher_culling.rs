@@ -1,38 +1,52 @@
-use crate::mesher::{mesh_tables, mesh_tables::*};
+use crate::mesher::{
+    block_models::{full_faces, model_faces, opposite_face},
+    chunk_mesh_data::ChunkMeshData,
+    mesh_builder::MeshBuilder,
+    mesh_tables::*,
+    orientation::Orientation,
+};
 use avoxel_blocks::{Block, BlockLibrary};
 use avoxel_chunk::*;
-use avoxel_math::{BevyVec3, Extent3};
-use bevy::{
-    prelude::Mesh,
-    render::{
-        mesh::{Indices, VertexAttributeValues},
-        pipeline::PrimitiveTopology,
-    },
-};
+use avoxel_math::{BevyVec3, Extent3, Pos};
 use std::sync::Arc;
 
-const ATTRIBUTE_TEXTURE_DATUM: &str = "Texture_Datum";
+/// How far below the surface skirts reach, enough to cover the coarsest cells
+const SKIRT_DEPTH: i32 = 1 << MAX_LOD_LEVEL;
+
+pub fn generate_mesh_culled(
+    chunk: &Chunk,
+    block_library: Arc<BlockLibrary>,
+) -> Option<ChunkMeshData> {
+    generate_mesh_with_skirts(chunk, block_library, [false; 6])
+}
 
-pub fn generate_mesh_culled(chunk: &Chunk, block_library: Arc<BlockLibrary>) -> Option<Mesh> {
+/// Like `generate_mesh_culled` but keeps the faces near the surface on the sides in `skirts`,
+/// indexed like `Block::texture_ids`, even where the neighboring chunk covers them.
+/// Used next to chunks meshed at another level so the seams don't leave holes.
+pub fn generate_mesh_with_skirts(
+    chunk: &Chunk,
+    block_library: Arc<BlockLibrary>,
+    skirts: [bool; 6],
+) -> Option<ChunkMeshData> {
     if chunk.is_empty() {
         return None;
     }
-    // mesh storages
-    let mut vertices: Vec<[f32; 3]> = vec![];
-    let mut normals: Vec<[f32; 3]> = vec![];
-    let mut texture_data: Vec<u32> = vec![];
-    let mut indices: Vec<u32> = vec![];
-
-    // keep track of vertices needed for indices
-    let mut vert_count = 0;
-
-    let mut extend_mesh = |square: &Square| {
-        vertices.extend(&square.verts);
-        normals.extend(&square.norms);
-        texture_data.extend(&square.texture_data);
-        indices.extend(&mesh_tables::indices(vert_count));
-        vert_count += 4;
-    };
+    let mut mesh_builder = MeshBuilder::new(&block_library);
+    add_culled_faces(chunk, &block_library, skirts, &mut mesh_builder, |_| true);
+    mesh_builder.build()
+}
+
+/// Adds the visible faces of the voxels whose block id passes `filter`,
+/// voxels that are left out still hide the faces next to them
+pub(crate) fn add_culled_faces(
+    chunk: &Chunk,
+    block_library: &BlockLibrary,
+    skirts: [bool; 6],
+    mesh_builder: &mut MeshBuilder,
+    filter: impl Fn(u32) -> bool,
+) {
+    let mut extend_mesh =
+        |square: &Square, column_tint: &ColumnTint| mesh_builder.add_square(square, column_tint);
 
     let extent = chunk.extent();
     let sub_extent = Extent3 {
@@ -56,89 +70,240 @@ pub fn generate_mesh_culled(chunk: &Chunk, block_library: Arc<BlockLibrary>) ->
             continue;
         }
 
-        let block = block_library.get_block(*v as usize);
+        let id = v.id();
+        if !filter(id) {
+            continue;
+        }
+        let block = block_library.get_block(id as usize);
+        let orientation = Orientation::of(block, *v);
+        let texture_ids = orientation.texture_ids(block.texture_ids);
 
-        let local_block_pos = (block_pos - chunk.extent().min - CHUNK_PADDING).to_vec3();
+        let local_pos = block_pos - chunk.extent().min - CHUNK_PADDING;
+        let local_block_pos = local_pos.to_vec3();
+        let skirt =
+            |side: usize| skirts[side] && is_skirt_face(chunk, i, local_pos, side, SKIRT_DEPTH);
+        let column_tint = chunk.get_column_tint(block_pos.x, block_pos.z);
 
-        let neighbor_left = chunk.voxels[i - CHUNK_LAYER_SIZE_WITH_PADDING as usize];
-        if is_face_visible(&neighbor_left) {
-            let tex_id = block.texture_ids[Block::LEFT];
-            extend_mesh(&square_left(
-                local_block_pos,
-                tex_id,
-                block_library.get_block_texture(tex_id).rand_rot,
-            ));
+        const LAYER: usize = CHUNK_LAYER_SIZE_WITH_PADDING as usize;
+        const ROW: usize = CHUNK_SIZE_WITH_PADDING as usize;
+        if !block.shape.is_cube() {
+            let neighbors = [i + 1, i + LAYER, i - 1, i - LAYER, i + ROW, i - ROW];
+            for face in model_faces(block, &orientation, local_block_pos) {
+                let visible = face.side.map_or(true, |side| {
+                    is_face_visible(block_library, id, chunk.voxels[neighbors[side]], side)
+                });
+                if visible {
+                    extend_mesh(&face.square, &column_tint);
+                }
+            }
+            continue;
         }
 
-        let neighbor_back = chunk.voxels[i - CHUNK_SIZE_WITH_PADDING as usize];
-        if is_face_visible(&neighbor_back) {
-            let tex_id = block.texture_ids[Block::BACK];
-            extend_mesh(&square_back(
-                local_block_pos,
-                tex_id,
-                block_library.get_block_texture(tex_id).rand_rot,
-            ));
+        // fluids are drawn at the height of their level unless the same fluid is above
+        let is_fluid = block.fluid.is_some();
+        let surface_height = |index: usize| {
+            if chunk.voxels[index + 1].id() == id {
+                1.
+            } else {
+                fluid_height(chunk.voxels[index].level())
+            }
+        };
+        let height = if is_fluid { surface_height(i) } else { 1. };
+        let mut add_face = |square: Square, bottom: f32| {
+            extend_mesh(
+                &with_height(square, local_block_pos.y, bottom, height),
+                &column_tint,
+            );
+        };
+        // the bottom of a visible side face, the same fluid only covers it up to its own height
+        let side_bottom = |index: usize, side: usize| {
+            let neighbor = chunk.voxels[index];
+            if is_face_visible(block_library, id, neighbor, side) || skirt(side) {
+                Some(0.)
+            } else if is_fluid && neighbor.id() == id && surface_height(index) < height {
+                Some(surface_height(index))
+            } else {
+                None
+            }
+        };
+
+        if let Some(bottom) = side_bottom(i - CHUNK_LAYER_SIZE_WITH_PADDING as usize, Block::LEFT) {
+            let tex_id = texture_ids[Block::LEFT];
+            add_face(
+                square_left(
+                    local_block_pos,
+                    tex_id,
+                    block_library.get_block_texture(tex_id).rand_rot,
+                ),
+                bottom,
+            );
+        }
+
+        if let Some(bottom) = side_bottom(i - CHUNK_SIZE_WITH_PADDING as usize, Block::BACK) {
+            let tex_id = texture_ids[Block::BACK];
+            add_face(
+                square_back(
+                    local_block_pos,
+                    tex_id,
+                    block_library.get_block_texture(tex_id).rand_rot,
+                ),
+                bottom,
+            );
         }
 
         let neighbor_bottom = chunk.voxels[i - 1];
-        if is_face_visible(&neighbor_bottom) {
-            let tex_id = block.texture_ids[Block::BOTTOM];
-            extend_mesh(&square_bottom(
-                local_block_pos,
-                tex_id,
-                block_library.get_block_texture(tex_id).rand_rot,
-            ));
+        if is_face_visible(block_library, id, neighbor_bottom, Block::BOTTOM) {
+            let tex_id = texture_ids[Block::BOTTOM];
+            add_face(
+                square_bottom(
+                    local_block_pos,
+                    tex_id,
+                    block_library.get_block_texture(tex_id).rand_rot,
+                ),
+                0.,
+            );
         }
 
         let neighbor_top = chunk.voxels[i + 1];
-        if is_face_visible(&neighbor_top) {
-            let tex_id = block.texture_ids[Block::TOP];
-            extend_mesh(&square_top(
-                local_block_pos,
-                tex_id,
-                block_library.get_block_texture(tex_id).rand_rot,
-            ));
+        if is_face_visible(block_library, id, neighbor_top, Block::TOP)
+            || (height < 1. && neighbor_top.id() != id)
+        {
+            let tex_id = texture_ids[Block::TOP];
+            add_face(
+                square_top(
+                    local_block_pos,
+                    tex_id,
+                    block_library.get_block_texture(tex_id).rand_rot,
+                ),
+                0.,
+            );
         }
 
-        let neighbor_front = chunk.voxels[i + CHUNK_SIZE_WITH_PADDING as usize];
-        if is_face_visible(&neighbor_front) {
-            let tex_id = block.texture_ids[Block::FRONT];
-            extend_mesh(&square_front(
-                local_block_pos,
-                tex_id,
-                block_library.get_block_texture(tex_id).rand_rot,
-            ));
+        if let Some(bottom) = side_bottom(i + CHUNK_SIZE_WITH_PADDING as usize, Block::FRONT) {
+            let tex_id = texture_ids[Block::FRONT];
+            add_face(
+                square_front(
+                    local_block_pos,
+                    tex_id,
+                    block_library.get_block_texture(tex_id).rand_rot,
+                ),
+                bottom,
+            );
         }
 
-        let neighbor_right = chunk.voxels[i + CHUNK_LAYER_SIZE_WITH_PADDING as usize];
-        if is_face_visible(&neighbor_right) {
-            let tex_id = block.texture_ids[Block::RIGHT];
-            extend_mesh(&square_right(
-                local_block_pos,
-                tex_id,
-                block_library.get_block_texture(tex_id).rand_rot,
-            ));
+        if let Some(bottom) = side_bottom(i + CHUNK_LAYER_SIZE_WITH_PADDING as usize, Block::RIGHT)
+        {
+            let tex_id = texture_ids[Block::RIGHT];
+            add_face(
+                square_right(
+                    local_block_pos,
+                    tex_id,
+                    block_library.get_block_texture(tex_id).rand_rot,
+                ),
+                bottom,
+            );
         }
     }
+}
+
+/// Faces are hidden by neighbors that cover the whole adjacent side and by the same fluid
+pub(crate) fn is_face_visible(
+    block_library: &BlockLibrary,
+    id: u32,
+    neighbor: u32,
+    face: usize,
+) -> bool {
+    if neighbor == Block::AIR {
+        return true;
+    }
+    let neighbor_id = neighbor.id();
+    let neighbor_block = block_library.get_block(neighbor_id as usize);
+    if neighbor_block.fluid.is_some() {
+        return neighbor_id != id;
+    }
+    let orientation = Orientation::of(neighbor_block, neighbor);
+    !full_faces(neighbor_block, &orientation)[opposite_face(face)]
+}
 
-    if vertices.is_empty() {
-        return None;
+/// True for faces on a horizontal side of the chunk with air at most `depth` voxels above,
+/// `i` is the index of the voxel and `local_pos` its position without the padding
+fn is_skirt_face(chunk: &Chunk, i: usize, local_pos: Pos, side: usize, depth: i32) -> bool {
+    let on_side = match side {
+        Block::LEFT => local_pos.x == 0,
+        Block::RIGHT => local_pos.x == CHUNK_SIZE - 1,
+        Block::BACK => local_pos.z == 0,
+        Block::FRONT => local_pos.z == CHUNK_SIZE - 1,
+        _ => false,
+    };
+    // the padding above the chunk counts as well
+    on_side
+        && (1..=depth.min(CHUNK_SIZE - local_pos.y))
+            .any(|above| chunk.voxels[i + above as usize] == Block::AIR)
+}
+
+/// Height of a fluid surface, sources are a bit lower than a full block
+fn fluid_height(level: u32) -> f32 {
+    if level & FLUID_FALLING != 0 {
+        1.
+    } else {
+        (8 - level.min(7)) as f32 / 9.
     }
+}
 
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    mesh.set_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        VertexAttributeValues::from(vertices),
-    );
-    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
-    mesh.set_attribute(
-        ATTRIBUTE_TEXTURE_DATUM,
-        VertexAttributeValues::from(texture_data),
-    );
-    mesh.set_indices(Some(Indices::U32(indices)));
-    Some(mesh)
+/// Lowers the top vertices of a face for blocks that don't fill the whole height and raises
+/// the bottom vertices of side faces that are covered up to `bottom`
+fn with_height(mut square: Square, y: f32, bottom: f32, height: f32) -> Square {
+    for vert in square.verts.iter_mut() {
+        if vert[1] > y {
+            vert[1] = y + height;
+        } else if bottom > 0. {
+            vert[1] = y + bottom;
+        }
+    }
+    square
 }
 
-fn is_face_visible(neighbor: &u32) -> bool {
-    *neighbor == Block::AIR
+#[cfg(test)]
+mod tests {
+    use crate::generate_mesh_culled;
+    use avoxel_blocks::{Block, BlockLibrary, BlockTexture, Fluid};
+    use avoxel_chunk::{Chunk, VoxelBits};
+    use avoxel_math::Pos;
+    use std::sync::Arc;
+
+    #[test]
+    fn fluid_sides_above_lower_levels() {
+        let mut block_library = BlockLibrary::new();
+        block_library.add_block_texture(BlockTexture::default());
+        block_library.add_block(Block::default());
+        block_library.add_block(Block {
+            name: "water".to_string(),
+            fluid: Some(Fluid::WATER),
+            ..Default::default()
+        });
+        let mut chunk = Chunk::new(Pos::new(0, 0, 0), 0);
+        chunk.set_voxel(1, Pos::new(5, 10, 5));
+        chunk.set_voxel(1u32.with_level(3), Pos::new(6, 10, 5));
+        let mesh = generate_mesh_culled(&chunk, Arc::new(block_library)).unwrap();
+
+        // only the source has a face between the two, above the lower level
+        let between: Vec<usize> = (0..mesh.positions.len() / 4)
+            .filter(|face| {
+                mesh.positions[face * 4..face * 4 + 4]
+                    .iter()
+                    .all(|v| v[0] == 6.)
+            })
+            .collect();
+        assert_eq!(between.len(), 1);
+        let face = between[0] * 4;
+        assert_eq!(mesh.normals[face][0], 1.);
+        let heights: Vec<f32> = mesh.positions[face..face + 4]
+            .iter()
+            .map(|v| v[1])
+            .collect();
+        let min = heights.iter().cloned().fold(f32::MAX, f32::min);
+        let max = heights.iter().cloned().fold(f32::MIN, f32::max);
+        assert_eq!(min, 10. + 5. / 9.);
+        assert_eq!(max, 10. + 8. / 9.);
+    }
 }
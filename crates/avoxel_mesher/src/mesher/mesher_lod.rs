@@ -0,0 +1,178 @@
+use crate::mesher::{
+    chunk_mesh_data::ChunkMeshData, mesh_builder::MeshBuilder, mesh_tables::*,
+    mesher_culling::is_face_visible,
+};
+use avoxel_blocks::{Block, BlockLibrary};
+use avoxel_chunk::{LodChunk, VoxelBits};
+use avoxel_math::{BevyVec3, Pos};
+use bevy_math::Vec3;
+use std::sync::Arc;
+
+/// How many cells below the surface skirts reach
+const SKIRT_CELLS: i32 = 2;
+
+/// Meshes the cells of a downsampled chunk as cubes that are `scale` voxels wide.
+/// Cells are drawn as full blocks, other shapes and fluids are left out. The faces near
+/// the surface on the sides in `skirts`, indexed like `Block::texture_ids`, are kept
+/// even where the neighboring chunk covers them.
+pub fn generate_mesh_lod(
+    lod_chunk: &LodChunk,
+    block_library: Arc<BlockLibrary>,
+    skirts: [bool; 6],
+) -> Option<ChunkMeshData> {
+    if lod_chunk.is_empty() && lod_chunk.ambient_voxel == Block::AIR {
+        return None;
+    }
+    let mut mesh_builder = MeshBuilder::new(&block_library);
+    let size = lod_chunk.size();
+    let scale = lod_chunk.scale() as f32;
+    let faces: [(usize, Pos, fn(Vec3, u32, bool) -> Square); 6] = [
+        (Block::TOP, Pos::new(0, 1, 0), square_top),
+        (Block::RIGHT, Pos::new(1, 0, 0), square_right),
+        (Block::BOTTOM, Pos::new(0, -1, 0), square_bottom),
+        (Block::LEFT, Pos::new(-1, 0, 0), square_left),
+        (Block::FRONT, Pos::new(0, 0, 1), square_front),
+        (Block::BACK, Pos::new(0, 0, -1), square_back),
+    ];
+    for x in 0..size {
+        for z in 0..size {
+            let column_tint = lod_chunk.get_column_tint(x, z);
+            for y in 0..size {
+                let cell = Pos::new(x, y, z);
+                let voxel = lod_chunk.get_voxel(cell);
+                // only solid cubes can be drawn as cells
+                if !block_library.is_solid_cube(voxel.id()) {
+                    continue;
+                }
+                let block = block_library.get_block(voxel.id() as usize);
+                let origin = (cell * lod_chunk.scale()).to_vec3();
+                for (side, offset, square) in faces.iter() {
+                    let neighbor = cell + *offset;
+                    let outside =
+                        !(0..size).contains(&neighbor.x) || !(0..size).contains(&neighbor.z);
+                    let visible =
+                        is_face_visible(
+                            &block_library,
+                            voxel,
+                            lod_chunk.get_neighbor_voxel(neighbor),
+                            *side,
+                        ) || (skirts[*side] && outside && is_near_surface(lod_chunk, cell));
+                    if !visible {
+                        continue;
+                    }
+                    let tex_id = block.texture_ids[*side];
+                    let face = square(
+                        Vec3::ZERO,
+                        tex_id,
+                        block_library.get_block_texture(tex_id).rand_rot,
+                    );
+                    mesh_builder.add_square(&scaled(face, origin, scale), &column_tint);
+                }
+            }
+        }
+    }
+    mesh_builder.build()
+}
+
+/// True if there is air at most `SKIRT_CELLS` cells above the cell
+fn is_near_surface(lod_chunk: &LodChunk, cell: Pos) -> bool {
+    (1..=SKIRT_CELLS).any(|above| {
+        let cell = cell + Pos::new(0, above, 0);
+        // cells further above belong to the chunk above and count as solid
+        cell.y <= lod_chunk.size() && lod_chunk.get_neighbor_voxel(cell) == Block::AIR
+    })
+}
+
+/// Moves a face of the unit cube at the origin to `origin` and scales it up
+fn scaled(mut square: Square, origin: Vec3, scale: f32) -> Square {
+    for vert in square.verts.iter_mut() {
+        *vert = (origin + Vec3::from(*vert) * scale).into();
+    }
+    square
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate_mesh_lod, ChunkMeshData};
+    use avoxel_blocks::{Block, BlockLibrary, BlockShape, BlockTexture};
+    use avoxel_chunk::{Chunk, LodChunk, LodVoting};
+    use avoxel_math::Pos;
+    use std::{collections::HashSet, sync::Arc};
+
+    /// Air, stone and a slab
+    fn block_library() -> Arc<BlockLibrary> {
+        let mut block_library = BlockLibrary::new();
+        block_library.add_block_texture(BlockTexture::default());
+        block_library.add_block(Block::default());
+        block_library.add_block(Block {
+            name: "stone".to_string(),
+            ..Default::default()
+        });
+        block_library.add_block(Block {
+            name: "slab".to_string(),
+            shape: BlockShape::Slab,
+            ..Default::default()
+        });
+        Arc::new(block_library)
+    }
+
+    /// Stone up to y 15 with a cell high layer of slabs on top, the padding included
+    fn terrain() -> Chunk {
+        let mut chunk = Chunk::new(Pos::new(0, 0, 0), 0);
+        chunk.fill_area(1, Pos::new(-1, -1, -1), Pos::new(65, 16, 65));
+        chunk.fill_area(2, Pos::new(-1, 16, -1), Pos::new(65, 20, 65));
+        chunk
+    }
+
+    /// The lowest corner of each face whose vertices all lie on `plane` along `axis`
+    fn faces_on_plane(mesh: &ChunkMeshData, axis: usize, plane: f32) -> HashSet<[i32; 3]> {
+        let mut faces = HashSet::new();
+        for face in mesh.positions.chunks(4) {
+            if face.iter().all(|v| v[axis] == plane) {
+                let min = |a: usize| face.iter().map(|v| v[a] as i32).min().unwrap();
+                faces.insert([min(0), min(1), min(2)]);
+            }
+        }
+        faces
+    }
+
+    #[test]
+    fn skirts() {
+        let block_library = block_library();
+        let lod_chunk = LodChunk::from_chunk(&terrain(), 2, LodVoting::Majority, |id| {
+            block_library.is_solid_cube(id)
+        });
+        // the neighbor at +x covers the side
+        let mesh = generate_mesh_lod(&lod_chunk, block_library.clone(), [false; 6]).unwrap();
+        assert!(faces_on_plane(&mesh, 0, 64.).is_empty());
+
+        let mut skirts = [false; 6];
+        skirts[Block::RIGHT] = true;
+        let mesh = generate_mesh_lod(&lod_chunk, block_library, skirts).unwrap();
+        let faces = faces_on_plane(&mesh, 0, 64.);
+        // two cells below the surface
+        assert_eq!(faces.len(), 16 * 2);
+        let heights: HashSet<i32> = faces.iter().map(|face| face[1]).collect();
+        assert_eq!(heights, [8, 12].iter().copied().collect());
+        // the other sides stay covered
+        assert!(faces_on_plane(&mesh, 0, 0.).is_empty());
+        assert!(faces_on_plane(&mesh, 2, 64.).is_empty());
+    }
+
+    #[test]
+    fn only_cubes_are_drawn() {
+        let block_library = block_library();
+        // slabs count as air, so the stone below is the surface
+        let lod_chunk = LodChunk::from_chunk(&terrain(), 2, LodVoting::Majority, |id| {
+            block_library.is_solid_cube(id)
+        });
+        let mesh = generate_mesh_lod(&lod_chunk, block_library.clone(), [false; 6]).unwrap();
+        assert_eq!(faces_on_plane(&mesh, 1, 16.).len(), 16 * 16);
+
+        // cells of other shapes are left out when meshing
+        let lod_chunk = LodChunk::from_chunk(&terrain(), 2, LodVoting::Majority, |id| id != 0);
+        assert_eq!(lod_chunk.get_voxel(Pos::new(0, 4, 0)), 2);
+        let mesh = generate_mesh_lod(&lod_chunk, block_library, [false; 6]).unwrap();
+        assert!(mesh.positions.iter().all(|v| v[1] <= 16.));
+    }
+}
@@ -0,0 +1,309 @@
+use crate::mesher::{
+    chunk_mesh_data::ChunkMeshData, mesh_builder::MeshBuilder, mesh_tables::*,
+    mesher_culling::add_culled_faces,
+};
+use avoxel_blocks::{Block, BlockLibrary};
+use avoxel_chunk::*;
+use bevy_math::Vec3;
+use std::sync::Arc;
+
+/// Set in the texture datum of faces the shader textures by their world position
+pub(crate) const TEXTURE_DATUM_TRIPLANAR: u32 = 1 << 31;
+
+const SIZE: usize = CHUNK_SIZE_WITH_PADDING as usize;
+const LAYER: usize = SIZE * SIZE;
+/// Cells lie between the voxel centers, including the padding
+const CELLS: usize = SIZE - 1;
+/// Densities above this are inside of the surface
+const ISO_LEVEL: f32 = 127.5;
+
+/// Meshes the chunk as a smooth surface with naive Surface Nets. Every cell between
+/// 8 voxel centers that the surface crosses gets a vertex and every crossed edge
+/// between two voxels a quad. Voxels are as full as their density, only cube
+/// blocks that aren't fluids count. Other blocks get the faces `generate_mesh_culled`
+/// would give them.
+/// Faces use the top, bottom or side texture of the block depending on their direction
+/// and are textured by their world position so materials don't stretch.
+pub fn generate_mesh_smooth(
+    chunk: &Chunk,
+    block_library: Arc<BlockLibrary>,
+) -> Option<ChunkMeshData> {
+    if chunk.is_empty() {
+        return None;
+    }
+    let smooth: Vec<bool> = (0..block_library.block_count())
+        .map(|id| {
+            let block = block_library.get_block(id);
+            id != Block::AIR as usize && block.fluid.is_none() && block.shape.is_cube()
+        })
+        .collect();
+    let is_smooth = |id: u32| smooth.get(id as usize).copied().unwrap_or(false);
+    let densities: Vec<f32> = chunk
+        .voxels
+        .iter()
+        .enumerate()
+        .map(|(i, voxel)| {
+            let density = if is_smooth(voxel.id()) {
+                chunk.density_at(i, *voxel)
+            } else {
+                0
+            };
+            density as f32 - ISO_LEVEL
+        })
+        .collect();
+
+    // one vertex with a smooth normal for every cell the surface crosses
+    let mut cell_vertices: Vec<Option<(Vec3, Vec3)>> = vec![None; CELLS * CELLS * CELLS];
+    for x in 0..CELLS {
+        for z in 0..CELLS {
+            for y in 0..CELLS {
+                cell_vertices[cell_index(x, y, z)] = cell_vertex(&densities, x, y, z);
+            }
+        }
+    }
+
+    // quads for the crossed edges starting at the voxels of this chunk, edges starting
+    // in the padding belong to the neighboring chunks
+    let mut mesh_builder = MeshBuilder::new(&block_library);
+    let padding = CHUNK_PADDING as usize;
+    for x in padding..SIZE - padding {
+        for z in padding..SIZE - padding {
+            for y in padding..SIZE - padding {
+                let i = index(x, y, z);
+                for axis in 0..3 {
+                    let offset = [LAYER, 1, SIZE][axis];
+                    let inside = densities[i] > 0.;
+                    if inside == (densities[i + offset] > 0.) {
+                        continue;
+                    }
+                    let solid = if inside { i } else { i + offset };
+                    let side = match (axis, inside) {
+                        (0, true) => Block::RIGHT,
+                        (0, false) => Block::LEFT,
+                        (1, true) => Block::TOP,
+                        (1, false) => Block::BOTTOM,
+                        (_, true) => Block::FRONT,
+                        (_, false) => Block::BACK,
+                    };
+                    let vertices = match quad_cells(x, y, z, axis, inside)
+                        .iter()
+                        .map(|cell| cell_vertices[*cell])
+                        .collect::<Option<Vec<(Vec3, Vec3)>>>()
+                    {
+                        Some(vertices) => vertices,
+                        None => continue,
+                    };
+
+                    let block = block_library.get_block(chunk.voxels[solid].id() as usize);
+                    let tex_id = block.texture_ids[side];
+                    let uvs = [[0., 1.], [1., 1.], [1., 0.], [0., 0.]];
+                    let mut texture_data = pack_texture_data(uvs, tex_id);
+                    for datum in texture_data.iter_mut() {
+                        *datum |= TEXTURE_DATUM_TRIPLANAR;
+                    }
+                    let solid_pos = chunk.index_to_pos(solid);
+                    let mut square = Square {
+                        verts: [[0.; 3]; 4],
+                        norms: [[0.; 3]; 4],
+                        texture_data,
+                    };
+                    for (v, (pos, normal)) in vertices.iter().enumerate() {
+                        square.verts[v] = (*pos).into();
+                        square.norms[v] = (*normal).into();
+                    }
+                    mesh_builder
+                        .add_square(&square, &chunk.get_column_tint(solid_pos.x, solid_pos.z));
+                }
+            }
+        }
+    }
+
+    // fluids and other shapes keep their blocky faces
+    add_culled_faces(chunk, &block_library, [false; 6], &mut mesh_builder, |id| {
+        !is_smooth(id)
+    });
+    mesh_builder.build()
+}
+
+fn index(x: usize, y: usize, z: usize) -> usize {
+    y + z * SIZE + x * LAYER
+}
+
+fn cell_index(x: usize, y: usize, z: usize) -> usize {
+    y + z * CELLS + x * CELLS * CELLS
+}
+
+/// The vertex of the cell with its lowest corner at the voxel `x`, `y`, `z`
+/// placed at the average of the edge crossings, `None` if the surface doesn't cross it
+fn cell_vertex(densities: &[f32], x: usize, y: usize, z: usize) -> Option<(Vec3, Vec3)> {
+    let corner = |c: usize| {
+        let (dx, dy, dz) = (c & 1, (c >> 1) & 1, (c >> 2) & 1);
+        (
+            Vec3::new(dx as f32, dy as f32, dz as f32),
+            densities[index(x + dx, y + dy, z + dz)],
+        )
+    };
+    let corners: Vec<(Vec3, f32)> = (0..8).map(corner).collect();
+    let inside = corners.iter().filter(|(_, density)| *density > 0.).count();
+    if inside == 0 || inside == 8 {
+        return None;
+    }
+
+    let mut sum = Vec3::ZERO;
+    let mut crossings = 0;
+    let mut gradient = Vec3::ZERO;
+    for (c, (pos, density)) in corners.iter().enumerate() {
+        // densities grow towards the inside
+        gradient += (*pos * 2. - Vec3::ONE) * *density;
+        for bit in 0..3 {
+            if c & (1 << bit) != 0 {
+                continue;
+            }
+            let (other_pos, other_density) = corners[c | (1 << bit)];
+            if (*density > 0.) != (other_density > 0.) {
+                let t = density / (density - other_density);
+                sum += *pos + (other_pos - *pos) * t;
+                crossings += 1;
+            }
+        }
+    }
+    // voxel centers are half a voxel into the voxel, the padding is left out
+    let origin = Vec3::new(x as f32, y as f32, z as f32) + Vec3::splat(0.5 - CHUNK_PADDING as f32);
+    let normal = if gradient.length_squared() > 0. {
+        -gradient.normalize()
+    } else {
+        Vec3::new(0., 1., 0.)
+    };
+    Some((origin + sum / crossings as f32, normal))
+}
+
+/// The 4 cells around the edge from the voxel `x`, `y`, `z` along `axis`,
+/// counterclockwise seen from the side the face looks at
+fn quad_cells(x: usize, y: usize, z: usize, axis: usize, positive: bool) -> [usize; 4] {
+    // the other two axes in the order that keeps the winding right for positive faces
+    let (u, v) = [(1, 2), (2, 0), (0, 1)][axis];
+    let cell = |du: usize, dv: usize| {
+        let mut pos = [x, y, z];
+        pos[u] -= du;
+        pos[v] -= dv;
+        cell_index(pos[0], pos[1], pos[2])
+    };
+    if positive {
+        [cell(1, 1), cell(0, 1), cell(0, 0), cell(1, 0)]
+    } else {
+        [cell(1, 1), cell(1, 0), cell(0, 0), cell(0, 1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{generate_mesh_smooth, ChunkMeshData};
+    use avoxel_blocks::{Block, BlockLibrary, BlockShape, BlockTexture};
+    use avoxel_chunk::{Chunk, CHUNK_SIZE};
+    use avoxel_math::Pos;
+    use std::{collections::HashMap, sync::Arc};
+
+    /// Stone up to y 9, the padding included
+    fn flat(pos: Pos) -> Chunk {
+        let mut chunk = Chunk::new(pos, 0);
+        let extent = chunk.extent();
+        chunk.fill_area(
+            1,
+            extent.min,
+            Pos::new(extent.max.x + 1, 10, extent.max.z + 1),
+        );
+        chunk
+    }
+
+    /// Hills with partly full voxels at the top, the same in every chunk's padding
+    fn hills(pos: Pos) -> Chunk {
+        let mut chunk = Chunk::new(pos, 0);
+        let extent = chunk.extent();
+        for x in extent.min.x..=extent.max.x {
+            for z in extent.min.z..=extent.max.z {
+                let height = 8 + (x + z * 2).rem_euclid(5);
+                for y in extent.min.y..=height {
+                    chunk.set_voxel(1, Pos::new(x, y, z));
+                }
+                let density = 60 + (x * 7 + z * 3).rem_euclid(190) as u8;
+                chunk.set_density(density, Pos::new(x, height, z));
+            }
+        }
+        chunk
+    }
+
+    /// The vertices of the mesh by the voxel center of the cell they were placed in
+    fn vertices_by_cell(mesh: &ChunkMeshData, origin: Pos) -> HashMap<Pos, ([f32; 3], [f32; 3])> {
+        let mut vertices = HashMap::new();
+        for (position, normal) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            let world = [
+                position[0] + origin.x as f32,
+                position[1] + origin.y as f32,
+                position[2] + origin.z as f32,
+            ];
+            let cell = |c: f32| (c - 0.5).floor() as i32;
+            let key = Pos::new(cell(world[0]), cell(world[1]), cell(world[2]));
+            vertices.insert(key, (world, *normal));
+        }
+        vertices
+    }
+
+    #[test]
+    fn flat_surface() {
+        let mesh =
+            generate_mesh_smooth(&flat(Pos::new(0, 0, 0)), Arc::new(BlockLibrary::default()))
+                .unwrap();
+        // one quad for every column, halfway between the voxel centers
+        assert_eq!(mesh.positions.len(), (CHUNK_SIZE * CHUNK_SIZE * 4) as usize);
+        assert!(mesh.positions.iter().all(|position| position[1] == 10.));
+        assert!(mesh.normals.iter().all(|normal| *normal == [0., 1., 0.]));
+    }
+
+    #[test]
+    fn vertices_match_across_chunk_borders() {
+        let block_library = Arc::new(BlockLibrary::default());
+        let left = generate_mesh_smooth(&hills(Pos::new(0, 0, 0)), block_library.clone()).unwrap();
+        let right = generate_mesh_smooth(&hills(Pos::new(1, 0, 0)), block_library).unwrap();
+        let left = vertices_by_cell(&left, Pos::new(0, 0, 0));
+        let right = vertices_by_cell(&right, Pos::new(CHUNK_SIZE, 0, 0));
+        // cells between the voxels at x 63 and 64 are shared by both chunks
+        let mut shared = 0;
+        for (cell, vertex) in left.iter().filter(|(cell, _)| cell.x == CHUNK_SIZE - 1) {
+            if let Some(other) = right.get(cell) {
+                assert_eq!(vertex, other, "{:?}", cell);
+                shared += 1;
+            }
+        }
+        assert!(shared > CHUNK_SIZE, "{} shared cells", shared);
+    }
+
+    #[test]
+    fn other_blocks_keep_their_faces() {
+        let mut block_library = BlockLibrary::new();
+        block_library.add_block_texture(BlockTexture::default());
+        block_library.add_block(Block::default());
+        block_library.add_block(Block {
+            name: "stone".to_string(),
+            ..Default::default()
+        });
+        block_library.add_block(Block {
+            name: "slab".to_string(),
+            shape: BlockShape::Slab,
+            ..Default::default()
+        });
+        let mut chunk = flat(Pos::new(0, 0, 0));
+        chunk.set_voxel(2, Pos::new(5, 10, 5));
+        let mesh = generate_mesh_smooth(&chunk, Arc::new(block_library)).unwrap();
+        // the top and four sides of the slab, its bottom lies on the stone
+        assert_eq!(
+            mesh.positions.len(),
+            (CHUNK_SIZE * CHUNK_SIZE * 4 + 5 * 4) as usize
+        );
+        let slab_top = mesh
+            .positions
+            .chunks(4)
+            .filter(|face| face.iter().all(|v| v[1] == 10.5))
+            .count();
+        assert_eq!(slab_top, 1);
+    }
+}
@@ -1,12 +1,14 @@
+use avoxel_chunk_map::{TaskToken, WorldId};
 use avoxel_math::Pos;
 use bevy::prelude::Mesh;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use std::time::Instant;
 
 pub struct MeshingChannels {
-    /// Sending Instant for timing purposes
-    pub(crate) tx: Sender<(Pos, Mesh, Instant)>,
-    pub(crate) rx: Receiver<(Pos, Mesh, Instant)>,
+    /// Sending Instant for timing purposes.
+    /// The mesh is `None` if the chunk has no visible faces.
+    pub(crate) tx: Sender<(WorldId, Pos, Option<Mesh>, TaskToken, Instant)>,
+    pub(crate) rx: Receiver<(WorldId, Pos, Option<Mesh>, TaskToken, Instant)>,
 }
 
 impl Default for MeshingChannels {
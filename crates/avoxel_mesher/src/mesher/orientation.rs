@@ -0,0 +1,100 @@
+use avoxel_blocks::{Block, BlockProperty};
+use avoxel_chunk::{Axis, Facing, Voxel, VoxelBits};
+
+/// For every face the face of the unrotated block that ends up there.
+/// Faces are in the order of `Block::texture_ids`: top, right, bottom, left, front, back.
+type FacePermutation = [usize; 6];
+
+const IDENTITY: FacePermutation = [0, 1, 2, 3, 4, 5];
+
+fn facing_permutation(facing: Facing) -> FacePermutation {
+    match facing {
+        Facing::South => IDENTITY,
+        Facing::West => [0, 5, 2, 4, 1, 3],
+        Facing::North => [0, 3, 2, 1, 5, 4],
+        Facing::East => [0, 4, 2, 5, 3, 1],
+        Facing::Up => [4, 1, 5, 3, 2, 0],
+        Facing::Down => [5, 1, 4, 3, 0, 2],
+    }
+}
+
+fn axis_permutation(axis: Axis) -> FacePermutation {
+    match axis {
+        Axis::Y => IDENTITY,
+        Axis::X => [3, 0, 1, 2, 4, 5],
+        Axis::Z => [5, 1, 4, 3, 0, 2],
+    }
+}
+
+fn rotate_by_facing(facing: Facing, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    match facing {
+        Facing::South => [x, y, z],
+        Facing::West => [-z, y, x],
+        Facing::North => [-x, y, -z],
+        Facing::East => [z, y, -x],
+        Facing::Up => [x, z, -y],
+        Facing::Down => [x, -z, y],
+    }
+}
+
+fn rotate_by_axis(axis: Axis, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    match axis {
+        Axis::Y => [x, y, z],
+        Axis::X => [y, -x, z],
+        Axis::Z => [x, -z, y],
+    }
+}
+
+/// The rotation of a block from its orientation properties.
+/// The axis is applied first, then the facing.
+pub struct Orientation {
+    axis: Axis,
+    facing: Facing,
+}
+
+impl Orientation {
+    /// Blocks without orientation properties keep the default orientation
+    pub fn of(block: &Block, voxel: Voxel) -> Self {
+        let axis = if block.has_property(BlockProperty::Axis) {
+            voxel.axis()
+        } else {
+            Axis::Y
+        };
+        let facing = if block.has_property(BlockProperty::Facing) {
+            voxel.facing()
+        } else if block.has_property(BlockProperty::HorizontalFacing) {
+            match voxel.facing() {
+                Facing::Up | Facing::Down => Facing::South,
+                facing => facing,
+            }
+        } else {
+            Facing::South
+        };
+        Self { axis, facing }
+    }
+
+    /// Rotates a direction
+    pub fn rotate(&self, direction: [f32; 3]) -> [f32; 3] {
+        rotate_by_facing(self.facing, rotate_by_axis(self.axis, direction))
+    }
+
+    /// Rotates a point within the unit cube around the center of the block
+    pub fn rotate_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let [x, y, z] = self.rotate([point[0] - 0.5, point[1] - 0.5, point[2] - 0.5]);
+        [x + 0.5, y + 0.5, z + 0.5]
+    }
+
+    /// Returns the face of the unrotated block that ends up at `face`
+    pub fn local_face(&self, face: usize) -> usize {
+        axis_permutation(self.axis)[facing_permutation(self.facing)[face]]
+    }
+
+    /// Returns the texture ids rotated so each face gets the texture of the face that ends up there
+    pub fn texture_ids(&self, texture_ids: [u32; 6]) -> [u32; 6] {
+        let mut rotated = texture_ids;
+        for (face, texture_id) in rotated.iter_mut().enumerate() {
+            *texture_id = texture_ids[self.local_face(face)];
+        }
+        rotated
+    }
+}
@@ -1,4 +1,5 @@
 use avoxel_blocks::BlockLibrary;
+use avoxel_rendering::prelude::BlockMaterials;
 use bevy::prelude::*;
 
 #[derive(Eq, PartialEq, Copy, Clone)]
@@ -32,13 +33,32 @@ impl State {
 pub fn handle_state_system(
     mut state: ResMut<State>,
     block_library: Res<BlockLibrary>,
+    block_materials: Res<BlockMaterials>,
     mut textures: ResMut<Assets<Texture>>,
 ) {
     match state.get_state() {
         States::LoadingMaterials => {
+            // the block library may still be loading from a file
+            if block_library.block_count() == 0 {
+                return;
+            }
             let mut loaded = true;
-            if let Some(texture) = textures.get_mut(&block_library.get_texture_handle()) {
-                texture.reinterpret_stacked_2d_as_array(block_library.get_texture_count());
+            if let Some(texture) = textures.get_mut(&block_materials.get_texture_handle()) {
+                // textures built by the `TextureArrayBuilder` already are arrays
+                if texture.size.depth == 1 {
+                    let size = texture.size;
+                    let layers = size.height / size.width.max(1);
+                    if layers != block_library.get_texture_count() {
+                        warn!(
+                            "block texture is {}x{} which fits {} textures but texture_count is {}",
+                            size.width,
+                            size.height,
+                            layers,
+                            block_library.get_texture_count()
+                        );
+                    }
+                    texture.reinterpret_stacked_2d_as_array(layers.max(1));
+                }
             } else {
                 loaded = false;
             }
@@ -1,106 +1,241 @@
 use crate::mesher::*;
-use avoxel_blocks::BlockLibrary;
+use avoxel_blocks::{Block, BlockLibrary};
 use avoxel_chunk::*;
-use avoxel_chunk_map::{chunk_map_diagnostics::MESH_TIMES, ChunkMap, ChunkViewer};
+use avoxel_chunk_map::{
+    chunk_map_diagnostics::MESH_TIMES, ChunkMap, ChunkViewer, ChunkWorlds, TaskToken, WorldId,
+};
 use avoxel_math::*;
-use avoxel_rendering::AvoxelChunkBundle;
+use avoxel_rendering::{prelude::BlockMaterials, AvoxelChunkBundle};
 use bevy::{diagnostic::Diagnostics, prelude::*, tasks::AsyncComputeTaskPool};
-use std::time::Instant;
+use crossbeam_channel::Sender;
+use std::{sync::Arc, time::Instant};
+
+/// Makes the chunk maps of all worlds mark changed chunks dirty instead of compressing them
+pub fn enable_meshing(mut worlds: ResMut<ChunkWorlds>) {
+    worlds.set_meshing(true);
+}
+
+/// True if the chunk is in a world and within the render distance of one of its viewers
+fn is_visible(worlds: &ChunkWorlds, (world, pos): &(WorldId, Pos)) -> bool {
+    worlds
+        .get(*world)
+        .map_or(false, |chunk_map| chunk_map.visible_chunks.contains(pos))
+}
+
+/// Sides of the chunk, indexed like `Block::texture_ids`, that are next to
+/// a visible chunk meshed at another level and need skirts
+fn skirts(chunk_map: &ChunkMap, pos: Pos, level: u32) -> [bool; 6] {
+    let mut skirts = [false; 6];
+    for (side, offset) in [
+        (Block::RIGHT, Pos::new(1, 0, 0)),
+        (Block::LEFT, Pos::new(-1, 0, 0)),
+        (Block::FRONT, Pos::new(0, 0, 1)),
+        (Block::BACK, Pos::new(0, 0, -1)),
+    ]
+    .iter()
+    {
+        let neighbor = pos + *offset;
+        skirts[*side] =
+            chunk_map.visible_chunks.contains(&neighbor) && chunk_map.lod_level(&neighbor) != level;
+    }
+    skirts
+}
+
+/// Meshes a loaded chunk at full resolution or downsampled to its level
+fn mesh_chunk(
+    chunk: &Chunk,
+    block_library: Arc<BlockLibrary>,
+    mode: MeshingMode,
+    level: u32,
+    voting: LodVoting,
+    skirts: [bool; 6],
+) -> Option<ChunkMeshData> {
+    if level == 0 {
+        match mode {
+            MeshingMode::Culled => generate_mesh_with_skirts(chunk, block_library, skirts),
+            // smooth surfaces line up with the neighbors on their own
+            MeshingMode::Smooth => generate_mesh_smooth(chunk, block_library),
+        }
+    } else {
+        let lod_chunk =
+            LodChunk::from_chunk(chunk, level, voting, |id| block_library.is_solid_cube(id));
+        generate_mesh_lod(&lod_chunk, block_library, skirts)
+    }
+}
+
+fn send_mesh(
+    sender: Sender<(WorldId, Pos, Option<Mesh>, TaskToken, Instant)>,
+    message: (WorldId, Pos, Option<Mesh>, TaskToken, Instant),
+) {
+    if let Err(e) = sender.send(message) {
+        warn!("failed to send mesh with channel: {}", e.0 .1.to_string());
+    }
+}
 
 #[allow(clippy::too_many_arguments)]
 pub fn mesh_dirty_chunks(
     mut commands: Commands,
     pool: Res<AsyncComputeTaskPool>,
-    mut chunk_map: ResMut<ChunkMap>,
+    mut worlds: ResMut<ChunkWorlds>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut diagnostics: ResMut<Diagnostics>,
-    block_library: Res<BlockLibrary>,
+    block_materials: Res<BlockMaterials>,
     mut mesher: ResMut<Mesher>,
     _viewers: Query<&ChunkViewer>,
 ) {
-    // spawn meshing tasks for dirty chunks
-    for pos in &chunk_map.dirty_chunks {
-        let sender = mesher.meshing_channels.tx.clone();
-        let block_library = chunk_map.block_library.clone();
-        match chunk_map.chunks.get(pos) {
-            None => match chunk_map.compressed_chunks.get(pos) {
-                None => continue,
-                Some(c) => {
-                    let chunk = c.decompress(chunk_map.get_byteorder());
-                    pool.spawn(async move {
-                        let start_instant = Instant::now();
-                        if let Some(mesh) = generate_mesh_culled(&chunk, block_library) {
-                            match sender.send((chunk.pos, mesh, start_instant)) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    warn!(
-                                        "failed to send mesh with channel: {}",
-                                        e.0 .0.to_string()
-                                    );
+    // spawn meshing tasks for the dirty chunks closest to a viewer
+    for (world, chunk_map) in worlds.iter_mut() {
+        let mut dirty_chunks: Vec<Pos> = chunk_map.dirty_chunks.iter().copied().collect();
+        chunk_map.sort_by_viewer_distance(&mut dirty_chunks);
+        dirty_chunks.truncate(mesher.meshing_tasks_per_frame);
+        let voting = chunk_map
+            .get_lod()
+            .map_or_else(Default::default, |lod| lod.voting);
+        let mode = mesher.mode;
+        for pos in &dirty_chunks {
+            let sender = mesher.meshing_channels.tx.clone();
+            let block_library = chunk_map.block_library.clone();
+            let token = TaskToken::new();
+            let level = chunk_map.lod_level(pos);
+            let skirts = skirts(chunk_map, *pos, level);
+            let pos = *pos;
+            match chunk_map.chunks.get(&pos) {
+                None => match chunk_map.compressed_chunks.get(&pos) {
+                    None => match chunk_map.lod_chunks.get(&pos) {
+                        None => continue,
+                        Some(lod_chunk) => {
+                            let lod_chunk = lod_chunk.clone();
+                            let task_token = token.clone();
+                            pool.spawn(async move {
+                                if task_token.is_cancelled() {
+                                    return;
                                 }
+                                let start_instant = Instant::now();
+                                let mesh = generate_mesh_lod(&lod_chunk, block_library, skirts)
+                                    .map(Mesh::from);
+                                send_mesh(sender, (world, pos, mesh, task_token, start_instant));
+                            })
+                            .detach();
+                        }
+                    },
+                    Some(c) => {
+                        let chunk = c.decompress(chunk_map.get_byteorder());
+                        let task_token = token.clone();
+                        pool.spawn(async move {
+                            if task_token.is_cancelled() {
+                                return;
                             }
+                            let start_instant = Instant::now();
+                            let mesh =
+                                mesh_chunk(&chunk, block_library, mode, level, voting, skirts)
+                                    .map(Mesh::from);
+                            send_mesh(sender, (world, pos, mesh, task_token, start_instant));
+                        })
+                        .detach();
+                    }
+                },
+                Some(c) => {
+                    let chunk = c.clone();
+                    let task_token = token.clone();
+                    pool.spawn(async move {
+                        if task_token.is_cancelled() {
+                            return;
                         }
+                        let chunk = chunk.lock();
+                        let start_instant = Instant::now();
+                        let mesh = mesh_chunk(&chunk, block_library, mode, level, voting, skirts)
+                            .map(Mesh::from);
+                        send_mesh(sender, (world, pos, mesh, task_token, start_instant));
                     })
                     .detach();
                 }
-            },
-            Some(c) => {
-                let chunk = c.clone();
-                pool.spawn(async move {
-                    let chunk = chunk.lock();
-                    let start_instant = Instant::now();
-                    if let Some(mesh) = generate_mesh_culled(&chunk, block_library) {
-                        match sender.send((chunk.pos, mesh, start_instant)) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                warn!("failed to send mesh with channel: {}", e.0 .0.to_string());
-                            }
-                        }
-                    }
-                })
-                .detach();
+            };
+            // a newer mesh of the chunk makes the older one obsolete
+            if let Some(previous) = mesher.meshing_tasks.insert((world, pos), token) {
+                previous.cancel();
             }
-        };
-    }
-    if !chunk_map.dirty_chunks.is_empty() {
-        chunk_map.dirty_chunks.clear();
+        }
+        for pos in &dirty_chunks {
+            chunk_map.dirty_chunks.remove(pos);
+        }
     }
 
-    // spawn avoxel chunk bundles for completed chunk meshes
+    // spawn avoxel chunk bundles for the completed chunk meshes closest to a viewer
     let receiver = mesher.meshing_channels.rx.clone();
-    for (pos, mesh, start_instant) in receiver.try_iter().take(12) {
-        if chunk_map.contains_chunk(&pos) {
-            let current_entity = commands
-                .spawn()
-                .insert_bundle(AvoxelChunkBundle {
-                    mesh: meshes.add(mesh),
-                    material: block_library.get_material_handle(0).clone(),
-                    transform: Transform::from_translation((pos * CHUNK_SIZE).to_vec3()),
-                    ..Default::default()
-                })
-                .id();
-
-            match mesher.mesh_entities.get(&pos) {
-                None => {}
-                Some(entities) => {
+    for (world, pos, mesh, token, start_instant) in receiver.try_iter() {
+        // discard meshes of cancelled tasks
+        let current_task = mesher
+            .meshing_tasks
+            .get(&(world, pos))
+            .map_or(false, |current| current.same_task(&token));
+        if !current_task || token.is_cancelled() {
+            continue;
+        }
+        mesher.meshing_tasks.remove(&(world, pos));
+        mesher
+            .finished_meshes
+            .insert((world, pos), (mesh, start_instant));
+    }
+    for (world, chunk_map) in worlds.iter_mut() {
+        let mut finished: Vec<Pos> = mesher
+            .finished_meshes
+            .keys()
+            .filter(|(mesh_world, _)| *mesh_world == world)
+            .map(|(_, pos)| *pos)
+            .collect();
+        chunk_map.sort_by_viewer_distance(&mut finished);
+        finished.truncate(mesher.meshes_per_frame);
+        for pos in finished {
+            let (mesh, start_instant) = match mesher.finished_meshes.remove(&(world, pos)) {
+                Some(finished_mesh) => finished_mesh,
+                None => continue,
+            };
+            if chunk_map.is_meshable(&pos) {
+                if let Some(entities) = mesher.mesh_entities.remove(&(world, pos)) {
                     for entity in entities {
-                        commands.entity(*entity).despawn_recursive();
+                        commands.entity(entity).despawn_recursive();
                     }
                 }
-            }
-            mesher.mesh_entities.insert(pos, vec![current_entity]);
-            diagnostics.add_measurement(MESH_TIMES, start_instant.elapsed().as_secs_f64());
+                // chunks without any visible faces don't get a mesh entity
+                if let Some(mesh) = mesh {
+                    let current_entity = commands
+                        .spawn()
+                        .insert_bundle(AvoxelChunkBundle {
+                            mesh: meshes.add(mesh),
+                            material: block_materials.get_world_material_handle(world),
+                            transform: Transform::from_translation((pos * CHUNK_SIZE).to_vec3()),
+                            ..Default::default()
+                        })
+                        .insert(world)
+                        .id();
+                    mesher
+                        .mesh_entities
+                        .insert((world, pos), vec![current_entity]);
+                }
+                diagnostics.add_measurement(MESH_TIMES, start_instant.elapsed().as_secs_f64());
 
-            // compress chunks after meshing is complete
-            chunk_map.compress_chunk(pool.clone(), pos);
+                // compress chunks after meshing is complete
+                chunk_map.compress_chunk(pool.clone(), pos);
+            }
         }
     }
 
-    // de-spawn chunk meshes
+    // de-spawn chunk meshes and cancel meshing of chunks that left the view
+    // or whose world was removed
+    mesher
+        .finished_meshes
+        .retain(|key, _| is_visible(&worlds, key));
+    mesher.meshing_tasks.retain(|key, token| {
+        let visible = is_visible(&worlds, key);
+        if !visible {
+            token.cancel();
+        }
+        visible
+    });
     let mut positions = vec![];
-    for pos in mesher.mesh_entities.keys() {
-        if !chunk_map.visible_chunks.contains(pos) {
-            positions.push(*pos);
+    for key in mesher.mesh_entities.keys() {
+        if !is_visible(&worlds, key) {
+            positions.push(*key);
         }
     }
 
@@ -0,0 +1,93 @@
+use avoxel_blocks::BlockLibrary;
+use avoxel_chunk::{Chunk, CHUNK_SIZE};
+use avoxel_chunk_map::ChunkMap;
+use avoxel_math::Pos;
+use avoxel_mesher::{generate_mesh_culled, ChunkMeshData};
+use bevy_tasks::{AsyncComputeTaskPool, TaskPool};
+use std::{collections::HashSet, sync::Arc};
+
+/// A pattern of blocks and air. The padding gets a pattern of its own like a
+/// generator that decorates chunks without knowing their neighbors.
+fn generate(pos: Pos) -> Chunk {
+    let mut chunk = Chunk::new(pos, 0);
+    let extent = chunk.extent();
+    let origin = pos * CHUNK_SIZE;
+    for x in extent.min.x..=extent.max.x {
+        for y in extent.min.y..=extent.max.y {
+            for z in extent.min.z..=extent.max.z {
+                let local = Pos::new(x, y, z) - origin;
+                let padding = [local.x, local.y, local.z]
+                    .iter()
+                    .any(|c| *c < 0 || *c >= CHUNK_SIZE);
+                let seed = if padding { 3 } else { 0 };
+                let voxel = ((x * 7 + y * 13 + z * 31 + seed).rem_euclid(5) < 2) as u32;
+                chunk.set_voxel(voxel, Pos::new(x, y, z));
+            }
+        }
+    }
+    chunk
+}
+
+fn mesh(chunk_map: &ChunkMap, pos: Pos) -> ChunkMeshData {
+    let chunk = chunk_map
+        .get_compressed_chunk(&pos, true)
+        .unwrap()
+        .decompress(true);
+    generate_mesh_culled(&chunk, Arc::new(BlockLibrary::default())).unwrap()
+}
+
+/// The faces of the mesh on the plane `x` facing `normal_x`, by the y and z of their corner
+fn faces_on_plane(mesh: &ChunkMeshData, x: f32, normal_x: f32) -> HashSet<(i32, i32)> {
+    let mut faces = HashSet::new();
+    for face in 0..mesh.positions.len() / 4 {
+        let vertices = &mesh.positions[face * 4..face * 4 + 4];
+        if vertices.iter().all(|v| v[0] == x) && mesh.normals[face * 4][0] == normal_x {
+            let y = vertices.iter().map(|v| v[1] as i32).min().unwrap();
+            let z = vertices.iter().map(|v| v[2] as i32).min().unwrap();
+            faces.insert((y, z));
+        }
+    }
+    faces
+}
+
+/// Checks that every voxel pair across the border between the chunk at the origin
+/// and the one at +x has exactly one face between them if one of them is air
+fn assert_border_culled(chunk_map: &ChunkMap) {
+    let left = faces_on_plane(&mesh(chunk_map, Pos::new(0, 0, 0)), 64., 1.);
+    let right = faces_on_plane(&mesh(chunk_map, Pos::new(1, 0, 0)), 0., -1.);
+    let solid = |pos: Pos| chunk_map.get_voxel(&pos).unwrap() != 0;
+    for y in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            let (a, b) = (solid(Pos::new(63, y, z)), solid(Pos::new(64, y, z)));
+            assert_eq!(left.contains(&(y, z)), a && !b, "left face at {} {}", y, z);
+            assert_eq!(
+                right.contains(&(y, z)),
+                b && !a,
+                "right face at {} {}",
+                y,
+                z
+            );
+        }
+    }
+}
+
+#[test]
+fn border_faces_are_culled_from_both_sides() {
+    let pool = AsyncComputeTaskPool(TaskPool::new());
+    let mut chunk_map = ChunkMap::default();
+    chunk_map.insert_chunk(pool.clone(), generate(Pos::new(0, 0, 0)));
+    chunk_map.insert_chunk(pool.clone(), generate(Pos::new(1, 0, 0)));
+    assert_border_culled(&chunk_map);
+
+    // edits on the border show up on both sides
+    for z in 0..CHUNK_SIZE {
+        chunk_map.set_voxel(1, &Pos::new(63, 20, z));
+        chunk_map.set_voxel(0, &Pos::new(64, 21, z));
+    }
+    assert_border_culled(&chunk_map);
+
+    // chunks that are loaded again take the padding from their neighbor
+    chunk_map.remove_chunk(&Pos::new(0, 0, 0));
+    chunk_map.insert_chunk(pool, generate(Pos::new(0, 0, 0)));
+    assert_border_culled(&chunk_map);
+}
@@ -1,6 +1,6 @@
 #[macro_use]
 extern crate criterion;
-use bevy::prelude::Vec3;
+use bevy_math::Vec3;
 use criterion::{black_box, BenchmarkId, Criterion};
 
 fn sqrt_clamp_velocity(velocity: Vec3, max_velocity: f32) -> Vec3 {
@@ -1,5 +1,6 @@
+use avoxel_chunk_map::WorldId;
 use avoxel_math::{Aabb, BevyVec3, Pos};
-use bevy::prelude::Vec3;
+use bevy_math::Vec3;
 
 pub struct AvoxelBox {
     /// The bounding box
@@ -10,6 +11,8 @@ pub struct AvoxelBox {
     pub(crate) on_ceiling: bool,
     pub(crate) velocity: Vec3,
     pub(crate) changes: AvoxelBoxChanges,
+    /// The world the box collides with
+    pub(crate) world: WorldId,
 }
 
 impl AvoxelBox {
@@ -21,6 +24,7 @@ impl AvoxelBox {
             on_ceiling: false,
             velocity: Vec3::ZERO,
             changes: AvoxelBoxChanges::all(),
+            world: WorldId::DEFAULT,
         }
     }
 
@@ -55,11 +59,17 @@ impl AvoxelBox {
         }
     }
 
+    pub fn world(&self) -> WorldId {
+        self.world
+    }
+
     pub fn pos(&self) -> Pos {
         Pos::from_vec3(&self.translated_aabb().min.floor())
     }
 }
 
+/// Turned into an `AvoxelBox` of the world in the `WorldId` component of the entity,
+/// or of the default world if there is none
 pub struct AvoxelBoxBuilder {
     pub(crate) aabb: Aabb,
     pub(crate) translation: Vec3,
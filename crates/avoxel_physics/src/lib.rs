@@ -1,8 +1,6 @@
-use avoxel_chunk_map::ChunkMap;
-use bevy::{
-    app::AppBuilder,
-    prelude::{IntoSystem, Plugin},
-};
+use avoxel_chunk_map::ChunkWorlds;
+use bevy_app::{AppBuilder, Plugin};
+use bevy_ecs::system::IntoSystem;
 
 mod avoxel_box;
 mod box_map;
@@ -19,8 +17,8 @@ pub struct AvoxelPhysicsPlugin;
 
 impl Plugin for AvoxelPhysicsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        if !app.world().contains_resource::<ChunkMap>() {
-            bevy::log::error!("AvoxelChunkMapPlugin needs to be added first.");
+        if !app.world().contains_resource::<ChunkWorlds>() {
+            bevy_log::error!("AvoxelChunkMapPlugin needs to be added first.");
         }
 
         app.insert_resource(AvoxelPhysicsState::default())
@@ -5,17 +5,26 @@ use crate::{
     AvoxelPhysicsState,
 };
 use avoxel_blocks::Block;
-use avoxel_chunk_map::ChunkMap;
+use avoxel_chunk::VoxelBits;
+use avoxel_chunk_map::{ChunkWorlds, WorldId};
 use avoxel_math::{Aabb, BevyVec3, Pos};
-use bevy::prelude::{Commands, Entity, Query, Res, ResMut, Time, Transform, Vec3};
+use bevy_core::Time;
+use bevy_ecs::{
+    entity::Entity,
+    system::{Commands, Query, Res, ResMut},
+};
+use bevy_math::Vec3;
+use bevy_transform::components::Transform;
 
 pub fn create_avoxel_boxes_system(
     mut commands: Commands,
     mut box_map: ResMut<BoxMap>,
-    query: Query<(Entity, &AvoxelBoxBuilder)>,
+    query: Query<(Entity, &AvoxelBoxBuilder, Option<&WorldId>)>,
 ) {
-    for (entity, bb) in query.iter() {
-        let handle = box_map.insert(bb.build());
+    for (entity, bb, world) in query.iter() {
+        let mut avoxel_box = bb.build();
+        avoxel_box.world = world.copied().unwrap_or_default();
+        let handle = box_map.insert(avoxel_box);
         commands
             .entity(entity)
             .insert(AvoxelBoxHandleComponent::from(handle));
@@ -27,13 +36,18 @@ pub fn create_avoxel_boxes_system(
 pub fn box_move_and_slide(
     time: Res<Time>,
     mut box_map: ResMut<BoxMap>,
-    chunk_map: Res<ChunkMap>,
+    worlds: Res<ChunkWorlds>,
     physics_state: Res<AvoxelPhysicsState>,
 ) {
     if physics_state.paused() {
         return;
     }
     for (_, b) in box_map.boxes.iter_mut() {
+        // boxes of removed worlds stay where they are
+        let chunk_map = match worlds.get(b.world) {
+            Some(chunk_map) => chunk_map,
+            None => continue,
+        };
         let mut changed = true;
         let prev_aabb = b.translated_aabb();
         let prev_translation = b.translation;
@@ -56,7 +70,9 @@ pub fn box_move_and_slide(
                     let md = b.translated_aabb().minkowski_difference(&block_aabb);
                     if md.point_colliding(&Vec3::ZERO) {
                         if let Some(v) = chunk_map.get_voxel(&block_pos) {
-                            if v != Block::AIR {
+                            // fluids don't collide
+                            let is_fluid = chunk_map.block_library.get_fluid(v.id()).is_some();
+                            if v != Block::AIR && !is_fluid {
                                 let pen_vec = md.penetration_vector(&Vec3::ZERO);
                                 if pen_vec.y > 0. {
                                     if linear_velocity.y < 0. {
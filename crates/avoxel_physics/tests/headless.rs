@@ -0,0 +1,235 @@
+use avoxel_chunk::Chunk;
+use avoxel_chunk_map::{
+    AvoxelChunkMapPlugin, ChunkMap, ChunkStorage, ChunkViewer, ChunkWorlds, WorldId,
+};
+use avoxel_math::{Aabb, Pos};
+use avoxel_physics::{AvoxelBoxBuilder, AvoxelBoxHandleComponent, AvoxelPhysicsPlugin, BoxMap};
+use bevy_app::App;
+use bevy_core::CorePlugin;
+use bevy_math::Vec3;
+use bevy_transform::components::Transform;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn headless_app() -> App {
+    let mut app = App::build();
+    app.add_plugin(CorePlugin)
+        .add_plugin(AvoxelChunkMapPlugin)
+        .add_plugin(AvoxelPhysicsPlugin);
+    app.app
+}
+
+fn chunk_map(app: &App, world: WorldId) -> &ChunkMap {
+    app.world
+        .get_resource::<ChunkWorlds>()
+        .unwrap()
+        .get(world)
+        .unwrap()
+}
+
+fn loaded(chunk_map: &ChunkMap) -> bool {
+    !chunk_map.simulated_chunks.is_empty()
+        && chunk_map
+            .simulated_chunks
+            .iter()
+            .all(|pos| chunk_map.contains_chunk(pos))
+}
+
+/// Runs frames until `done` returns true, panics after `TIMEOUT`
+fn run_until<F: Fn(&ChunkWorlds) -> bool>(app: &mut App, done: F) {
+    let start = Instant::now();
+    loop {
+        app.update();
+        if done(app.world.get_resource::<ChunkWorlds>().unwrap()) {
+            return;
+        }
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "timed out waiting for the chunk map"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn runs_without_rendering() {
+    let mut app = headless_app();
+    app.world
+        .spawn()
+        .insert(ChunkViewer::new(1, Pos::new(0, 0, 0)));
+    let translation = Vec3::new(0.5, 100., 0.5);
+    let avoxel_box = app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(AvoxelBoxBuilder::new(
+            translation,
+            Aabb {
+                min: Vec3::ZERO,
+                max: Vec3::ONE,
+            },
+        ))
+        .id();
+
+    // generation
+    run_until(&mut app, |worlds| {
+        loaded(worlds.get(WorldId::DEFAULT).unwrap())
+    });
+    // without a mesher the loaded chunks are compressed right away
+    run_until(&mut app, |worlds| {
+        let chunk_map = worlds.get(WorldId::DEFAULT).unwrap();
+        chunk_map.compressed_chunks.len() == chunk_map.simulated_chunks.len()
+    });
+
+    // edits
+    let pos = Pos::new(5, 5, 5);
+    {
+        let mut worlds = app.world.get_resource_mut::<ChunkWorlds>().unwrap();
+        worlds.get_mut(WorldId::DEFAULT).unwrap().set_voxel(1, &pos);
+    }
+    // the edited chunk is compressed again
+    run_until(&mut app, |worlds| {
+        let chunk_map = worlds.get(WorldId::DEFAULT).unwrap();
+        chunk_map.compressed_chunks.contains_key(&Pos::new(0, 0, 0))
+    });
+    let chunk_map = chunk_map(&app, WorldId::DEFAULT);
+    assert!(chunk_map.modified_chunks.contains(&Pos::new(0, 0, 0)));
+    assert!(chunk_map.dirty_chunks.is_empty());
+    assert_eq!(chunk_map.get_voxel(&pos), Some(1));
+    // and so is the chunk that was decompressed to read the voxel
+    run_until(&mut app, |worlds| {
+        let chunk_map = worlds.get(WorldId::DEFAULT).unwrap();
+        chunk_map.compressed_chunks.len() == chunk_map.simulated_chunks.len()
+    });
+
+    // physics
+    assert!(app
+        .world
+        .get::<AvoxelBoxHandleComponent>(avoxel_box)
+        .is_some());
+    assert_eq!(
+        app.world.get::<Transform>(avoxel_box).unwrap().translation,
+        translation
+    );
+}
+
+#[test]
+fn edits_persist() {
+    let dir = std::env::temp_dir().join("avoxel_headless_edits_persist");
+    let _ = std::fs::remove_dir_all(&dir);
+    let pos = Pos::new(5, 5, 5);
+    let app_with_storage = || {
+        let mut app = headless_app();
+        app.world
+            .get_resource_mut::<ChunkWorlds>()
+            .unwrap()
+            .get_mut(WorldId::DEFAULT)
+            .unwrap()
+            .set_storage(Some(ChunkStorage::new(&dir)));
+        app.world
+            .spawn()
+            .insert(ChunkViewer::new(0, Pos::new(0, 0, 0)));
+        run_until(&mut app, |worlds| {
+            let chunk_map = worlds.get(WorldId::DEFAULT).unwrap();
+            loaded(chunk_map) && chunk_map.chunks.is_empty()
+        });
+        app
+    };
+
+    let mut app = app_with_storage();
+    app.world
+        .get_resource_mut::<ChunkWorlds>()
+        .unwrap()
+        .get_mut(WorldId::DEFAULT)
+        .unwrap()
+        .set_voxel(7, &pos);
+    run_until(&mut app, |worlds| {
+        worlds.get(WorldId::DEFAULT).unwrap().chunks.is_empty()
+    });
+    app.world
+        .get_resource_mut::<ChunkWorlds>()
+        .unwrap()
+        .get_mut(WorldId::DEFAULT)
+        .unwrap()
+        .save_modified_chunks()
+        .unwrap();
+
+    // a new app loads the saved chunk instead of generating it
+    let app = app_with_storage();
+    assert_eq!(chunk_map(&app, WorldId::DEFAULT).get_voxel(&pos), Some(7));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn generate_stone(pos: &Pos) -> Chunk {
+    Chunk::new(*pos, 1)
+}
+
+#[test]
+fn worlds_are_independent() {
+    const CAVES: WorldId = WorldId(1);
+    let mut app = headless_app();
+    app.world
+        .get_resource_mut::<ChunkWorlds>()
+        .unwrap()
+        .insert(CAVES, ChunkMap::new(false, &generate_stone));
+    app.world
+        .spawn()
+        .insert(ChunkViewer::new(1, Pos::new(0, 0, 0)));
+    app.world
+        .spawn()
+        .insert(ChunkViewer::new(0, Pos::new(10, 0, 0)))
+        .insert(CAVES);
+    let avoxel_box = app
+        .world
+        .spawn()
+        .insert(Transform::default())
+        .insert(AvoxelBoxBuilder::new(
+            Vec3::new(640.5, 100., 0.5),
+            Aabb {
+                min: Vec3::ZERO,
+                max: Vec3::ONE,
+            },
+        ))
+        .insert(CAVES)
+        .id();
+
+    run_until(&mut app, |worlds| {
+        loaded(worlds.get(WorldId::DEFAULT).unwrap()) && loaded(worlds.get(CAVES).unwrap())
+    });
+    // each world only loads the chunks around its own viewers
+    let overworld = chunk_map(&app, WorldId::DEFAULT);
+    let caves = chunk_map(&app, CAVES);
+    assert_eq!(overworld.simulated_chunks.len(), 27);
+    assert!(!overworld.simulated_chunks.contains(&Pos::new(10, 0, 0)));
+    assert_eq!(caves.simulated_chunks.len(), 1);
+    assert_eq!(caves.get_voxel(&Pos::new(645, 5, 5)), Some(1));
+
+    // edits stay in their world
+    let pos = Pos::new(5, 5, 5);
+    let overworld_voxel = overworld.get_voxel(&pos);
+    app.world
+        .get_resource_mut::<ChunkWorlds>()
+        .unwrap()
+        .get_mut(CAVES)
+        .unwrap()
+        .set_voxel(0, &Pos::new(645, 5, 5));
+    app.update();
+    assert_eq!(
+        chunk_map(&app, WorldId::DEFAULT).get_voxel(&pos),
+        overworld_voxel
+    );
+    assert_eq!(
+        chunk_map(&app, CAVES).get_voxel(&Pos::new(645, 5, 5)),
+        Some(0)
+    );
+
+    // the box collides with the world of its entity
+    let handle = app
+        .world
+        .get::<AvoxelBoxHandleComponent>(avoxel_box)
+        .unwrap()
+        .handle();
+    let box_map = app.world.get_resource::<BoxMap>().unwrap();
+    assert_eq!(box_map.get(handle).unwrap().world(), CAVES);
+}
@@ -0,0 +1,54 @@
+use crate::material::BlockMaterial;
+use avoxel_chunk_map::WorldId;
+use bevy::{prelude::*, utils::HashMap};
+
+/// The texture and materials the blocks of the `BlockLibrary` are rendered with.
+/// Worlds with a block library of their own can have their own material.
+#[derive(Default)]
+pub struct BlockMaterials {
+    texture_handle: Handle<Texture>,
+    material_handles: Vec<Handle<BlockMaterial>>,
+    world_material_handles: HashMap<WorldId, Handle<BlockMaterial>>,
+}
+
+impl BlockMaterials {
+    pub fn get_texture_handle(&self) -> Handle<Texture> {
+        self.texture_handle.clone()
+    }
+
+    pub fn set_texture_handle(&mut self, texture_handle: Handle<Texture>) -> &mut Self {
+        self.texture_handle = texture_handle;
+        self
+    }
+
+    pub fn add_material_handle(&mut self, material_handle: Handle<BlockMaterial>) -> &mut Self {
+        self.material_handles.push(material_handle);
+        self
+    }
+
+    /// Falls back to the default material until a material is added
+    pub fn get_material_handle(&self, material_id: usize) -> Handle<BlockMaterial> {
+        self.material_handles
+            .get(material_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Renders the chunks of `world` with the material instead of the first one
+    pub fn set_world_material_handle(
+        &mut self,
+        world: WorldId,
+        material_handle: Handle<BlockMaterial>,
+    ) -> &mut Self {
+        self.world_material_handles.insert(world, material_handle);
+        self
+    }
+
+    /// The material the chunks of `world` are rendered with
+    pub fn get_world_material_handle(&self, world: WorldId) -> Handle<BlockMaterial> {
+        self.world_material_handles
+            .get(&world)
+            .cloned()
+            .unwrap_or_else(|| self.get_material_handle(0))
+    }
+}
@@ -1,3 +1,4 @@
+use avoxel_blocks::BlockLibrary;
 use bevy::prelude::*;
 use bevy::render::shader;
 
@@ -5,17 +6,30 @@ pub use entity::*;
 use material::BlockMaterial;
 pub use material::*;
 
+use crate::block_materials::BlockMaterials;
 use crate::fog_settings::FogSettings;
 use crate::render_graph::add_avoxel_graph;
+use crate::texture_animation::{update_texture_animation_frames, TextureAnimationFrames};
+use crate::texture_array_builder::build_texture_array_system;
 
 pub mod render_graph;
 
+mod block_materials;
 mod entity;
 mod fog_settings;
 mod material;
+mod texture_animation;
+mod texture_array_builder;
 
 pub mod prelude {
-    pub use crate::{entity::*, fog_settings::FogSettings, material::BlockMaterial};
+    pub use crate::{
+        block_materials::BlockMaterials,
+        entity::*,
+        fog_settings::FogSettings,
+        material::BlockMaterial,
+        texture_animation::TextureAnimationFrames,
+        texture_array_builder::{TextureArrayBuilder, TextureArrayError},
+    };
 }
 
 #[derive(Default)]
@@ -23,12 +37,19 @@ pub struct AvoxelRenderingPlugin;
 
 impl Plugin for AvoxelRenderingPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        if app.world().get_resource::<BlockLibrary>().is_none() {
+            app.init_resource::<BlockLibrary>();
+        }
         app.add_asset::<BlockMaterial>()
             .add_system_to_stage(
                 CoreStage::PostUpdate,
                 shader::asset_shader_defs_system::<BlockMaterial>.system(),
             )
-            .init_resource::<FogSettings>();
+            .init_resource::<FogSettings>()
+            .init_resource::<TextureAnimationFrames>()
+            .init_resource::<BlockMaterials>()
+            .add_system(build_texture_array_system.system())
+            .add_system(update_texture_animation_frames.system());
 
         add_avoxel_graph(app.world_mut());
 
@@ -1,5 +1,6 @@
 mod fog_settings_node;
 mod pipeline;
+mod texture_animation_node;
 
 pub use pipeline::*;
 
@@ -7,14 +8,17 @@ pub use pipeline::*;
 pub mod node {
     pub const BLOCK_MATERIAL: &str = "block_material";
     pub const FOG_SETTINGS: &str = "fog_settings";
+    pub const TEXTURE_ANIMATION: &str = "texture_animation";
 }
 
 pub mod uniform {
     pub const FOG_SETTINGS: &str = "FogSettings";
+    pub const TEXTURE_ANIMATION: &str = "TextureAnimation";
 }
 
 use crate::prelude::BlockMaterial;
 use crate::render_graph::fog_settings_node::FogSettingsNode;
+use crate::render_graph::texture_animation_node::TextureAnimationNode;
 use bevy::prelude::{Assets, Shader, World};
 use bevy::render::pipeline::PipelineDescriptor;
 use bevy::render::render_graph::{base, AssetRenderResourcesNode, RenderGraph};
@@ -26,6 +30,7 @@ pub(crate) fn add_avoxel_graph(world: &mut World) {
         AssetRenderResourcesNode::<BlockMaterial>::new(true),
     );
     graph.add_system_node(node::FOG_SETTINGS, FogSettingsNode::new());
+    graph.add_system_node(node::TEXTURE_ANIMATION, TextureAnimationNode::new());
     graph
         .add_node_edge(node::BLOCK_MATERIAL, base::node::MAIN_PASS)
         .unwrap();
@@ -52,6 +52,10 @@ layout(location = 3) in vec4 v_WorldTangent;
 
 layout(location = 4) in float v_Layer;
 layout(location = 5) in float v_FogAmount;
+layout(location = 6) in float v_NextLayer;
+layout(location = 7) in float v_FrameBlend;
+layout(location = 8) in vec3 v_Tint;
+layout(location = 9) in float v_Triplanar;
 layout(set = 1, binding = 1) uniform FogSettings {
     vec4 FogColor;
     float FogNear;
@@ -280,18 +284,55 @@ vec3 reinhard_extended_luminance(vec3 color, float max_white_l) {
 
 #endif
 
+#ifdef BLOCKMATERIAL_BASE_COLOR_TEXTURE
+vec4 base_color_texture(vec2 uv) {
+    vec4 texture_color = texture(
+        sampler2DArray(BlockMaterial_base_color_texture, BlockMaterial_base_color_texture_sampler),
+        vec3(uv, v_Layer));
+    // interpolated texture animations blend into the next frame
+    if (v_FrameBlend > 0.0) {
+        texture_color = mix(texture_color, texture(
+            sampler2DArray(BlockMaterial_base_color_texture, BlockMaterial_base_color_texture_sampler),
+            vec3(uv, v_NextLayer)), v_FrameBlend);
+    }
+    return texture_color;
+}
+#endif
+
 void main() {
+    // triplanar faces project the texture along each axis, the other
+    // textures use the projection along the axis closest to the normal
+    vec3 blend = pow(abs(normalize(v_WorldNormal)), vec3(4.0));
+    blend /= blend.x + blend.y + blend.z;
+    vec2 uv = v_Uv;
+    if (v_Triplanar > 0.5) {
+        if (blend.x > blend.y && blend.x > blend.z) {
+            uv = fract(v_WorldPosition.zy);
+        } else if (blend.y > blend.z) {
+            uv = fract(v_WorldPosition.xz);
+        } else {
+            uv = fract(v_WorldPosition.xy);
+        }
+    }
+
     vec4 output_color = base_color;
 #ifdef BLOCKMATERIAL_BASE_COLOR_TEXTURE
-    output_color *= texture(
-        sampler2DArray(BlockMaterial_base_color_texture, BlockMaterial_base_color_texture_sampler),
-        vec3(v_Uv, v_Layer));
+    vec4 texture_color;
+    if (v_Triplanar > 0.5) {
+        texture_color = base_color_texture(fract(v_WorldPosition.zy)) * blend.x
+            + base_color_texture(fract(v_WorldPosition.xz)) * blend.y
+            + base_color_texture(fract(v_WorldPosition.xy)) * blend.z;
+    } else {
+        texture_color = base_color_texture(uv);
+    }
+    output_color *= texture_color;
 #endif
+    output_color.rgb *= v_Tint;
 
 #ifndef BLOCKMATERIAL_UNLIT
     // calculate non-linear roughness from linear perceptualRoughness
 #    ifdef BLOCKMATERIAL_METALLIC_ROUGHNESS_TEXTURE
-    vec4 metallic_roughness = texture(sampler2DArray(BlockMaterial_metallic_roughness_texture, BlockMaterial_metallic_roughness_texture_sampler), vec3(v_Uv, v_Layer));
+    vec4 metallic_roughness = texture(sampler2DArray(BlockMaterial_metallic_roughness_texture, BlockMaterial_metallic_roughness_texture_sampler), vec3(uv, v_Layer));
     // Sampling from GLTF standard channels for now
     float metallic = metallic * metallic_roughness.b;
     float perceptual_roughness = perceptual_roughness * metallic_roughness.g;
@@ -316,11 +357,11 @@ void main() {
 
 #    ifdef BLOCKMATERIAL_NORMAL_MAP
     mat3 TBN = mat3(T, B, N);
-    N = TBN * normalize(texture(sampler2DArray(BlockMaterial_normal_map, BlockMaterial_normal_map_sampler), vec3(v_Uv, v_Layer)).rgb * 2.0 - 1.0);
+    N = TBN * normalize(texture(sampler2DArray(BlockMaterial_normal_map, BlockMaterial_normal_map_sampler), vec3(uv, v_Layer)).rgb * 2.0 - 1.0);
 #    endif
 
 #    ifdef BLOCKMATERIAL_OCCLUSION_TEXTURE
-    float occlusion = texture(sampler2DArray(BlockMaterial_occlusion_texture, BlockMaterial_occlusion_texture_sampler), vec3(v_Uv, v_Layer)).r;
+    float occlusion = texture(sampler2DArray(BlockMaterial_occlusion_texture, BlockMaterial_occlusion_texture_sampler), vec3(uv, v_Layer)).r;
 #    else
     float occlusion = 1.0;
 #    endif
@@ -328,7 +369,7 @@ void main() {
 #    ifdef BLOCKMATERIAL_EMISSIVE_TEXTURE
     vec4 emissive = emissive;
     // TODO use .a for exposure compensation in HDR
-    emissive.rgb *= texture(sampler2DArray(BlockMaterial_emissive_texture, BlockMaterial_emissive_texture_sampler), vec3(v_Uv, v_Layer)).rgb;
+    emissive.rgb *= texture(sampler2DArray(BlockMaterial_emissive_texture, BlockMaterial_emissive_texture_sampler), vec3(uv, v_Layer)).rgb;
 #    endif
 
     vec3 V = normalize(CameraPos.xyz - v_WorldPosition.xyz);
@@ -2,7 +2,12 @@
 
 layout(location = 0) in vec3 Vertex_Position;
 layout(location = 1) in vec3 Vertex_Normal;
+// bits 0-11 texture layer, 12-16 u, 17-21 v, 31 triplanar
 layout(location = 2) in uint Texture_Datum;
+// bits 0-7 frame count, 8-15 first frame, 16-30 frame time in ms, 31 interpolate
+layout(location = 4) in uint Texture_Animation;
+// 0xRRGGBB
+layout(location = 5) in uint Vertex_Tint;
 
 #ifdef STANDARDMATERIAL_NORMAL_MAP
 layout(location = 3) in vec4 Vertex_Tangent;
@@ -29,18 +34,48 @@ layout(set = 1, binding = 1) uniform FogSettings {
     float FogNear;
     float FogFar;
 };
+layout(set = 1, binding = 2) uniform TextureAnimation {
+    float Time;
+    uvec4 Frames[64];
+};
 layout(location = 4) out float v_Layer;
 layout(location = 5) out float v_FogAmount;
+layout(location = 6) out float v_NextLayer;
+layout(location = 7) out float v_FrameBlend;
+layout(location = 8) out vec3 v_Tint;
+layout(location = 9) out float v_Triplanar;
+
+uint animation_frame(uint index) {
+    return Frames[index / 4u][index % 4u];
+}
 
 void main() {
     vec4 world_position = Model * vec4(Vertex_Position, 1.0);
     v_WorldPosition = world_position.xyz;
     v_WorldNormal = mat3(Model) * Vertex_Normal;
-    v_Uv = vec2(Texture_Datum >> 12u & 0x1u, Texture_Datum >> 13u & 0x1u);
+    v_Uv = vec2(Texture_Datum >> 12u & 0x1Fu, Texture_Datum >> 17u & 0x1Fu) / 16.0;
     #ifdef STANDARDMATERIAL_NORMAL_MAP
     v_WorldTangent = vec4(mat3(Model) * Vertex_Tangent.xyz, Vertex_Tangent.w);
     #endif
     gl_Position = ViewProj * world_position;
     v_Layer = Texture_Datum & 0xFFFu;
+    // smooth meshes are textured by their world position instead of their uvs
+    v_Triplanar = float(Texture_Datum >> 31u);
+    // tints are given in sRGB like the textures
+    v_Tint = pow(vec3(Vertex_Tint >> 16u & 0xFFu, Vertex_Tint >> 8u & 0xFFu, Vertex_Tint & 0xFFu) / 255.0, vec3(2.2));
+    v_NextLayer = v_Layer;
+    v_FrameBlend = 0.0;
+    uint frame_count = Texture_Animation & 0xFFu;
+    if (frame_count > 1u) {
+        uint first_frame = Texture_Animation >> 8u & 0xFFu;
+        float frame_time = max(float(Texture_Animation >> 16u & 0x7FFFu) / 1000.0, 0.001);
+        float frame = Time / frame_time;
+        uint index = uint(frame) % frame_count;
+        v_Layer = animation_frame(first_frame + index);
+        v_NextLayer = animation_frame(first_frame + (index + 1u) % frame_count);
+        if ((Texture_Animation >> 31u) != 0u) {
+            v_FrameBlend = fract(frame);
+        }
+    }
     v_FogAmount = smoothstep(FogNear, FogFar, length(gl_Position.xyz));
 }
@@ -0,0 +1,129 @@
+use crate::{render_graph::uniform, texture_animation::TextureAnimationFrames};
+use bevy::ecs::system::BoxedSystem;
+use bevy::{
+    core::{AsBytes, Time},
+    ecs::system::IntoSystem,
+    prelude::{Local, Res, ResMut, World},
+    render::{
+        render_graph::{CommandQueue, Node, ResourceSlots, SystemNode},
+        renderer::{
+            BufferId, BufferInfo, BufferMapMode, BufferUsage, RenderContext, RenderResourceBinding,
+            RenderResourceBindings, RenderResourceContext,
+        },
+    },
+};
+
+/// The time is wrapped so it keeps its precision in long sessions
+const TIME_WRAP_SECONDS: f64 = 3600.0;
+
+/// Writes the time and the animation frames the shader uses to animate block textures
+#[derive(Debug)]
+pub struct TextureAnimationNode {
+    command_queue: CommandQueue,
+}
+
+impl TextureAnimationNode {
+    pub fn new() -> Self {
+        Self {
+            command_queue: Default::default(),
+        }
+    }
+}
+
+impl Node for TextureAnimationNode {
+    fn update(
+        &mut self,
+        _world: &World,
+        render_context: &mut dyn RenderContext,
+        _input: &ResourceSlots,
+        _output: &mut ResourceSlots,
+    ) {
+        self.command_queue.execute(render_context);
+    }
+}
+
+impl SystemNode for TextureAnimationNode {
+    fn get_system(&self) -> BoxedSystem {
+        let system = texture_animation_node_system.system().config(|config| {
+            config.0 = Some(TextureAnimationNodeState {
+                command_queue: self.command_queue.clone(),
+                texture_animation_buffer: None,
+                staging_buffer: None,
+            })
+        });
+        Box::new(system)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TextureAnimationNodeState {
+    command_queue: CommandQueue,
+    texture_animation_buffer: Option<BufferId>,
+    staging_buffer: Option<BufferId>,
+}
+
+pub fn texture_animation_node_system(
+    mut state: Local<TextureAnimationNodeState>,
+    render_resource_context: Res<Box<dyn RenderResourceContext>>,
+    mut render_resource_bindings: ResMut<RenderResourceBindings>,
+    time: Res<Time>,
+    animation_frames: Res<TextureAnimationFrames>,
+) {
+    // the time is padded to 16 bytes, each frame takes up 4 bytes of an uvec4
+    let time_size = std::mem::size_of::<[f32; 4]>();
+    let frames_size = TextureAnimationFrames::MAX_FRAMES * std::mem::size_of::<u32>();
+    let texture_animation_uniform_size = time_size + frames_size;
+    let staging_buffer = if let Some(staging_buffer) = state.staging_buffer {
+        render_resource_context.map_buffer(staging_buffer, BufferMapMode::Write);
+        staging_buffer
+    } else {
+        let buffer = render_resource_context.create_buffer(BufferInfo {
+            size: texture_animation_uniform_size,
+            buffer_usage: BufferUsage::COPY_DST | BufferUsage::UNIFORM,
+            ..Default::default()
+        });
+        render_resource_bindings.set(
+            uniform::TEXTURE_ANIMATION,
+            RenderResourceBinding::Buffer {
+                buffer,
+                range: 0..texture_animation_uniform_size as u64,
+                dynamic_index: None,
+            },
+        );
+        state.texture_animation_buffer = Some(buffer);
+
+        let staging_buffer = render_resource_context.create_buffer(BufferInfo {
+            size: texture_animation_uniform_size,
+            buffer_usage: BufferUsage::COPY_SRC | BufferUsage::MAP_WRITE,
+            mapped_at_creation: true,
+        });
+
+        state.staging_buffer = Some(staging_buffer);
+        staging_buffer
+    };
+
+    let seconds = (time.seconds_since_startup() % TIME_WRAP_SECONDS) as f32;
+    let mut frames = [0u32; TextureAnimationFrames::MAX_FRAMES];
+    for (frame, layer) in frames.iter_mut().zip(animation_frames.frames.iter()) {
+        *frame = *layer;
+    }
+    render_resource_context.write_mapped_buffer(
+        staging_buffer,
+        0..texture_animation_uniform_size as u64,
+        &mut |data, _renderer| {
+            // time
+            data[0..std::mem::size_of::<f32>()].copy_from_slice(seconds.as_bytes());
+            // frames
+            data[time_size..texture_animation_uniform_size].copy_from_slice(frames[..].as_bytes());
+        },
+    );
+    render_resource_context.unmap_buffer(staging_buffer);
+    let texture_animation_buffer = state.texture_animation_buffer.unwrap();
+    state.command_queue.copy_buffer_to_buffer(
+        staging_buffer,
+        0,
+        texture_animation_buffer,
+        0,
+        texture_animation_uniform_size as u64,
+    );
+}
@@ -0,0 +1,24 @@
+use avoxel_blocks::{BlockLibrary, MAX_ANIMATION_FRAMES};
+use bevy::prelude::*;
+
+/// The texture array layers of all block texture animations.
+/// The meshes store where the frames of an animation start in this table.
+#[derive(Debug, Default)]
+pub struct TextureAnimationFrames {
+    pub frames: Vec<u32>,
+}
+
+impl TextureAnimationFrames {
+    /// The number of frames the uniform has room for
+    pub const MAX_FRAMES: usize = MAX_ANIMATION_FRAMES;
+}
+
+/// Hands the animation frames of the block textures to the renderer
+pub fn update_texture_animation_frames(
+    block_library: Res<BlockLibrary>,
+    mut animation_frames: ResMut<TextureAnimationFrames>,
+) {
+    if block_library.is_changed() {
+        animation_frames.frames = block_library.animation_frames();
+    }
+}
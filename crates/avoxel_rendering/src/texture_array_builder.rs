@@ -0,0 +1,298 @@
+use crate::block_materials::BlockMaterials;
+use avoxel_blocks::{BlockLibrary, BlockTexture};
+use bevy::{
+    asset::HandleId,
+    prelude::*,
+    render::texture::{Extent3d, TextureDimension, TextureFormat},
+};
+use std::fmt;
+
+const PIXEL_SIZE: usize = 4;
+
+/// Stacks individual block images into one array texture for the block material.
+/// Texture ids are assigned in the order the images are added.
+///
+/// Insert it as a resource. The textures and texture count of the `BlockLibrary` are
+/// set right away, so block library files can use the texture names and keep the
+/// textures when they are reloaded. Once all images are loaded the array texture is
+/// built, the texture size of the `BlockLibrary` and the texture handle of the
+/// `BlockMaterials` are set.
+pub struct TextureArrayBuilder {
+    textures: Vec<(String, Handle<Texture>, BlockTexture)>,
+    handle_id: HandleId,
+    built: bool,
+}
+
+impl Default for TextureArrayBuilder {
+    fn default() -> Self {
+        Self {
+            textures: vec![],
+            handle_id: HandleId::random::<Texture>(),
+            built: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureArrayError {
+    NoTextures,
+    NotLoaded {
+        name: String,
+    },
+    NotSquare {
+        name: String,
+        width: u32,
+        height: u32,
+    },
+    SizeMismatch {
+        name: String,
+        size: u32,
+        expected: u32,
+    },
+    UnsupportedFormat {
+        name: String,
+        format: TextureFormat,
+    },
+}
+
+impl fmt::Display for TextureArrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureArrayError::NoTextures => write!(f, "no block textures were added"),
+            TextureArrayError::NotLoaded { name } => {
+                write!(f, "block texture \"{}\" isn't loaded", name)
+            }
+            TextureArrayError::NotSquare {
+                name,
+                width,
+                height,
+            } => write!(
+                f,
+                "block texture \"{}\" is {}x{} but has to be square",
+                name, width, height
+            ),
+            TextureArrayError::SizeMismatch {
+                name,
+                size,
+                expected,
+            } => write!(
+                f,
+                "block texture \"{}\" is {}x{} but the other textures are {}x{}",
+                name, size, size, expected, expected
+            ),
+            TextureArrayError::UnsupportedFormat { name, format } => write!(
+                f,
+                "block texture \"{}\" has format {:?}, only Rgba8UnormSrgb is supported",
+                name, format
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureArrayError {}
+
+impl TextureArrayBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_texture(
+        &mut self,
+        name: &str,
+        handle: Handle<Texture>,
+        block_texture: BlockTexture,
+    ) -> &mut Self {
+        self.textures
+            .push((name.to_string(), handle, block_texture));
+        self
+    }
+
+    /// Returns the texture id of the texture with `name`
+    pub fn get_texture_id(&self, name: &str) -> Option<u32> {
+        self.textures
+            .iter()
+            .position(|(texture_name, _, _)| texture_name == name)
+            .map(|texture_id| texture_id as u32)
+    }
+
+    /// The handle the array texture will have, materials can use it before it's built
+    pub fn texture_handle(&self) -> Handle<Texture> {
+        Handle::weak(self.handle_id)
+    }
+
+    pub fn is_loaded(&self, textures: &Assets<Texture>) -> bool {
+        self.textures
+            .iter()
+            .all(|(_, handle, _)| textures.get(handle).is_some())
+    }
+
+    /// Checks that all images are loaded, square and the same size and stacks them
+    pub fn build(&self, textures: &Assets<Texture>) -> Result<Texture, TextureArrayError> {
+        stack_textures(
+            self.textures
+                .iter()
+                .map(|(name, handle, _)| (name.as_str(), textures.get(handle))),
+        )
+    }
+
+    /// The names and settings of the textures in texture id order
+    fn block_textures(&self) -> Vec<(String, BlockTexture)> {
+        self.textures
+            .iter()
+            .map(|(name, _, block_texture)| (name.clone(), block_texture.clone()))
+            .collect()
+    }
+}
+
+/// Stacks square textures of the same size into the layers of an array texture.
+/// `None` stands for a texture that isn't loaded.
+fn stack_textures<'a>(
+    textures: impl Iterator<Item = (&'a str, Option<&'a Texture>)>,
+) -> Result<Texture, TextureArrayError> {
+    let mut texture_size = None;
+    let mut data = vec![];
+    for (name, texture) in textures {
+        let texture = texture.ok_or_else(|| TextureArrayError::NotLoaded {
+            name: name.to_string(),
+        })?;
+        if texture.format != TextureFormat::Rgba8UnormSrgb {
+            return Err(TextureArrayError::UnsupportedFormat {
+                name: name.to_string(),
+                format: texture.format,
+            });
+        }
+        let Extent3d { width, height, .. } = texture.size;
+        if width != height {
+            return Err(TextureArrayError::NotSquare {
+                name: name.to_string(),
+                width,
+                height,
+            });
+        }
+        let expected = *texture_size.get_or_insert(width);
+        if width != expected {
+            return Err(TextureArrayError::SizeMismatch {
+                name: name.to_string(),
+                size: width,
+                expected,
+            });
+        }
+        data.extend_from_slice(&texture.data);
+    }
+    let texture_size = texture_size.ok_or(TextureArrayError::NoTextures)?;
+    let layers = (data.len() / (texture_size * texture_size) as usize / PIXEL_SIZE) as u32;
+    Ok(Texture::new(
+        Extent3d::new(texture_size, texture_size, layers),
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    ))
+}
+
+/// Builds the array texture of a `TextureArrayBuilder` resource once its images are loaded
+pub fn build_texture_array_system(
+    builder: Option<ResMut<TextureArrayBuilder>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut block_library: ResMut<BlockLibrary>,
+    mut block_materials: ResMut<BlockMaterials>,
+) {
+    let mut builder = match builder {
+        Some(builder) => builder,
+        None => return,
+    };
+    if builder.is_added() {
+        // texture ids are known before the images are loaded,
+        // block library files can use the texture names right away
+        block_library
+            .set_block_textures(builder.block_textures())
+            .set_texture_count(builder.textures.len() as u32);
+    }
+    if builder.built || !builder.is_loaded(&textures) {
+        return;
+    }
+    builder.built = true;
+    let texture = match builder.build(&textures) {
+        Ok(texture) => texture,
+        Err(e) => {
+            error!("failed to build the block texture array: {}", e);
+            return;
+        }
+    };
+    let texture_size = texture.size.width;
+    let texture_handle = textures.set(builder.handle_id, texture);
+    block_materials.set_texture_handle(texture_handle);
+    block_library.set_texture_size(texture_size);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stack_textures, TextureArrayError, PIXEL_SIZE};
+    use bevy::render::texture::{Extent3d, Texture, TextureDimension, TextureFormat};
+
+    fn texture(width: u32, height: u32, value: u8, format: TextureFormat) -> Texture {
+        Texture::new(
+            Extent3d::new(width, height, 1),
+            TextureDimension::D2,
+            vec![value; (width * height) as usize * PIXEL_SIZE],
+            format,
+        )
+    }
+
+    fn rgba(size: u32, value: u8) -> Texture {
+        texture(size, size, value, TextureFormat::Rgba8UnormSrgb)
+    }
+
+    #[test]
+    fn stacks_textures_as_layers() {
+        let (a, b) = (rgba(4, 1), rgba(4, 2));
+        let array = stack_textures(vec![("a", Some(&a)), ("b", Some(&b))].into_iter()).unwrap();
+        assert_eq!(array.size, Extent3d::new(4, 4, 2));
+        assert_eq!(array.format, TextureFormat::Rgba8UnormSrgb);
+        let layer_len = 4 * 4 * PIXEL_SIZE;
+        assert!(array.data[..layer_len].iter().all(|v| *v == 1));
+        assert!(array.data[layer_len..].iter().all(|v| *v == 2));
+    }
+
+    #[test]
+    fn errors() {
+        let square = rgba(4, 0);
+        let small = rgba(2, 0);
+        let wide = texture(4, 2, 0, TextureFormat::Rgba8UnormSrgb);
+        let linear = texture(4, 4, 0, TextureFormat::Rgba8Unorm);
+        let cases: Vec<(Vec<(&str, Option<&Texture>)>, &str)> = vec![
+            (vec![], "NoTextures"),
+            (vec![("a", Some(&square)), ("b", None)], "NotLoaded"),
+            (vec![("a", Some(&wide))], "NotSquare"),
+            (
+                vec![("a", Some(&square)), ("b", Some(&small))],
+                "SizeMismatch",
+            ),
+            (vec![("a", Some(&linear))], "UnsupportedFormat"),
+        ];
+        for (textures, expected) in cases {
+            let names: Vec<&str> = textures.iter().map(|(name, _)| *name).collect();
+            let error = match stack_textures(textures.into_iter()) {
+                Ok(_) => panic!("{:?} were stacked", names),
+                Err(error) => error,
+            };
+            assert!(
+                format!("{:?}", error).starts_with(expected),
+                "expected {} from {:?}, got {}",
+                expected,
+                names,
+                error
+            );
+        }
+        let error = stack_textures(vec![("a", Some(&square)), ("b", Some(&small))].into_iter())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            TextureArrayError::SizeMismatch {
+                size: 2,
+                expected: 4,
+                ..
+            }
+        ));
+    }
+}
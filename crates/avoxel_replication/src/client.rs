@@ -0,0 +1,268 @@
+use crate::{
+    messages::{decode, encode, ClientMessage, ServerMessage, VoxelEdit},
+    transport::{Transport, TransportEvent, SERVER},
+};
+use avoxel_chunk::{Voxel, CHUNK_SIZE};
+use avoxel_chunk_map::{ChunkMap, ChunkViewer, ChunkWorlds, WorldId};
+use avoxel_math::{DivFloor, Extent3, Pos};
+use bevy_ecs::prelude::*;
+use bevy_log::warn;
+use bevy_tasks::AsyncComputeTaskPool;
+use bevy_utils::{HashMap, HashSet};
+
+/// Sent when the server rejected an edit this client requested.
+/// The prediction of the edit was already reverted.
+pub struct EditRejectedEvent {
+    pub sequence: u32,
+    pub edit: VoxelEdit,
+}
+
+struct PredictedEdit {
+    sequence: u32,
+    edit: VoxelEdit,
+}
+
+/// Receives the chunks within the client's `ChunkViewer` from the server.
+/// Requested edits are predicted, they are applied to the `ChunkMap` right away
+/// and kept on top of the edits from the server until the server confirms or rejects them.
+pub struct ReplicationClient {
+    transport: Box<dyn Transport>,
+    /// The viewer position and distances the server knows about
+    sent_viewer: Option<(Pos, i32, i32)>,
+    next_sequence: u32,
+    /// Edits the server hasn't confirmed or rejected yet, oldest first
+    predicted_edits: Vec<PredictedEdit>,
+    /// The voxels the server has at positions with predicted edits
+    server_voxels: HashMap<Pos, Voxel>,
+    /// The world the chunks of the server go to
+    world: WorldId,
+    /// Chunks of the server the client has, the server has to be told when they're gone
+    received_chunks: HashSet<Pos>,
+    /// Chunks the server sent that the client didn't keep
+    dropped_chunks: Vec<Pos>,
+}
+
+impl ReplicationClient {
+    pub fn new<T: Transport>(transport: T) -> Self {
+        Self {
+            transport: Box::new(transport),
+            sent_viewer: None,
+            next_sequence: 0,
+            predicted_edits: vec![],
+            server_voxels: Default::default(),
+            world: WorldId::DEFAULT,
+            received_chunks: Default::default(),
+            dropped_chunks: vec![],
+        }
+    }
+
+    /// Applies the edit to the chunk map and asks the server to make it.
+    /// Returns the sequence number of the request, or `None` if the voxel isn't loaded.
+    pub fn request_edit(
+        &mut self,
+        chunk_map: &mut ChunkMap,
+        voxel: Voxel,
+        pos: &Pos,
+    ) -> Option<u32> {
+        let current_voxel = chunk_map.get_voxel(pos)?;
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        let edit = VoxelEdit { pos: *pos, voxel };
+        self.server_voxels.entry(*pos).or_insert(current_voxel);
+        self.predicted_edits.push(PredictedEdit { sequence, edit });
+        chunk_map.set_voxel(voxel, pos);
+        self.send(&ClientMessage::EditRequest { sequence, edit });
+        Some(sequence)
+    }
+
+    /// Puts the chunks of the server into another world, has to be set before the app runs
+    pub fn set_world(&mut self, world: WorldId) {
+        self.world = world;
+    }
+
+    pub fn world(&self) -> WorldId {
+        self.world
+    }
+
+    /// Number of requested edits the server hasn't answered yet
+    pub fn predicted_edit_count(&self) -> usize {
+        self.predicted_edits.len()
+    }
+
+    fn send(&self, message: &ClientMessage) {
+        self.transport.send(SERVER, encode(message));
+    }
+
+    /// Sets the voxel to the newest prediction at the position or to the voxel
+    /// of the server once there are no predictions left
+    fn resolve(&mut self, chunk_map: &mut ChunkMap, pos: &Pos) {
+        let server_voxel = match self.server_voxels.get(pos) {
+            Some(server_voxel) => *server_voxel,
+            None => return,
+        };
+        let predicted_voxel = self
+            .predicted_edits
+            .iter()
+            .rev()
+            .find(|predicted| predicted.edit.pos == *pos)
+            .map(|predicted| predicted.edit.voxel);
+        if predicted_voxel.is_none() {
+            self.server_voxels.remove(pos);
+        }
+        chunk_map.set_voxel(predicted_voxel.unwrap_or(server_voxel), pos);
+    }
+
+    /// Edits from the server are applied in order, predictions stay on top of them
+    fn apply_server_edit(&mut self, chunk_map: &mut ChunkMap, edit: &VoxelEdit) {
+        match self.server_voxels.get_mut(&edit.pos) {
+            Some(server_voxel) => {
+                *server_voxel = edit.voxel;
+                self.resolve(chunk_map, &edit.pos);
+            }
+            None => chunk_map.set_voxel(edit.voxel, &edit.pos),
+        }
+    }
+
+    /// Drops the prediction of an acked or nacked request
+    fn finish_edit(&mut self, chunk_map: &mut ChunkMap, sequence: u32) -> Option<VoxelEdit> {
+        let i = self
+            .predicted_edits
+            .iter()
+            .position(|predicted| predicted.sequence == sequence)?;
+        let predicted = self.predicted_edits.remove(i);
+        self.resolve(chunk_map, &predicted.edit.pos);
+        Some(predicted.edit)
+    }
+
+    /// A chunk from the server overwrites the predictions within it
+    fn reapply_predictions(&mut self, chunk_map: &mut ChunkMap, chunk_pos: Pos, extent: Extent3) {
+        let positions: Vec<Pos> = self
+            .server_voxels
+            .keys()
+            .filter(|pos| extent.contains_point(**pos))
+            .copied()
+            .collect();
+        for pos in positions {
+            if pos.div_floor(CHUNK_SIZE) == chunk_pos {
+                if let Some(server_voxel) = chunk_map.get_voxel(&pos) {
+                    self.server_voxels.insert(pos, server_voxel);
+                }
+            }
+            self.resolve(chunk_map, &pos);
+        }
+    }
+}
+
+/// Chunks and their changes come from the server so the client doesn't generate
+/// chunks or run block ticks and fluids
+pub fn disable_simulation(client: Res<ReplicationClient>, mut worlds: ResMut<ChunkWorlds>) {
+    match worlds.get_mut(client.world) {
+        Some(chunk_map) => {
+            chunk_map.set_generating(false);
+            chunk_map.set_simulating(false);
+        }
+        None => warn!("the replicated world {:?} doesn't exist", client.world),
+    }
+}
+
+/// Tells the server which of its chunks the client unloaded or evicted on its own,
+/// otherwise the server would think the client still has them and never send them again
+pub fn send_dropped_chunks(mut client: ResMut<ReplicationClient>, worlds: Res<ChunkWorlds>) {
+    let chunk_map = match worlds.get(client.world) {
+        Some(chunk_map) => chunk_map,
+        None => return,
+    };
+    let client = &mut *client;
+    let mut dropped_chunks = std::mem::take(&mut client.dropped_chunks);
+    client.received_chunks.retain(|pos| {
+        let kept = chunk_map.contains_chunk(pos);
+        if !kept {
+            dropped_chunks.push(*pos);
+        }
+        kept
+    });
+    if !dropped_chunks.is_empty() {
+        client.send(&ClientMessage::DroppedChunks(dropped_chunks));
+    }
+}
+
+/// Tells the server where the first `ChunkViewer` of the client's world is whenever it moves
+pub fn send_viewer_updates(
+    mut client: ResMut<ReplicationClient>,
+    viewers: Query<(&ChunkViewer, Option<&WorldId>)>,
+) {
+    let viewer = viewers
+        .iter()
+        .find(|(_, world)| world.copied().unwrap_or_default() == client.world)
+        .map(|(viewer, _)| viewer);
+    let viewer = match viewer {
+        Some(viewer) => viewer,
+        None => return,
+    };
+    let current = (
+        viewer.get_pos(),
+        viewer.render_distance(),
+        viewer.simulate_distance(),
+    );
+    if client.sent_viewer == Some(current) {
+        return;
+    }
+    client.sent_viewer = Some(current);
+    client.send(&ClientMessage::Viewer {
+        pos: current.0,
+        render_distance: current.1,
+        simulate_distance: current.2,
+    });
+}
+
+pub fn receive_server_messages(
+    pool: Res<AsyncComputeTaskPool>,
+    mut client: ResMut<ReplicationClient>,
+    mut worlds: ResMut<ChunkWorlds>,
+    mut rejected_events: EventWriter<EditRejectedEvent>,
+) {
+    let chunk_map = match worlds.get_mut(client.world) {
+        Some(chunk_map) => chunk_map,
+        None => return,
+    };
+    while let Some(event) = client.transport.poll() {
+        let bytes = match event {
+            TransportEvent::Message(_, bytes) => bytes,
+            _ => continue,
+        };
+        match decode(&bytes) {
+            Some(ServerMessage::Chunk(compressed_chunk)) => {
+                // the viewer may have moved on while the chunk was on its way
+                let pos = compressed_chunk.pos;
+                if chunk_map.simulated_chunks.contains(&pos)
+                    || chunk_map.lod_only_chunks.contains(&pos)
+                {
+                    let chunk = compressed_chunk.decompress(true);
+                    let (chunk_pos, extent) = (chunk.pos, chunk.extent());
+                    chunk_map.insert_chunk(pool.clone(), chunk);
+                    client.received_chunks.insert(chunk_pos);
+                    client.reapply_predictions(chunk_map, chunk_pos, extent);
+                } else {
+                    client.dropped_chunks.push(pos);
+                }
+            }
+            Some(ServerMessage::UnloadChunk(pos)) => {
+                client.received_chunks.remove(&pos);
+                chunk_map.remove_chunk(&pos);
+            }
+            Some(ServerMessage::VoxelEdits(edits)) => {
+                for edit in edits.iter() {
+                    client.apply_server_edit(chunk_map, edit);
+                }
+            }
+            Some(ServerMessage::EditAck(sequence)) => {
+                client.finish_edit(chunk_map, sequence);
+            }
+            Some(ServerMessage::EditNack { sequence, edit }) => {
+                client.finish_edit(chunk_map, sequence);
+                rejected_events.send(EditRejectedEvent { sequence, edit });
+            }
+            None => warn!("dropped a message from the server"),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+mod client;
+mod messages;
+mod server;
+mod transport;
+
+pub use crate::{
+    client::{EditRejectedEvent, ReplicationClient},
+    messages::{ClientMessage, ServerMessage, VoxelEdit},
+    server::{EditValidator, ReplicationServer},
+    transport::{ConnectionId, LoopbackTransport, Transport, TransportEvent, SERVER},
+};
+use avoxel_chunk_map::ChunkWorlds;
+use bevy_app::{AppBuilder, CoreStage, Plugin};
+use bevy_ecs::system::IntoSystem;
+
+/// Replicates the `ChunkMap` of a world to clients. Needs the `AvoxelChunkMapPlugin`
+/// and a `ReplicationServer` resource.
+pub struct ReplicationServerPlugin;
+
+impl Plugin for ReplicationServerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if !app.world().contains_resource::<ChunkWorlds>() {
+            bevy_log::error!("AvoxelChunkMapPlugin needs to be added first.");
+        }
+
+        app.add_startup_system(server::enable_edit_recording.system())
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                server::receive_client_messages.system(),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, server::send_chunk_updates.system());
+    }
+}
+
+/// Fills the `ChunkMap` of a world with chunks from a server. Needs the `AvoxelChunkMapPlugin`
+/// and a `ReplicationClient` resource.
+pub struct ReplicationClientPlugin;
+
+impl Plugin for ReplicationClientPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        if !app.world().contains_resource::<ChunkWorlds>() {
+            bevy_log::error!("AvoxelChunkMapPlugin needs to be added first.");
+        }
+
+        app.add_event::<EditRejectedEvent>()
+            .add_startup_system(client::disable_simulation.system())
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                client::receive_server_messages.system(),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, client::send_viewer_updates.system())
+            .add_system_to_stage(CoreStage::PostUpdate, client::send_dropped_chunks.system());
+    }
+}
@@ -0,0 +1,56 @@
+use avoxel_chunk::{Lz4CompressedChunk, Voxel};
+use avoxel_math::Pos;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A voxel that was set to a new value
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VoxelEdit {
+    pub pos: Pos,
+    pub voxel: Voxel,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// The chunk position and distances of the client's `ChunkViewer`
+    Viewer {
+        pos: Pos,
+        render_distance: i32,
+        simulate_distance: i32,
+    },
+    /// Asks the server to make an edit the client already predicted. The server
+    /// answers with `EditAck` after sending the edit or with `EditNack`.
+    /// Sequence numbers increase with every request.
+    EditRequest { sequence: u32, edit: VoxelEdit },
+    /// Chunks the client unloaded or evicted without being told to,
+    /// the server sends them again once they are within the viewer
+    DroppedChunks(Vec<Pos>),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// A chunk that entered the client's simulate distance, compressed with
+    /// LittleEndian byteorder
+    Chunk(Lz4CompressedChunk),
+    /// A chunk that left the client's simulate distance
+    UnloadChunk(Pos),
+    /// Edits of chunks the client has, in the order they were made
+    VoxelEdits(Vec<VoxelEdit>),
+    /// The requested edit was made, it was part of the `VoxelEdits` sent before
+    EditAck(u32),
+    /// The requested edit was rejected
+    EditNack { sequence: u32, edit: VoxelEdit },
+}
+
+pub(crate) fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    bincode::serialize(message).expect("Failed to encode message")
+}
+
+pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    match bincode::deserialize(bytes) {
+        Ok(message) => Some(message),
+        Err(e) => {
+            bevy_log::warn!("failed to decode message: {}", e);
+            None
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    messages::{decode, encode, ClientMessage, ServerMessage, VoxelEdit},
+    transport::{ConnectionId, Transport, TransportEvent},
+};
+use avoxel_chunk::{VoxelBits, CHUNK_SIZE};
+use avoxel_chunk_map::{ChunkMap, ChunkViewer, ChunkViewerMoveEvent, ChunkWorlds, WorldId};
+use avoxel_math::{BevyVec3, DivFloor, Pos};
+use bevy_ecs::prelude::*;
+use bevy_log::warn;
+use bevy_utils::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Decides whether a client may make an edit. Only called for edits of chunks
+/// the client has and with a block id that exists.
+pub type EditValidator = Arc<dyn Fn(&ChunkMap, ConnectionId, &VoxelEdit) -> bool + Send + Sync>;
+
+struct ReplicatedClient {
+    /// The `ChunkViewer` entity spawned for the client
+    viewer: Option<Entity>,
+    /// Chunks the client has been sent and not told to unload
+    sent_chunks: HashSet<Pos>,
+    /// Acks and nacks of the client's edit requests, sent after the edits
+    edit_results: Vec<ServerMessage>,
+}
+
+/// Sends chunks within the `ChunkViewer` of each client and the edits of those
+/// chunks to the clients, and applies the edits clients request.
+pub struct ReplicationServer {
+    transport: Box<dyn Transport>,
+    clients: HashMap<ConnectionId, ReplicatedClient>,
+    /// Max number of chunks sent to each client per frame
+    pub(crate) chunks_per_frame: usize,
+    /// Max render and simulate distance of client viewers, larger distances are clamped
+    max_view_distance: i32,
+    edit_validator: Option<EditValidator>,
+    /// The world that is replicated
+    world: WorldId,
+}
+
+impl ReplicationServer {
+    pub fn new<T: Transport>(transport: T) -> Self {
+        Self {
+            transport: Box::new(transport),
+            clients: Default::default(),
+            chunks_per_frame: 4,
+            max_view_distance: 16,
+            edit_validator: None,
+            world: WorldId::DEFAULT,
+        }
+    }
+
+    pub fn set_chunks_per_frame(&mut self, chunks_per_frame: usize) {
+        self.chunks_per_frame = chunks_per_frame;
+    }
+
+    pub fn set_max_view_distance(&mut self, max_view_distance: i32) {
+        self.max_view_distance = max_view_distance.max(0);
+    }
+
+    pub fn set_edit_validator(&mut self, edit_validator: Option<EditValidator>) {
+        self.edit_validator = edit_validator;
+    }
+
+    /// Replicates another world, has to be set before the app runs
+    pub fn set_world(&mut self, world: WorldId) {
+        self.world = world;
+    }
+
+    pub fn world(&self) -> WorldId {
+        self.world
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// The chunks the client currently has
+    pub fn sent_chunks(&self, connection: ConnectionId) -> Option<&HashSet<Pos>> {
+        self.clients
+            .get(&connection)
+            .map(|client| &client.sent_chunks)
+    }
+
+    fn validate_edit(
+        &self,
+        chunk_map: &ChunkMap,
+        connection: ConnectionId,
+        edit: &VoxelEdit,
+    ) -> bool {
+        let client = match self.clients.get(&connection) {
+            Some(client) => client,
+            None => return false,
+        };
+        if !client.sent_chunks.contains(&edit.pos.div_floor(CHUNK_SIZE)) {
+            return false;
+        }
+        if edit.voxel.id() as usize >= chunk_map.block_library.block_count() {
+            return false;
+        }
+        if chunk_map.get_voxel(&edit.pos).is_none() {
+            return false;
+        }
+        self.edit_validator
+            .as_ref()
+            .map_or(true, |validator| validator(chunk_map, connection, edit))
+    }
+}
+
+fn send(transport: &dyn Transport, connection: ConnectionId, message: &ServerMessage) {
+    transport.send(connection, encode(message));
+}
+
+/// True if the voxel is in the chunk or in its padding
+fn chunk_contains(chunk_key: &Pos, pos: &Pos) -> bool {
+    let min = *chunk_key * CHUNK_SIZE - 1;
+    let max = *chunk_key * CHUNK_SIZE + CHUNK_SIZE;
+    (min.x..=max.x).contains(&pos.x)
+        && (min.y..=max.y).contains(&pos.y)
+        && (min.z..=max.z).contains(&pos.z)
+}
+
+/// Makes the chunk map record the edits that get sent to the clients
+pub fn enable_edit_recording(server: Res<ReplicationServer>, mut worlds: ResMut<ChunkWorlds>) {
+    match worlds.get_mut(server.world) {
+        Some(chunk_map) => chunk_map.set_record_edits(true),
+        None => warn!("the replicated world {:?} doesn't exist", server.world),
+    }
+}
+
+pub fn receive_client_messages(
+    mut commands: Commands,
+    mut server: ResMut<ReplicationServer>,
+    mut worlds: ResMut<ChunkWorlds>,
+    mut viewers: Query<&mut ChunkViewer>,
+    mut move_events: EventWriter<ChunkViewerMoveEvent>,
+) {
+    let world = server.world;
+    let chunk_map = match worlds.get_mut(world) {
+        Some(chunk_map) => chunk_map,
+        None => return,
+    };
+    while let Some(event) = server.transport.poll() {
+        match event {
+            TransportEvent::Connected(connection) => {
+                server.clients.insert(
+                    connection,
+                    ReplicatedClient {
+                        viewer: None,
+                        sent_chunks: Default::default(),
+                        edit_results: vec![],
+                    },
+                );
+            }
+            TransportEvent::Disconnected(connection) => {
+                if let Some(viewer) = server
+                    .clients
+                    .remove(&connection)
+                    .and_then(|client| client.viewer)
+                {
+                    commands.entity(viewer).despawn();
+                    // the chunks of the viewer get unloaded
+                    move_events.send(ChunkViewerMoveEvent(world));
+                }
+            }
+            TransportEvent::Message(connection, bytes) => match decode(&bytes) {
+                Some(ClientMessage::Viewer {
+                    pos,
+                    render_distance,
+                    simulate_distance,
+                }) => {
+                    let max_distance = server.max_view_distance;
+                    let render_distance = render_distance.clamp(0, max_distance);
+                    let simulate_distance = simulate_distance.clamp(0, max_distance);
+                    let client = match server.clients.get_mut(&connection) {
+                        Some(client) => client,
+                        None => continue,
+                    };
+                    let viewer = client
+                        .viewer
+                        .and_then(|entity| viewers.get_mut(entity).ok());
+                    match viewer {
+                        Some(mut viewer)
+                            if viewer.render_distance() == render_distance
+                                && viewer.simulate_distance() == simulate_distance =>
+                        {
+                            viewer.set_translation((pos * CHUNK_SIZE).to_vec3());
+                        }
+                        _ => {
+                            let viewer = ChunkViewer::new(render_distance, pos)
+                                .with_simulate_distance(simulate_distance);
+                            match client.viewer {
+                                Some(entity) => {
+                                    commands.entity(entity).insert(viewer);
+                                }
+                                None => {
+                                    client.viewer =
+                                        Some(commands.spawn().insert(viewer).insert(world).id())
+                                }
+                            }
+                        }
+                    }
+                }
+                Some(ClientMessage::DroppedChunks(positions)) => {
+                    if let Some(client) = server.clients.get_mut(&connection) {
+                        for pos in positions.iter() {
+                            client.sent_chunks.remove(pos);
+                        }
+                    }
+                }
+                Some(ClientMessage::EditRequest { sequence, edit }) => {
+                    let result = if server.validate_edit(chunk_map, connection, &edit) {
+                        // the recorded edit is sent to every client that has the chunk
+                        chunk_map.set_voxel(edit.voxel, &edit.pos);
+                        ServerMessage::EditAck(sequence)
+                    } else {
+                        ServerMessage::EditNack { sequence, edit }
+                    };
+                    if let Some(client) = server.clients.get_mut(&connection) {
+                        client.edit_results.push(result);
+                    }
+                }
+                None => warn!("dropped a message from client {}", connection),
+            },
+        }
+    }
+}
+
+pub fn send_chunk_updates(
+    mut server: ResMut<ReplicationServer>,
+    mut worlds: ResMut<ChunkWorlds>,
+    viewers: Query<&ChunkViewer>,
+) {
+    let chunk_map = match worlds.get_mut(server.world) {
+        Some(chunk_map) => chunk_map,
+        None => return,
+    };
+    let edits: Vec<VoxelEdit> = chunk_map
+        .take_voxel_edits()
+        .into_iter()
+        .map(|(pos, voxel)| VoxelEdit { pos, voxel })
+        .collect();
+    let server = &mut *server;
+    let transport = &*server.transport;
+    for (connection, client) in server.clients.iter_mut() {
+        // edits go first since chunks sent afterwards already contain them
+        let client_edits: Vec<VoxelEdit> = edits
+            .iter()
+            .filter(|edit| {
+                client
+                    .sent_chunks
+                    .iter()
+                    .any(|chunk_key| chunk_contains(chunk_key, &edit.pos))
+            })
+            .copied()
+            .collect();
+        if !client_edits.is_empty() {
+            send(
+                transport,
+                *connection,
+                &ServerMessage::VoxelEdits(client_edits),
+            );
+        }
+        // the client can drop its prediction once the edit arrived
+        for result in client.edit_results.drain(..) {
+            send(transport, *connection, &result);
+        }
+
+        let viewer = match client.viewer.and_then(|entity| viewers.get(entity).ok()) {
+            Some(viewer) => viewer,
+            None => continue,
+        };
+        let simulated_chunks = viewer.get_simulated_chunks();
+        let unloaded: Vec<Pos> = client
+            .sent_chunks
+            .iter()
+            .filter(|pos| !simulated_chunks.contains(*pos))
+            .copied()
+            .collect();
+        for pos in unloaded {
+            client.sent_chunks.remove(&pos);
+            send(transport, *connection, &ServerMessage::UnloadChunk(pos));
+        }
+
+        // closest chunks first
+        let viewer_pos = viewer.get_pos();
+        let mut new_chunks: Vec<Pos> = simulated_chunks
+            .iter()
+            .filter(|pos| !client.sent_chunks.contains(*pos) && chunk_map.contains_chunk(pos))
+            .copied()
+            .collect();
+        new_chunks.sort_by_cached_key(|pos| {
+            let d = *pos - viewer_pos;
+            d.x * d.x + d.y * d.y + d.z * d.z
+        });
+        new_chunks.truncate(server.chunks_per_frame);
+        for pos in new_chunks {
+            if let Some(chunk) = chunk_map.get_compressed_chunk(&pos, true) {
+                client.sent_chunks.insert(pos);
+                send(transport, *connection, &ServerMessage::Chunk(chunk));
+            }
+        }
+    }
+}
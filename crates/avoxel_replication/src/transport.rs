@@ -0,0 +1,103 @@
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+/// Identifies the other end of a connection. Clients always talk to `SERVER`.
+pub type ConnectionId = u32;
+
+pub const SERVER: ConnectionId = 0;
+
+pub enum TransportEvent {
+    Connected(ConnectionId),
+    Message(ConnectionId, Vec<u8>),
+    Disconnected(ConnectionId),
+}
+
+/// Moves encoded messages between a server and its clients.
+/// Messages have to arrive in the order they were sent.
+pub trait Transport: Send + Sync + 'static {
+    /// Sends the bytes to the connection, messages to unknown connections are dropped
+    fn send(&self, connection: ConnectionId, bytes: Vec<u8>);
+
+    /// Returns the next event or `None` if there is nothing to receive right now
+    fn poll(&self) -> Option<TransportEvent>;
+}
+
+/// An in-memory `Transport` for running the server and clients in one process.
+/// Create the server end with `LoopbackTransport::server` and connect clients to it,
+/// a client disconnects when its end is dropped.
+pub struct LoopbackTransport {
+    id: ConnectionId,
+    rx: Receiver<TransportEvent>,
+    tx: Sender<TransportEvent>,
+    peers: Arc<Mutex<HashMap<ConnectionId, Sender<TransportEvent>>>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl LoopbackTransport {
+    pub fn server() -> Self {
+        let (tx, rx) = unbounded();
+        Self {
+            id: SERVER,
+            rx,
+            tx,
+            peers: Default::default(),
+            next_id: Arc::new(AtomicU32::new(SERVER + 1)),
+        }
+    }
+
+    /// Returns the client end of a new connection to this server
+    pub fn connect(&self) -> Self {
+        assert_eq!(self.id, SERVER, "clients can only connect to a server");
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = unbounded();
+        self.peers.lock().insert(id, tx.clone());
+        let mut peers = HashMap::new();
+        peers.insert(SERVER, self.tx.clone());
+        let _ = self.tx.send(TransportEvent::Connected(id));
+        Self {
+            id,
+            rx,
+            tx,
+            peers: Arc::new(Mutex::new(peers)),
+            next_id: self.next_id.clone(),
+        }
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&self, connection: ConnectionId, bytes: Vec<u8>) {
+        if let Some(peer) = self.peers.lock().get(&connection) {
+            let _ = peer.send(TransportEvent::Message(self.id, bytes));
+        }
+    }
+
+    fn poll(&self) -> Option<TransportEvent> {
+        let event = self.rx.try_recv().ok()?;
+        if let TransportEvent::Disconnected(id) = &event {
+            self.peers.lock().remove(id);
+        }
+        Some(event)
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        if self.id == SERVER {
+            return;
+        }
+        if let Some(server) = self.peers.lock().get(&SERVER) {
+            let _ = server.send(TransportEvent::Disconnected(self.id));
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use avoxel_blocks::{Block, BlockLibrary};
+use avoxel_chunk::Voxel;
+use avoxel_chunk_map::{AvoxelChunkMapPlugin, ChunkMap, ChunkViewer, ChunkWorlds, WorldId};
+use avoxel_math::Pos;
+use avoxel_replication::{
+    ClientMessage, EditRejectedEvent, LoopbackTransport, ReplicationClient,
+    ReplicationClientPlugin, ReplicationServer, ReplicationServerPlugin, Transport, VoxelEdit,
+    SERVER,
+};
+use bevy_app::App;
+use bevy_core::CorePlugin;
+use bevy_ecs::prelude::*;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct RejectedEdits(Vec<VoxelEdit>);
+
+fn collect_rejected_edits(
+    mut events: EventReader<EditRejectedEvent>,
+    mut rejected: ResMut<RejectedEdits>,
+) {
+    rejected.0.extend(events.iter().map(|event| event.edit));
+}
+
+fn server_app(transport: LoopbackTransport) -> App {
+    let mut block_library = BlockLibrary::new();
+    block_library
+        .add_block(Block {
+            name: "air".to_string(),
+            ..Default::default()
+        })
+        .add_block(Block {
+            name: "stone".to_string(),
+            ..Default::default()
+        });
+    let mut app = App::build();
+    app.add_plugin(CorePlugin)
+        .insert_resource(block_library)
+        .add_plugin(AvoxelChunkMapPlugin)
+        .insert_resource(ReplicationServer::new(transport))
+        .add_plugin(ReplicationServerPlugin);
+    app.app
+}
+
+fn client_app(transport: LoopbackTransport) -> App {
+    let mut app = App::build();
+    app.add_plugin(CorePlugin)
+        .add_plugin(AvoxelChunkMapPlugin)
+        .insert_resource(ReplicationClient::new(transport))
+        .add_plugin(ReplicationClientPlugin)
+        .init_resource::<RejectedEdits>()
+        .add_system(collect_rejected_edits.system());
+    let mut app = app.app;
+    app.world
+        .spawn()
+        .insert(ChunkViewer::new(1, Pos::new(0, 0, 0)));
+    app
+}
+
+/// Updates both apps until `done` returns true for the client, panics after `TIMEOUT`
+fn run_until<F: Fn(&World) -> bool>(server: &mut App, client: &mut App, done: F) {
+    let start = Instant::now();
+    loop {
+        server.update();
+        client.update();
+        if done(&client.world) {
+            return;
+        }
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "timed out waiting for the client"
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn chunk_map(world: &World) -> &ChunkMap {
+    world
+        .get_resource::<ChunkWorlds>()
+        .unwrap()
+        .get(WorldId::DEFAULT)
+        .unwrap()
+}
+
+fn set_voxel(app: &mut App, voxel: Voxel, pos: &Pos) {
+    app.world
+        .get_resource_mut::<ChunkWorlds>()
+        .unwrap()
+        .get_mut(WorldId::DEFAULT)
+        .unwrap()
+        .set_voxel(voxel, pos);
+}
+
+fn predicted_edit_count(world: &World) -> usize {
+    world
+        .get_resource::<ReplicationClient>()
+        .unwrap()
+        .predicted_edit_count()
+}
+
+fn request_edit(app: &mut App, voxel: Voxel, pos: &Pos) -> Option<u32> {
+    app.world
+        .resource_scope(|world, mut client: Mut<ReplicationClient>| {
+            let mut worlds = world.get_resource_mut::<ChunkWorlds>().unwrap();
+            let chunk_map = worlds.get_mut(WorldId::DEFAULT).unwrap();
+            client.request_edit(chunk_map, voxel, pos)
+        })
+}
+
+/// Returns a server and a client that received the chunks around the client's viewer
+fn connected_apps() -> (App, App) {
+    let server_transport = LoopbackTransport::server();
+    let client_transport = server_transport.connect();
+    let mut server = server_app(server_transport);
+    let mut client = client_app(client_transport);
+    run_until(&mut server, &mut client, |world| {
+        let chunk_map = chunk_map(world);
+        !chunk_map.simulated_chunks.is_empty()
+            && chunk_map
+                .simulated_chunks
+                .iter()
+                .all(|pos| chunk_map.contains_chunk(pos))
+    });
+    (server, client)
+}
+
+/// A voxel different from the one the server has at the position
+fn changed_voxel(server: &App, pos: &Pos) -> Voxel {
+    match chunk_map(&server.world).get_voxel(pos) {
+        Some(1) => 0,
+        _ => 1,
+    }
+}
+
+#[test]
+fn replicates_chunks_and_edits() {
+    let (mut server, mut client) = connected_apps();
+    assert!(!chunk_map(&client.world).is_generating());
+    for pos in [
+        Pos::new(3, -20, 7),
+        Pos::new(-40, 0, 12),
+        Pos::new(63, 64, 0),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            chunk_map(&client.world).get_voxel(pos),
+            chunk_map(&server.world).get_voxel(pos)
+        );
+    }
+
+    // edits of the server are streamed to the client
+    let pos = Pos::new(-3, 40, 20);
+    let voxel = changed_voxel(&server, &pos);
+    set_voxel(&mut server, voxel, &pos);
+    run_until(&mut server, &mut client, |world| {
+        chunk_map(world).get_voxel(&pos) == Some(voxel)
+    });
+
+    // voxels that aren't loaded can't be edited
+    assert_eq!(request_edit(&mut client, 1, &Pos::new(1000, 0, 0)), None);
+
+    // edits with unknown blocks are rejected
+    let pos = Pos::new(5, 5, 5);
+    let unknown_block = VoxelEdit { pos, voxel: 99 };
+    let sequence = request_edit(&mut client, unknown_block.voxel, &pos);
+    assert!(sequence.is_some());
+    run_until(&mut server, &mut client, |world| {
+        predicted_edit_count(world) == 0
+    });
+    assert_eq!(
+        client.world.get_resource::<RejectedEdits>().unwrap().0,
+        vec![unknown_block]
+    );
+    assert_eq!(
+        chunk_map(&client.world).get_voxel(&pos),
+        chunk_map(&server.world).get_voxel(&pos)
+    );
+
+    // dropping the client disconnects it
+    drop(client);
+    server.update();
+    assert_eq!(
+        server
+            .world
+            .get_resource::<ReplicationServer>()
+            .unwrap()
+            .client_count(),
+        0
+    );
+}
+
+#[test]
+fn dropped_chunks_are_sent_again() {
+    let (mut server, mut client) = connected_apps();
+    // the client only gets its changes from the server
+    assert!(!chunk_map(&client.world).is_simulating());
+    assert!(chunk_map(&server.world).is_simulating());
+
+    // e.g. a chunk the client evicted
+    let pos = Pos::new(1, 0, 0);
+    client
+        .world
+        .get_resource_mut::<ChunkWorlds>()
+        .unwrap()
+        .get_mut(WorldId::DEFAULT)
+        .unwrap()
+        .remove_chunk(&pos);
+    assert!(!chunk_map(&client.world).contains_chunk(&pos));
+    run_until(&mut server, &mut client, |world| {
+        chunk_map(world).contains_chunk(&pos)
+    });
+    assert_eq!(
+        chunk_map(&client.world).get_voxel(&Pos::new(70, 5, 5)),
+        chunk_map(&server.world).get_voxel(&Pos::new(70, 5, 5))
+    );
+}
+
+#[test]
+fn predicts_and_reconciles_edits() {
+    let (mut server, mut client) = connected_apps();
+    // the server doesn't allow edits at y 10
+    server
+        .world
+        .get_resource_mut::<ReplicationServer>()
+        .unwrap()
+        .set_edit_validator(Some(Arc::new(|_, _, edit| edit.pos.y != 10)));
+
+    // predicted edits show up before the server answers and stay once acked
+    let pos = Pos::new(5, 5, 5);
+    let voxel = changed_voxel(&server, &pos);
+    assert_eq!(request_edit(&mut client, voxel, &pos), Some(0));
+    assert_eq!(chunk_map(&client.world).get_voxel(&pos), Some(voxel));
+    run_until(&mut server, &mut client, |world| {
+        predicted_edit_count(world) == 0
+    });
+    assert_eq!(chunk_map(&client.world).get_voxel(&pos), Some(voxel));
+    assert_eq!(chunk_map(&server.world).get_voxel(&pos), Some(voxel));
+
+    // nacked edits are rolled back
+    let pos = Pos::new(5, 10, 5);
+    let server_voxel = chunk_map(&server.world).get_voxel(&pos);
+    let voxel = changed_voxel(&server, &pos);
+    assert_eq!(request_edit(&mut client, voxel, &pos), Some(1));
+    assert_eq!(chunk_map(&client.world).get_voxel(&pos), Some(voxel));
+    run_until(&mut server, &mut client, |world| {
+        predicted_edit_count(world) == 0
+    });
+    assert_eq!(chunk_map(&client.world).get_voxel(&pos), server_voxel);
+    assert_eq!(
+        client.world.get_resource::<RejectedEdits>().unwrap().0,
+        vec![VoxelEdit { pos, voxel }]
+    );
+
+    // conflicting edits end up in the order the server made them,
+    // here the remote edit comes first and the client's edit wins
+    let pos = Pos::new(-7, 20, 30);
+    set_voxel(&mut server, 0, &pos);
+    request_edit(&mut client, 1, &pos);
+    run_until(&mut server, &mut client, |world| {
+        predicted_edit_count(world) == 0
+    });
+    assert_eq!(chunk_map(&server.world).get_voxel(&pos), Some(1));
+    assert_eq!(chunk_map(&client.world).get_voxel(&pos), Some(1));
+
+    // and here the remote edit comes last and wins
+    request_edit(&mut client, 0, &pos);
+    server.update();
+    set_voxel(&mut server, 1, &pos);
+    run_until(&mut server, &mut client, |world| {
+        predicted_edit_count(world) == 0
+    });
+    assert_eq!(chunk_map(&server.world).get_voxel(&pos), Some(1));
+    assert_eq!(chunk_map(&client.world).get_voxel(&pos), Some(1));
+}
+
+#[test]
+fn oversized_viewers_are_clamped() {
+    let server_transport = LoopbackTransport::server();
+    let client_transport = server_transport.connect();
+    let mut server = server_app(server_transport);
+    server
+        .world
+        .get_resource_mut::<ReplicationServer>()
+        .unwrap()
+        .set_max_view_distance(2);
+
+    let viewer = ClientMessage::Viewer {
+        pos: Pos::new(0, 0, 0),
+        render_distance: i32::MAX,
+        simulate_distance: -5,
+    };
+    client_transport.send(SERVER, bincode::serialize(&viewer).unwrap());
+    let start = Instant::now();
+    while server
+        .world
+        .query::<&ChunkViewer>()
+        .iter(&server.world)
+        .next()
+        .is_none()
+    {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "timed out waiting for the viewer"
+        );
+        server.update();
+    }
+    let viewer = server
+        .world
+        .query::<&ChunkViewer>()
+        .iter(&server.world)
+        .next()
+        .unwrap();
+    assert_eq!(viewer.render_distance(), 2);
+    assert_eq!(viewer.simulate_distance(), 0);
+}
@@ -27,7 +27,10 @@ fn main() {
         })
         .insert_resource(ClearColor(Color::rgb_u8(92, 119, 127)))
         // ChunkMap is the core of avoxel and to change terrain generation modify or change the generate chunk method
-        .insert_resource(ChunkMap::new(false, &generator::generate_chunk))
+        .insert_resource(ChunkWorlds::new(ChunkMap::new(
+            false,
+            &generator::generate_chunk,
+        )))
         .add_plugins(DefaultPlugins)
         .add_plugin(BlockLibraryPlugin)
         .add_plugins(AvoxelDefaultPlugins)
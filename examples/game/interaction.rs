@@ -3,7 +3,7 @@ use crate::player::FirstPersonCam;
 use avoxel::{
     blocks::Block,
     math::{BevyVec3, Pos},
-    prelude::ChunkMap,
+    prelude::{ChunkWorlds, WorldId},
 };
 use bevy::prelude::*;
 
@@ -54,10 +54,14 @@ fn setup_cursor(
 }
 
 fn cursor_position_system(
-    chunk_map: Res<ChunkMap>,
+    worlds: Res<ChunkWorlds>,
     mut cursor: Query<(&mut Transform, &mut Visible, &mut TargetBlockCursor)>,
     camera: Query<&GlobalTransform, With<FirstPersonCam>>,
 ) {
+    let chunk_map = match worlds.get(WorldId::DEFAULT) {
+        Some(chunk_map) => chunk_map,
+        None => return,
+    };
     for (mut transform, mut visible, mut cursor) in cursor.iter_mut().take(1) {
         for cam_transform in camera.iter().take(1) {
             if let Some(voxel_ray_cast_result) =
@@ -75,7 +79,7 @@ fn cursor_position_system(
 }
 
 fn place_destroy_block_system(
-    mut chunk_map: ResMut<ChunkMap>,
+    mut worlds: ResMut<ChunkWorlds>,
     mouse_input: Res<Input<MouseButton>>,
     cursor: Query<(&TargetBlockCursor, &Visible)>,
     windows: Res<Windows>,
@@ -86,6 +90,10 @@ fn place_destroy_block_system(
         }
     }
 
+    let chunk_map = match worlds.get_mut(WorldId::DEFAULT) {
+        Some(chunk_map) => chunk_map,
+        None => return,
+    };
     for (cursor, visible) in cursor.iter() {
         if visible.is_visible {
             if mouse_input.just_pressed(MouseButton::Left) {
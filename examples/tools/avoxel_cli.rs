@@ -0,0 +1,117 @@
+use avoxel::{
+    blocks::{Block, BlockLibrary},
+    generator::default_generator,
+    math::{Extent3, Pos},
+    prelude::{block_map_colors, load_texture_atlas, render_top_down_map_from_generator},
+};
+use std::process;
+
+const USAGE: &str = "Usage:
+    avoxel_cli map [--out <file.png>] [--min <x,y,z>] [--max <x,y,z>] [--no-shading]
+                   [--atlas <file.png>]
+
+Commands:
+    map     Renders a top-down map of the default generator to a PNG image,
+            block colours are averaged from the top textures of --atlas if given";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("map") => map_command(&args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    }
+}
+
+fn map_command(args: &[String]) {
+    let mut out = "map.png".to_string();
+    let mut extent = Extent3 {
+        min: Pos::new(-256, -64, -256),
+        max: Pos::new(255, 63, 255),
+    };
+    let mut height_shading = true;
+    let mut atlas = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => out = next_value(&mut args, arg).to_string(),
+            "--min" => extent.min = parse_pos(next_value(&mut args, arg)),
+            "--max" => extent.max = parse_pos(next_value(&mut args, arg)),
+            "--no-shading" => height_shading = false,
+            "--atlas" => {
+                let path = next_value(&mut args, arg);
+                match load_texture_atlas(path) {
+                    Ok(loaded) => atlas = Some(loaded),
+                    Err(e) => exit_with_error(&format!("failed to read {}: {}", path, e)),
+                }
+            }
+            _ => exit_with_error(&format!("unknown argument: {}", arg)),
+        }
+    }
+
+    let block_library = block_library(atlas.as_ref().map(|(_, texture_size)| *texture_size));
+    let colors = block_map_colors(
+        &block_library,
+        atlas.as_ref().map(|(pixels, _)| &pixels[..]),
+    );
+    let map = render_top_down_map_from_generator(
+        &default_generator::generate_chunk,
+        extent,
+        &colors,
+        height_shading,
+    );
+    if let Err(e) = map.save_png(&out) {
+        exit_with_error(&format!("failed to write {}: {}", out, e));
+    }
+    println!("Wrote {}x{} map to {}", map.width, map.height, out);
+}
+
+/// The default generator only places block 1 so that's all we need colours for.
+/// With an atlas its colour comes from the first texture instead.
+fn block_library(atlas_texture_size: Option<u32>) -> BlockLibrary {
+    let mut block_library = BlockLibrary::new();
+    block_library
+        .add_block(Block {
+            name: "air".to_string(),
+            ..Default::default()
+        })
+        .add_block(Block {
+            name: "grass".to_string(),
+            map_color: match atlas_texture_size {
+                Some(_) => None,
+                None => Some([89, 125, 53]),
+            },
+            ..Default::default()
+        });
+    if let Some(texture_size) = atlas_texture_size {
+        block_library.set_texture_size(texture_size);
+    }
+    block_library
+}
+
+fn next_value<'a>(args: &mut impl Iterator<Item = &'a String>, arg: &str) -> &'a str {
+    match args.next() {
+        Some(value) => value,
+        None => exit_with_error(&format!("missing value for {}", arg)),
+    }
+}
+
+fn parse_pos(value: &str) -> Pos {
+    let coords: Vec<i32> = value
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|_| exit_with_error(&format!("invalid position: {}", value)));
+    if coords.len() != 3 {
+        exit_with_error(&format!("expected x,y,z but got: {}", value));
+    }
+    Pos::new(coords[0], coords[1], coords[2])
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(1);
+}
@@ -4,9 +4,11 @@ pub struct AvoxelDefaultPlugins;
 
 impl PluginGroup for AvoxelDefaultPlugins {
     fn build(&mut self, group: &mut PluginGroupBuilder) {
+        #[cfg(feature = "avoxel_rendering")]
+        group.add(avoxel_blocks::BlockLibraryAssetPlugin);
         group.add(avoxel_chunk_map::AvoxelChunkMapPlugin);
         group.add(avoxel_physics::AvoxelPhysicsPlugin);
-        #[cfg(feature = "avoxel_mesher")]
+        #[cfg(feature = "rendering")]
         group.add(avoxel_mesher::AvoxelMesherPlugin);
     }
 }
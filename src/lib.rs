@@ -13,14 +13,26 @@ pub mod chunk {
     pub use avoxel_chunk::*;
 }
 
+pub mod generator {
+    pub use avoxel_generator::*;
+}
+
 pub mod math {
     pub use avoxel_math::*;
 }
 
+pub mod mesher {
+    pub use avoxel_mesher::*;
+}
+
 pub mod physics {
     pub use avoxel_physics::*;
 }
 
+pub mod replication {
+    pub use avoxel_replication::*;
+}
+
 #[cfg(feature = "rendering")]
 pub mod rendering {
     pub use avoxel_rendering::prelude::*;